@@ -22,6 +22,9 @@
 , targetFeatures ? []
   # Whether to perform release builds: longer compile times, faster binaries.
 , release ? true
+  # If true, the features of all workspace members are resolved together so that
+  # their common dependencies are built only once and shared by all of them.
+, shareDependencies ? false
   # Additional crate2nix configuration if it exists.
 , crateConfig
   ? if builtins.pathExists ./crate-config.nix
@@ -55,6 +58,7 @@ rec {
       packageId = "crate2nix";
       build = internal.buildRustCrateWithFeatures {
         packageId = "crate2nix";
+        unifyFeaturesWith = internal.sharedDependenciesPackageIds;
       };
 
       # Debug support which might change between releases.
@@ -96,6 +100,10 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "Fast multiple substring searching.";
+        homepage = "https://github.com/BurntSushi/aho-corasick";
+        repository = "https://github.com/BurntSushi/aho-corasick";
+        license = "Unlicense/MIT";
         dependencies = [
           {
             name = "memchr";
@@ -119,6 +127,9 @@ rec {
           "Ryan Scheel (Havvy) <ryan.havvy@gmail.com>"
           "Josh Triplett <josh@joshtriplett.org>"
         ];
+        description = "Library for ANSI terminal colours and styles (bold, underline)";
+        homepage = "https://github.com/ogham/rust-ansi-term";
+        license = "MIT";
         dependencies = [
           {
             name = "winapi";
@@ -137,7 +148,11 @@ rec {
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "Flexible concrete Error type built on std::error::Error";
+        repository = "https://github.com/dtolnay/anyhow";
+        license = "MIT OR Apache-2.0";
         features = {
+          "backtrace" = [ "dep:backtrace" ];
           "default" = [ "std" ];
         };
         resolvedDefaultFeatures = [ "default" "std" ];
@@ -150,6 +165,10 @@ rec {
         authors = [
           "softprops <d.tangren@gmail.com>"
         ];
+        description = "A simple interface for querying atty";
+        homepage = "https://github.com/softprops/atty";
+        repository = "https://github.com/softprops/atty";
+        license = "MIT";
         dependencies = [
           {
             name = "hermit-abi";
@@ -179,6 +198,11 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        description = "A macro to generate structures which behave like bitflags.
+";
+        homepage = "https://github.com/bitflags/bitflags";
+        repository = "https://github.com/bitflags/bitflags";
+        license = "MIT/Apache-2.0";
         features = {
         };
         resolvedDefaultFeatures = [ "default" ];
@@ -188,9 +212,13 @@ rec {
         version = "0.7.3";
         edition = "2015";
         sha256 = "12v8wizynqin0hqf140kmp9s38q223mp1b0hkqk8j5pk8720v560";
+        libName = "block_buffer";
         authors = [
           "RustCrypto Developers"
         ];
+        description = "Fixed size buffer for block processing of data";
+        repository = "https://github.com/RustCrypto/utils";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "block-padding";
@@ -217,9 +245,13 @@ rec {
         version = "0.1.5";
         edition = "2015";
         sha256 = "1xbkmysiz23vimd17rnsjpw9bgjxipwfslwyygqlkx4in3dxwygs";
+        libName = "block_padding";
         authors = [
           "RustCrypto Developers"
         ];
+        description = "Padding and unpadding of messages divided into blocks.";
+        repository = "https://github.com/RustCrypto/utils";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "byte-tools";
@@ -236,6 +268,10 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "A string type that is not required to be valid UTF-8.";
+        homepage = "https://github.com/BurntSushi/bstr";
+        repository = "https://github.com/BurntSushi/bstr";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "memchr";
@@ -245,6 +281,9 @@ rec {
         ];
         features = {
           "default" = [ "std" "unicode" ];
+          "lazy_static" = [ "dep:lazy_static" ];
+          "regex-automata" = [ "dep:regex-automata" ];
+          "serde" = [ "dep:serde" ];
           "serde1" = [ "std" "serde1-nostd" "serde/std" ];
           "serde1-nostd" = [ "serde" ];
           "std" = [ "memchr/std" ];
@@ -257,9 +296,13 @@ rec {
         version = "0.3.1";
         edition = "2015";
         sha256 = "1mqi29wsm8njpl51pfwr31wmpzs5ahlcb40wsjyd92l90ixcmdg3";
+        libName = "byte_tools";
         authors = [
           "RustCrypto Developers"
         ];
+        description = "Bytes related utility functions";
+        repository = "https://github.com/RustCrypto/utils";
+        license = "MIT OR Apache-2.0";
 
       };
       "byteorder" = rec {
@@ -270,6 +313,10 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "Library for reading/writing numbers in big-endian and little-endian.";
+        homepage = "https://github.com/BurntSushi/byteorder";
+        repository = "https://github.com/BurntSushi/byteorder";
+        license = "Unlicense OR MIT";
         features = {
           "default" = [ "std" ];
         };
@@ -285,6 +332,9 @@ rec {
           "Steve Klabnik <steve@steveklabnik.com>"
           "Rain <rain@sunshowers.io>"
         ];
+        description = "UTF-8 paths";
+        repository = "https://github.com/withoutboats/camino";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "serde";
@@ -294,6 +344,7 @@ rec {
           }
         ];
         features = {
+          "serde" = [ "dep:serde" ];
           "serde1" = [ "serde" ];
         };
         resolvedDefaultFeatures = [ "serde" "serde1" ];
@@ -303,9 +354,14 @@ rec {
         version = "0.1.1";
         edition = "2018";
         sha256 = "1mzi60pf0z83qkzqp7jwd61xnqz2b5ydsj7rnnikbgyicd5989h2";
+        libName = "cargo_platform";
         authors = [
           "The Cargo Project Developers"
         ];
+        description = "Cargo's representation of a target platform.";
+        homepage = "https://github.com/rust-lang/cargo";
+        repository = "https://github.com/rust-lang/cargo";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "serde";
@@ -323,6 +379,9 @@ rec {
         authors = [
           "Oliver Schneider <git-spam-no-reply9815368754983@oli-obk.de>"
         ];
+        description = "structured access to the output of `cargo metadata`";
+        repository = "https://github.com/oli-obk/cargo_metadata";
+        license = "MIT";
         dependencies = [
           {
             name = "camino";
@@ -351,6 +410,7 @@ rec {
         ];
         features = {
           "builder" = [ "derive_builder" ];
+          "derive_builder" = [ "dep:derive_builder" ];
         };
         resolvedDefaultFeatures = [ "default" ];
       };
@@ -359,10 +419,20 @@ rec {
         version = "1.0.0";
         edition = "2018";
         sha256 = "1za0vb97n4brpzpv8lsbnzmq5r8f2b0cpqqr0sy8h5bn751xxwds";
+        libName = "cfg_if";
         authors = [
           "Alex Crichton <alex@alexcrichton.com>"
         ];
+        description = "A macro to ergonomically define an item depending on a large number of #[cfg]
+parameters. Structured like an if-else chain, the first matching branch is the
+item that gets emitted.
+";
+        homepage = "https://github.com/alexcrichton/cfg-if";
+        repository = "https://github.com/alexcrichton/cfg-if";
+        license = "MIT/Apache-2.0";
         features = {
+          "compiler_builtins" = [ "dep:compiler_builtins" ];
+          "core" = [ "dep:core" ];
           "rustc-dep-of-std" = [ "core" "compiler_builtins" ];
         };
       };
@@ -374,6 +444,11 @@ rec {
         authors = [
           "Kevin K. <kbknapp@gmail.com>"
         ];
+        description = "A simple to use, efficient, and full-featured Command Line Argument Parser
+";
+        homepage = "https://clap.rs/";
+        repository = "https://github.com/clap-rs/clap";
+        license = "MIT";
         dependencies = [
           {
             name = "ansi_term";
@@ -410,13 +485,20 @@ rec {
           }
         ];
         features = {
+          "ansi_term" = [ "dep:ansi_term" ];
+          "atty" = [ "dep:atty" ];
+          "clippy" = [ "dep:clippy" ];
           "color" = [ "ansi_term" "atty" ];
           "default" = [ "suggestions" "color" "vec_map" ];
           "doc" = [ "yaml" ];
           "lints" = [ "clippy" ];
+          "strsim" = [ "dep:strsim" ];
           "suggestions" = [ "strsim" ];
+          "term_size" = [ "dep:term_size" ];
+          "vec_map" = [ "dep:vec_map" ];
           "wrap_help" = [ "term_size" "textwrap/term_size" ];
           "yaml" = [ "yaml-rust" ];
+          "yaml-rust" = [ "dep:yaml-rust" ];
         };
         resolvedDefaultFeatures = [ "ansi_term" "atty" "color" "default" "strsim" "suggestions" "vec_map" ];
       };
@@ -425,9 +507,13 @@ rec {
         version = "0.2.2";
         edition = "2015";
         sha256 = "1zbfjkp7w1wjcxb1p19dd21mn9xkj6nr2s5pav8b16whzh52cvsi";
+        libName = "colored_diff";
         authors = [
           "Christopher Durham <cad97@cad97.com>"
         ];
+        description = "Format the difference between two strings with ANSI colors";
+        repository = "https://github.com/CAD97/colored-diff";
+        license = "MIT OR Unlicense";
         dependencies = [
           {
             name = "ansi_term";
@@ -456,6 +542,9 @@ rec {
         authors = [
           "Peter Kolloch <info@eigenvalue.net>"
         ];
+        description = "crate2nix generates nix (as in NixOS) build files for rust using cargo.";
+        repository = "https://github.com/kolloch/crate2nix";
+        license = "Apache-2.0";
         dependencies = [
           {
             name = "anyhow";
@@ -469,6 +558,10 @@ rec {
             name = "cargo_metadata";
             packageId = "cargo_metadata";
           }
+          {
+            name = "difference";
+            packageId = "difference";
+          }
           {
             name = "hex";
             packageId = "hex";
@@ -547,9 +640,14 @@ rec {
         version = "0.8.5";
         edition = "2018";
         sha256 = "1ny481cx8a5pdknypb41jqym03dl8x26i2ldyyp3yb3zrq8zqb6q";
+        libName = "crossbeam_utils";
         authors = [
           "The Crossbeam Project Developers"
         ];
+        description = "Utilities for concurrent programming";
+        homepage = "https://github.com/crossbeam-rs/crossbeam/tree/master/crossbeam-utils";
+        repository = "https://github.com/crossbeam-rs/crossbeam";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "cfg-if";
@@ -563,6 +661,8 @@ rec {
         ];
         features = {
           "default" = [ "std" ];
+          "lazy_static" = [ "dep:lazy_static" ];
+          "loom" = [ "dep:loom" ];
           "std" = [ "lazy_static" ];
         };
         resolvedDefaultFeatures = [ "default" "lazy_static" "std" ];
@@ -576,8 +676,12 @@ rec {
         authors = [
           "Johann Hofmann <mail@johann-hofmann.com>"
         ];
+        description = "A Rust text diffing and assertion library.";
+        repository = "https://github.com/johannhof/difference.rs";
+        license = "MIT";
         features = {
           "bin" = [ "getopts" ];
+          "getopts" = [ "dep:getopts" ];
         };
         resolvedDefaultFeatures = [ "default" ];
       };
@@ -589,6 +693,9 @@ rec {
         authors = [
           "RustCrypto Developers"
         ];
+        description = "Traits for cryptographic hash functions";
+        repository = "https://github.com/RustCrypto/traits";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "generic-array";
@@ -596,6 +703,7 @@ rec {
           }
         ];
         features = {
+          "blobby" = [ "dep:blobby" ];
           "dev" = [ "blobby" ];
         };
       };
@@ -607,8 +715,13 @@ rec {
         authors = [
           "bluss"
         ];
+        description = "The enum `Either` with variants `Left` and `Right` is a general purpose sum type with two cases.
+";
+        repository = "https://github.com/bluss/either";
+        license = "MIT/Apache-2.0";
         features = {
           "default" = [ "use_std" ];
+          "serde" = [ "dep:serde" ];
         };
       };
       "fake-simd" = rec {
@@ -616,9 +729,13 @@ rec {
         version = "0.1.2";
         edition = "2015";
         sha256 = "1vfylvk4va2ivqx85603lyqqp0zk52cgbs4n5nfbbbqx577qm2p8";
+        libName = "fake_simd";
         authors = [
           "The Rust-Crypto Project Developers"
         ];
+        description = "Crate for mimicking simd crate on stable Rust";
+        repository = "https://github.com/RustCrypto/utils";
+        license = "MIT/Apache-2.0";
 
       };
       "fnv" = rec {
@@ -630,6 +747,9 @@ rec {
         authors = [
           "Alex Crichton <alex@alexcrichton.com>"
         ];
+        description = "Fowler–Noll–Vo hash function";
+        repository = "https://github.com/servo/rust-fnv";
+        license = "Apache-2.0 / MIT";
         features = {
           "default" = [ "std" ];
         };
@@ -643,6 +763,10 @@ rec {
         authors = [
           "Denis Kurilenko <webdesus@gmail.com>"
         ];
+        description = "Expanding opportunities standard library std::fs and std::io. Recursively copy folders with recept information about process and much more.";
+        homepage = "https://github.com/webdesus/fs_extra";
+        repository = "https://github.com/webdesus/fs_extra";
+        license = "MIT";
 
       };
       "fuchsia-cprng" = rec {
@@ -650,9 +774,12 @@ rec {
         version = "0.1.1";
         edition = "2018";
         sha256 = "1fnkqrbz7ixxzsb04bsz9p0zzazanma8znfdqjvh39n14vapfvx0";
+        libName = "fuchsia_cprng";
         authors = [
           "Erick Tryzelaar <etryzelaar@google.com>"
         ];
+        description = "Rust crate for the Fuchsia cryptographically secure pseudorandom number generator";
+        repository = "https://fuchsia.googlesource.com/fuchsia/+/master/garnet/public/rust/fuchsia-cprng";
 
       };
       "generic-array" = rec {
@@ -665,13 +792,18 @@ rec {
           "Bartłomiej Kamiński <fizyk20@gmail.com>"
           "Aaron Trent <novacrazy@gmail.com>"
         ];
+        description = "Generic types implementing functionality of arrays";
+        repository = "https://github.com/fizyk20/generic-array.git";
+        license = "MIT";
         dependencies = [
           {
             name = "typenum";
             packageId = "typenum";
           }
         ];
-
+        features = {
+          "serde" = [ "dep:serde" ];
+        };
       };
       "globset" = rec {
         crateName = "globset";
@@ -681,6 +813,13 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "Cross platform single glob and glob set matching. Glob set matching is the
+process of matching one or more glob patterns against a single candidate path
+simultaneously, and returning all of the globs that matched.
+";
+        homepage = "https://github.com/BurntSushi/ripgrep/tree/master/crates/globset";
+        repository = "https://github.com/BurntSushi/ripgrep/tree/master/crates/globset";
+        license = "Unlicense/MIT";
         dependencies = [
           {
             name = "aho-corasick";
@@ -708,6 +847,7 @@ rec {
           }
         ];
         features = {
+          "serde" = [ "dep:serde" ];
           "serde1" = [ "serde" ];
         };
       };
@@ -719,6 +859,9 @@ rec {
         authors = [
           "Gilad Naaman <gilad@naaman.io>"
         ];
+        description = "Glob-matched recursive file system walking.";
+        repository = "https://github.com/gilnaa/globwalk";
+        license = "MIT";
         dependencies = [
           {
             name = "bitflags";
@@ -743,6 +886,10 @@ rec {
         authors = [
           "Without Boats <woboats@gmail.com>"
         ];
+        description = "heck is a case conversion library.";
+        homepage = "https://github.com/withoutboats/heck";
+        repository = "https://github.com/withoutboats/heck";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "unicode-segmentation";
@@ -756,9 +903,15 @@ rec {
         version = "0.1.19";
         edition = "2018";
         sha256 = "0cxcm8093nf5fyn114w8vxbrbcyvv91d4015rdnlgfll7cs6gd32";
+        libName = "hermit_abi";
         authors = [
           "Stefan Lankes"
         ];
+        description = "hermit-abi is small interface to call functions from the unikernel RustyHermit.
+It is used to build the target `x86_64-unknown-hermit`.
+";
+        repository = "https://github.com/hermitcore/libhermit-rs";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "libc";
@@ -767,6 +920,8 @@ rec {
           }
         ];
         features = {
+          "compiler_builtins" = [ "dep:compiler_builtins" ];
+          "core" = [ "dep:core" ];
           "rustc-dep-of-std" = [ "core" "compiler_builtins/rustc-dep-of-std" "libc/rustc-dep-of-std" ];
         };
         resolvedDefaultFeatures = [ "default" ];
@@ -779,8 +934,12 @@ rec {
         authors = [
           "KokaKiwi <kokakiwi@kokakiwi.net>"
         ];
+        description = "Encoding and decoding data into/from hexadecimal representation.";
+        repository = "https://github.com/KokaKiwi/rust-hex";
+        license = "MIT OR Apache-2.0";
         features = {
           "default" = [ "std" ];
+          "serde" = [ "dep:serde" ];
           "std" = [ "alloc" ];
         };
         resolvedDefaultFeatures = [ "alloc" "default" "std" ];
@@ -793,6 +952,9 @@ rec {
         authors = [
           "The rust-url developers"
         ];
+        description = "IDNA (Internationalizing Domain Names in Applications) and Punycode.";
+        repository = "https://github.com/servo/rust-url/";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "matches";
@@ -817,6 +979,12 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "A fast library for efficiently matching ignore files such as `.gitignore`
+against file paths.
+";
+        homepage = "https://github.com/BurntSushi/ripgrep/tree/master/crates/ignore";
+        repository = "https://github.com/BurntSushi/ripgrep/tree/master/crates/ignore";
+        license = "Unlicense/MIT";
         dependencies = [
           {
             name = "crossbeam-utils";
@@ -872,6 +1040,9 @@ rec {
         authors = [
           "bluss"
         ];
+        description = "Extra iterator adaptors, iterator methods, free functions, and macros.";
+        repository = "https://github.com/bluss/rust-itertools";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "either";
@@ -891,6 +1062,9 @@ rec {
         authors = [
           "bluss"
         ];
+        description = "Extra iterator adaptors, iterator methods, free functions, and macros.";
+        repository = "https://github.com/bluss/rust-itertools";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "either";
@@ -911,6 +1085,9 @@ rec {
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "Fast functions for printing integer primitives to an io::Write";
+        repository = "https://github.com/dtolnay/itoa";
+        license = "MIT OR Apache-2.0";
         features = {
           "default" = [ "std" ];
         };
@@ -923,7 +1100,11 @@ rec {
         authors = [
           "Marvin Löbel <loebel.marvin@gmail.com>"
         ];
+        description = "A macro for declaring lazily evaluated statics in Rust.";
+        repository = "https://github.com/rust-lang-nursery/lazy-static.rs";
+        license = "MIT/Apache-2.0";
         features = {
+          "spin" = [ "dep:spin" ];
           "spin_no_std" = [ "spin" ];
         };
       };
@@ -935,9 +1116,15 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        description = "Raw FFI bindings to platform libraries like libc.
+";
+        homepage = "https://github.com/rust-lang/libc";
+        repository = "https://github.com/rust-lang/libc";
+        license = "MIT OR Apache-2.0";
         features = {
           "default" = [ "std" ];
           "rustc-dep-of-std" = [ "align" "rustc-std-workspace-core" ];
+          "rustc-std-workspace-core" = [ "dep:rustc-std-workspace-core" ];
           "use_std" = [ "std" ];
         };
         resolvedDefaultFeatures = [ "default" "std" ];
@@ -950,6 +1137,10 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        description = "A lightweight logging facade for Rust
+";
+        repository = "https://github.com/rust-lang/log";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "cfg-if";
@@ -961,6 +1152,9 @@ rec {
           "kv_unstable_serde" = [ "kv_unstable_std" "value-bag/serde" "serde" ];
           "kv_unstable_std" = [ "std" "kv_unstable" "value-bag/error" ];
           "kv_unstable_sval" = [ "kv_unstable" "value-bag/sval" "sval" ];
+          "serde" = [ "dep:serde" ];
+          "sval" = [ "dep:sval" ];
+          "value-bag" = [ "dep:value-bag" ];
         };
       };
       "maplit" = rec {
@@ -971,6 +1165,9 @@ rec {
         authors = [
           "bluss"
         ];
+        description = "Collection “literal” macros for HashMap, HashSet, BTreeMap, and BTreeSet.";
+        repository = "https://github.com/bluss/maplit";
+        license = "MIT/Apache-2.0";
 
       };
       "matches" = rec {
@@ -982,6 +1179,9 @@ rec {
         authors = [
           "Simon Sapin <simon.sapin@exyr.org>"
         ];
+        description = "A macro to evaluate, as a boolean, whether an expression matches a pattern.";
+        repository = "https://github.com/SimonSapin/rust-std-candidates";
+        license = "MIT";
 
       };
       "memchr" = rec {
@@ -993,8 +1193,13 @@ rec {
           "Andrew Gallant <jamslam@gmail.com>"
           "bluss"
         ];
+        description = "Safe interface to memchr.";
+        homepage = "https://github.com/BurntSushi/rust-memchr";
+        repository = "https://github.com/BurntSushi/rust-memchr";
+        license = "Unlicense/MIT";
         features = {
           "default" = [ "std" ];
+          "libc" = [ "dep:libc" ];
           "use_std" = [ "std" ];
         };
         resolvedDefaultFeatures = [ "default" "std" ];
@@ -1004,9 +1209,13 @@ rec {
         version = "0.1.1";
         edition = "2018";
         sha256 = "04jnq6arig0amz0scadavbzn9bg9k4zphmrm1562n6ygfj1dnj45";
+        libName = "nix_base32";
         authors = [
           "Peter Kolloch <info@eigenvalue.net>"
         ];
+        description = "Provides a nix (as in NixOS) compatible base32 encoding.";
+        repository = "https://github.com/kolloch/nix-base32";
+        license = "Apache-2.0";
 
       };
       "once_cell" = rec {
@@ -1017,9 +1226,13 @@ rec {
         authors = [
           "Aleksey Kladov <aleksey.kladov@gmail.com>"
         ];
+        description = "Single assignment cells and lazy values.";
+        repository = "https://github.com/matklad/once_cell";
+        license = "MIT OR Apache-2.0";
         features = {
           "alloc" = [ "race" ];
           "default" = [ "std" ];
+          "parking_lot" = [ "dep:parking_lot" ];
           "std" = [ "alloc" ];
         };
         resolvedDefaultFeatures = [ "alloc" "default" "race" "std" ];
@@ -1029,9 +1242,13 @@ rec {
         version = "0.2.3";
         edition = "2015";
         sha256 = "172j6bs8ndclqxa2m64qc0y1772rr73g4l9fg2svscgicnbfff98";
+        libName = "opaque_debug";
         authors = [
           "RustCrypto Developers"
         ];
+        description = "Macro for opaque Debug trait implementation";
+        repository = "https://github.com/RustCrypto/utils";
+        license = "MIT OR Apache-2.0";
 
       };
       "pathdiff" = rec {
@@ -1042,6 +1259,9 @@ rec {
         authors = [
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        description = "Library for diffing paths to obtain relative paths";
+        repository = "https://github.com/Manishearth/pathdiff";
+        license = "MIT/Apache-2.0";
 
       };
       "percent-encoding" = rec {
@@ -1049,10 +1269,14 @@ rec {
         version = "1.0.1";
         edition = "2015";
         sha256 = "0cgq08v1fvr6bs5fvy390cz830lq4fak8havdasdacxcw790s09i";
+        libName = "percent_encoding";
         libPath = "lib.rs";
         authors = [
           "The rust-url developers"
         ];
+        description = "Percent encoding and decoding";
+        repository = "https://github.com/servo/rust-url/";
+        license = "MIT/Apache-2.0";
 
       };
       "pest" = rec {
@@ -1063,6 +1287,10 @@ rec {
         authors = [
           "Dragoș Tiselice <dragostiselice@gmail.com>"
         ];
+        description = "The Elegant Parser";
+        homepage = "https://pest-parser.github.io/";
+        repository = "https://github.com/pest-parser/pest";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "ucd-trie";
@@ -1071,6 +1299,8 @@ rec {
         ];
         features = {
           "pretty-print" = [ "serde" "serde_json" ];
+          "serde" = [ "dep:serde" ];
+          "serde_json" = [ "dep:serde_json" ];
         };
       };
       "pest_derive" = rec {
@@ -1082,6 +1312,10 @@ rec {
         authors = [
           "Dragoș Tiselice <dragostiselice@gmail.com>"
         ];
+        description = "pest's derive macro";
+        homepage = "https://pest-parser.github.io/";
+        repository = "https://github.com/pest-parser/pest";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "pest";
@@ -1102,6 +1336,10 @@ rec {
         authors = [
           "Dragoș Tiselice <dragostiselice@gmail.com>"
         ];
+        description = "pest code generator";
+        homepage = "https://pest-parser.github.io/";
+        repository = "https://github.com/pest-parser/pest";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "pest";
@@ -1134,6 +1372,10 @@ rec {
         authors = [
           "Dragoș Tiselice <dragostiselice@gmail.com>"
         ];
+        description = "pest meta language parser and validator";
+        homepage = "https://pest-parser.github.io/";
+        repository = "https://github.com/pest-parser/pest";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "maplit";
@@ -1158,9 +1400,13 @@ rec {
         version = "1.0.4";
         edition = "2018";
         sha256 = "1373bhxaf0pagd8zkyd03kkx6bchzf6g0dkwrwzsnal9z47lj9fs";
+        libName = "proc_macro_error";
         authors = [
           "CreepySkeleton <creepy-skeleton@yandex.ru>"
         ];
+        description = "Almost drop-in replacement to panics in proc-macros";
+        repository = "https://gitlab.com/CreepySkeleton/proc-macro-error";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "proc-macro-error-attr";
@@ -1189,6 +1435,7 @@ rec {
         ];
         features = {
           "default" = [ "syn-error" ];
+          "syn" = [ "dep:syn" ];
           "syn-error" = [ "syn" ];
         };
         resolvedDefaultFeatures = [ "default" "syn" "syn-error" ];
@@ -1199,9 +1446,13 @@ rec {
         edition = "2018";
         sha256 = "0sgq6m5jfmasmwwy8x4mjygx5l7kp8s4j60bv25ckv2j1qc41gm1";
         procMacro = true;
+        libName = "proc_macro_error_attr";
         authors = [
           "CreepySkeleton <creepy-skeleton@yandex.ru>"
         ];
+        description = "Attribute macro for proc-macro-error crate";
+        repository = "https://gitlab.com/CreepySkeleton/proc-macro-error";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "proc-macro2";
@@ -1225,10 +1476,16 @@ rec {
         version = "1.0.27";
         edition = "2018";
         sha256 = "0f3h0zl5w5090ajmmvpmhkpr4iwqnn5rip3afacabhc657vwmn7h";
+        libName = "proc_macro2";
         authors = [
           "Alex Crichton <alex@alexcrichton.com>"
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "A substitute implementation of the compiler's `proc_macro` API to decouple
+token-based libraries from the procedural macro use case.
+";
+        repository = "https://github.com/alexcrichton/proc-macro2";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "unicode-xid";
@@ -1248,6 +1505,9 @@ rec {
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "Quasi-quoting macro quote!(...)";
+        repository = "https://github.com/dtolnay/quote";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "proc-macro2";
@@ -1269,6 +1529,11 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        description = "Random number generators and other randomness functionality.
+";
+        homepage = "https://github.com/rust-lang-nursery/rand";
+        repository = "https://github.com/rust-lang-nursery/rand";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "fuchsia-cprng";
@@ -1301,6 +1566,7 @@ rec {
         ];
         features = {
           "default" = [ "std" ];
+          "libc" = [ "dep:libc" ];
           "nightly" = [ "i128_support" ];
           "std" = [ "libc" ];
         };
@@ -1315,6 +1581,11 @@ rec {
           "The Rand Project Developers"
           "The Rust Project Developers"
         ];
+        description = "Core random number generator traits and tools for implementation.
+";
+        homepage = "https://crates.io/crates/rand_core";
+        repository = "https://github.com/rust-random/rand";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "rand_core";
@@ -1337,8 +1608,15 @@ rec {
           "The Rand Project Developers"
           "The Rust Project Developers"
         ];
+        description = "Core random number generator traits and tools for implementation.
+";
+        homepage = "https://crates.io/crates/rand_core";
+        repository = "https://github.com/rust-random/rand";
+        license = "MIT/Apache-2.0";
         features = {
+          "serde" = [ "dep:serde" ];
           "serde1" = [ "serde" "serde_derive" ];
+          "serde_derive" = [ "dep:serde_derive" ];
           "std" = [ "alloc" ];
         };
       };
@@ -1350,6 +1628,9 @@ rec {
         authors = [
           "Simonas Kazlauskas <rdrand@kazlauskas.me>"
         ];
+        description = "An implementation of random number generator based on rdrand and rdseed instructions";
+        repository = "https://github.com/nagisa/rust_rdrand/";
+        license = "ISC";
         dependencies = [
           {
             name = "rand_core";
@@ -1370,6 +1651,12 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        description = "An implementation of regular expressions for Rust. This implementation uses
+finite automata and guarantees linear time matching on all inputs.
+";
+        homepage = "https://github.com/rust-lang/regex";
+        repository = "https://github.com/rust-lang/regex";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "aho-corasick";
@@ -1388,7 +1675,9 @@ rec {
           }
         ];
         features = {
+          "aho-corasick" = [ "dep:aho-corasick" ];
           "default" = [ "std" "perf" "unicode" "regex-syntax/default" ];
+          "memchr" = [ "dep:memchr" ];
           "perf" = [ "perf-cache" "perf-dfa" "perf-inline" "perf-literal" ];
           "perf-literal" = [ "aho-corasick" "memchr" ];
           "unicode" = [ "unicode-age" "unicode-bool" "unicode-case" "unicode-gencat" "unicode-perl" "unicode-script" "unicode-segment" "regex-syntax/unicode" ];
@@ -1409,9 +1698,14 @@ rec {
         version = "0.6.25";
         edition = "2018";
         sha256 = "16y87hz1bxmmz6kk360cxwfm3jnbsxb3x4zw9x1gzz7khic2i5zl";
+        libName = "regex_syntax";
         authors = [
           "The Rust Project Developers"
         ];
+        description = "A regular expression parser.";
+        homepage = "https://github.com/rust-lang/regex";
+        repository = "https://github.com/rust-lang/regex";
+        license = "MIT/Apache-2.0";
         features = {
           "default" = [ "unicode" ];
           "unicode" = [ "unicode-age" "unicode-bool" "unicode-case" "unicode-gencat" "unicode-perl" "unicode-script" "unicode-segment" ];
@@ -1426,6 +1720,9 @@ rec {
         authors = [
           "Aaronepower <theaaronepower@gmail.com>"
         ];
+        description = "A safe, reliable implementation of remove_dir_all for Windows";
+        repository = "https://github.com/XAMPPRocky/remove_dir_all.git";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "winapi";
@@ -1444,7 +1741,11 @@ rec {
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "Fast floating point to string conversion";
+        repository = "https://github.com/dtolnay/ryu";
+        license = "Apache-2.0 OR BSL-1.0";
         features = {
+          "no-panic" = [ "dep:no-panic" ];
         };
       };
       "same-file" = rec {
@@ -1452,9 +1753,15 @@ rec {
         version = "1.0.6";
         edition = "2018";
         sha256 = "00h5j1w87dmhnvbv9l8bic3y7xxsnjmssvifw2ayvgx9mb1ivz4k";
+        libName = "same_file";
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "A simple crate for determining whether two file paths point to the same file.
+";
+        homepage = "https://github.com/BurntSushi/same-file";
+        repository = "https://github.com/BurntSushi/same-file";
+        license = "Unlicense/MIT";
         dependencies = [
           {
             name = "winapi-util";
@@ -1472,6 +1779,9 @@ rec {
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "Parser and evaluator for Cargo's flavor of Semantic Versioning";
+        repository = "https://github.com/dtolnay/semver";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "serde";
@@ -1482,6 +1792,7 @@ rec {
         ];
         features = {
           "default" = [ "std" ];
+          "serde" = [ "dep:serde" ];
         };
         resolvedDefaultFeatures = [ "default" "serde" "std" ];
       };
@@ -1494,6 +1805,10 @@ rec {
           "Erick Tryzelaar <erick.tryzelaar@gmail.com>"
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "A generic serialization/deserialization framework";
+        homepage = "https://serde.rs";
+        repository = "https://github.com/serde-rs/serde";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "serde_derive";
@@ -1510,6 +1825,7 @@ rec {
         features = {
           "default" = [ "std" ];
           "derive" = [ "serde_derive" ];
+          "serde_derive" = [ "dep:serde_derive" ];
         };
         resolvedDefaultFeatures = [ "default" "derive" "serde_derive" "std" ];
       };
@@ -1523,6 +1839,10 @@ rec {
           "Erick Tryzelaar <erick.tryzelaar@gmail.com>"
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "Macros 1.1 implementation of #[derive(Serialize, Deserialize)]";
+        homepage = "https://serde.rs";
+        repository = "https://github.com/serde-rs/serde";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "proc-macro2";
@@ -1550,6 +1870,9 @@ rec {
           "Erick Tryzelaar <erick.tryzelaar@gmail.com>"
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "A JSON serialization file format";
+        repository = "https://github.com/serde-rs/json";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "itoa";
@@ -1569,6 +1892,7 @@ rec {
         features = {
           "alloc" = [ "serde/alloc" ];
           "default" = [ "std" ];
+          "indexmap" = [ "dep:indexmap" ];
           "preserve_order" = [ "indexmap" ];
           "std" = [ "serde/std" ];
         };
@@ -1583,6 +1907,9 @@ rec {
         authors = [
           "RustCrypto Developers"
         ];
+        description = "SHA-1 hash function";
+        repository = "https://github.com/RustCrypto/hashes";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "block-buffer";
@@ -1612,6 +1939,8 @@ rec {
           "asm" = [ "sha1-asm" ];
           "asm-aarch64" = [ "asm" "libc" ];
           "default" = [ "std" ];
+          "libc" = [ "dep:libc" ];
+          "sha1-asm" = [ "dep:sha1-asm" ];
           "std" = [ "digest/std" ];
         };
       };
@@ -1623,6 +1952,12 @@ rec {
         authors = [
           "Danny Guo <dannyguo91@gmail.com>"
         ];
+        description = "Implementations of string similarity metrics.
+Includes Hamming, Levenshtein, OSA, Damerau-Levenshtein, Jaro, and Jaro-Winkler.
+";
+        homepage = "https://github.com/dguo/strsim-rs";
+        repository = "https://github.com/dguo/strsim-rs";
+        license = "MIT";
 
       };
       "structopt" = rec {
@@ -1634,6 +1969,9 @@ rec {
           "Guillaume Pinot <texitoi@texitoi.eu>"
           "others"
         ];
+        description = "Parse command line argument by defining a struct.";
+        repository = "https://github.com/TeXitoi/structopt";
+        license = "Apache-2.0 OR MIT";
         dependencies = [
           {
             name = "clap";
@@ -1657,6 +1995,7 @@ rec {
           "lints" = [ "clap/lints" ];
           "no_cargo" = [ "clap/no_cargo" ];
           "paw" = [ "structopt-derive/paw" "paw_dep" ];
+          "paw_dep" = [ "dep:paw_dep" ];
           "suggestions" = [ "clap/suggestions" ];
           "wrap_help" = [ "clap/wrap_help" ];
           "yaml" = [ "clap/yaml" ];
@@ -1669,9 +2008,13 @@ rec {
         edition = "2018";
         sha256 = "044w7z0bnsvla4d26s1r8s4q9sbx4f60c02yfxa1mxgmxi5964vq";
         procMacro = true;
+        libName = "structopt_derive";
         authors = [
           "Guillaume Pinot <texitoi@texitoi.eu>"
         ];
+        description = "Parse command line argument by defining a struct, derive crate.";
+        repository = "https://github.com/TeXitoi/structopt";
+        license = "Apache-2.0/MIT";
         dependencies = [
           {
             name = "heck";
@@ -1706,6 +2049,9 @@ rec {
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        description = "Parser for Rust source code";
+        repository = "https://github.com/dtolnay/syn";
+        license = "MIT OR Apache-2.0";
         dependencies = [
           {
             name = "proc-macro2";
@@ -1727,6 +2073,7 @@ rec {
           "default" = [ "derive" "parsing" "printing" "clone-impls" "proc-macro" ];
           "printing" = [ "quote" ];
           "proc-macro" = [ "proc-macro2/proc-macro" "quote/proc-macro" ];
+          "quote" = [ "dep:quote" ];
           "test" = [ "syn-test-suite/all-features" ];
         };
         resolvedDefaultFeatures = [ "clone-impls" "default" "derive" "full" "parsing" "printing" "proc-macro" "quote" ];
@@ -1739,6 +2086,12 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        description = "A library for managing a temporary directory and deleting all contents when it's
+dropped.
+";
+        homepage = "https://github.com/rust-lang/tempdir";
+        repository = "https://github.com/rust-lang/tempdir";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "rand";
@@ -1759,6 +2112,10 @@ rec {
         authors = [
           "Vincent Prouillet <hello@prouilletvincent.com>"
         ];
+        description = "Template engine based on Jinja2/Django templates";
+        homepage = "https://tera.netlify.com/";
+        repository = "https://github.com/Keats/tera";
+        license = "MIT";
         dependencies = [
           {
             name = "globwalk";
@@ -1795,8 +2152,14 @@ rec {
         ];
         features = {
           "builtins" = [ "slug" "percent-encoding" "humansize" "chrono" "chrono-tz" "rand" ];
+          "chrono" = [ "dep:chrono" ];
+          "chrono-tz" = [ "dep:chrono-tz" ];
           "default" = [ "builtins" ];
+          "humansize" = [ "dep:humansize" ];
+          "percent-encoding" = [ "dep:percent-encoding" ];
           "preserve_order" = [ "serde_json/preserve_order" ];
+          "rand" = [ "dep:rand" ];
+          "slug" = [ "dep:slug" ];
         };
       };
       "textwrap" = rec {
@@ -1807,13 +2170,25 @@ rec {
         authors = [
           "Martin Geisler <martin@geisler.net>"
         ];
+        description = "Textwrap is a small library for word wrapping, indenting, and
+dedenting strings.
+
+You can use it to format strings (such as help and error messages) for
+display in commandline applications. It is designed to be efficient
+and handle Unicode characters correctly.
+";
+        repository = "https://github.com/mgeisler/textwrap";
+        license = "MIT";
         dependencies = [
           {
             name = "unicode-width";
             packageId = "unicode-width";
           }
         ];
-
+        features = {
+          "hyphenation" = [ "dep:hyphenation" ];
+          "term_size" = [ "dep:term_size" ];
+        };
       };
       "thread_local" = rec {
         crateName = "thread_local";
@@ -1823,13 +2198,18 @@ rec {
         authors = [
           "Amanieu d'Antras <amanieu@gmail.com>"
         ];
+        description = "Per-object thread-local storage";
+        repository = "https://github.com/Amanieu/thread_local-rs";
+        license = "Apache-2.0/MIT";
         dependencies = [
           {
             name = "once_cell";
             packageId = "once_cell";
           }
         ];
-
+        features = {
+          "criterion" = [ "dep:criterion" ];
+        };
       };
       "tinyvec" = rec {
         crateName = "tinyvec";
@@ -1839,6 +2219,9 @@ rec {
         authors = [
           "Lokathor <zefria@gmail.com>"
         ];
+        description = "`tinyvec` provides 100% safe vec-like data structures.";
+        repository = "https://github.com/Lokathor/tinyvec";
+        license = "Zlib OR Apache-2.0 OR MIT";
         dependencies = [
           {
             name = "tinyvec_macros";
@@ -1848,6 +2231,8 @@ rec {
         ];
         features = {
           "alloc" = [ "tinyvec_macros" ];
+          "serde" = [ "dep:serde" ];
+          "tinyvec_macros" = [ "dep:tinyvec_macros" ];
         };
         resolvedDefaultFeatures = [ "alloc" "default" "tinyvec_macros" ];
       };
@@ -1859,6 +2244,9 @@ rec {
         authors = [
           "Soveu <marx.tomasz@gmail.com>"
         ];
+        description = "Some macros for tiny containers";
+        repository = "https://github.com/Soveu/tinyvec_macros";
+        license = "MIT OR Apache-2.0 OR Zlib";
 
       };
       "toml" = rec {
@@ -1869,6 +2257,13 @@ rec {
         authors = [
           "Alex Crichton <alex@alexcrichton.com>"
         ];
+        description = "A native Rust encoder and decoder of TOML-formatted files and streams. Provides
+implementations of the standard Serialize/Deserialize traits for TOML data to
+facilitate deserializing and serializing Rust structures.
+";
+        homepage = "https://github.com/alexcrichton/toml-rs";
+        repository = "https://github.com/alexcrichton/toml-rs";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "serde";
@@ -1876,6 +2271,7 @@ rec {
           }
         ];
         features = {
+          "indexmap" = [ "dep:indexmap" ];
           "preserve_order" = [ "indexmap" ];
         };
         resolvedDefaultFeatures = [ "default" ];
@@ -1890,6 +2286,12 @@ rec {
           "Paho Lurie-Gregg <paho@paholg.com>"
           "Andre Bogus <bogusandre@gmail.com>"
         ];
+        description = "Typenum is a Rust library for type-level numbers evaluated at
+    compile time. It currently supports bits, unsigned integers, and signed
+    integers. It also provides a type-level array of type-level numbers, but its
+    implementation is incomplete.";
+        repository = "https://github.com/paholg/typenum";
+        license = "MIT OR Apache-2.0";
         features = {
         };
       };
@@ -1898,9 +2300,15 @@ rec {
         version = "0.1.3";
         edition = "2018";
         sha256 = "072cblf8v3wzyaz3lhbpzgil4s03dpzg1ppy3gqx2l4v622y3pjn";
+        libName = "ucd_trie";
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "A trie for storing Unicode codepoint sets and maps.
+";
+        homepage = "https://github.com/BurntSushi/ucd-generate";
+        repository = "https://github.com/BurntSushi/ucd-generate";
+        license = "MIT/Apache-2.0";
         features = {
           "default" = [ "std" ];
         };
@@ -1911,9 +2319,13 @@ rec {
         version = "0.9.0";
         edition = "2018";
         sha256 = "08g21dn3wwix3ycfl0vrbahn0835nv2q3swm8wms0vwvgm07mid8";
+        libName = "unic_char_property";
         authors = [
           "The UNIC Project Developers"
         ];
+        description = "UNIC — Unicode Character Tools — Character Property taxonomy, contracts and build macros";
+        repository = "https://github.com/open-i18n/rust-unic/";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "unic-char-range";
@@ -1927,10 +2339,15 @@ rec {
         version = "0.9.0";
         edition = "2018";
         sha256 = "1g0z7iwvjhqspi6194zsff8vy6i3921hpqcrp3v1813hbwnh5603";
+        libName = "unic_char_range";
         authors = [
           "The UNIC Project Developers"
         ];
+        description = "UNIC — Unicode Character Tools — Character Range and Iteration";
+        repository = "https://github.com/open-i18n/rust-unic/";
+        license = "MIT/Apache-2.0";
         features = {
+          "rayon" = [ "dep:rayon" ];
           "unstable" = [ "exact-size-is-empty" "fused" "trusted-len" ];
         };
         resolvedDefaultFeatures = [ "default" ];
@@ -1940,9 +2357,13 @@ rec {
         version = "0.9.0";
         edition = "2018";
         sha256 = "1g1mm954m0zr497dl4kx3vr09yaly290zs33bbl4wrbaba1gzmw0";
+        libName = "unic_common";
         authors = [
           "The UNIC Project Developers"
         ];
+        description = "UNIC — Common Utilities";
+        repository = "https://github.com/open-i18n/rust-unic/";
+        license = "MIT/Apache-2.0";
         features = {
         };
         resolvedDefaultFeatures = [ "default" ];
@@ -1952,9 +2373,13 @@ rec {
         version = "0.9.0";
         edition = "2018";
         sha256 = "08wgz2q6vrdvmbd23kf9pbg8cyzm5q8hq9spc4blzy2ppqk5vvg4";
+        libName = "unic_segment";
         authors = [
           "The UNIC Project Developers"
         ];
+        description = "UNIC — Unicode Text Segmentation Algorithms";
+        repository = "https://github.com/open-i18n/rust-unic/";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "unic-ucd-segment";
@@ -1968,9 +2393,13 @@ rec {
         version = "0.9.0";
         edition = "2018";
         sha256 = "0027lczcg0r401g6fnzm2bq9fxhgxvri1nlryhhv8192lqic2y90";
+        libName = "unic_ucd_segment";
         authors = [
           "The UNIC Project Developers"
         ];
+        description = "UNIC — Unicode Character Database — Segmentation Properties";
+        repository = "https://github.com/open-i18n/rust-unic/";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "unic-char-property";
@@ -1992,9 +2421,13 @@ rec {
         version = "0.9.0";
         edition = "2018";
         sha256 = "1i5hnzpfnxkp4ijfk8kvhpvj84bij575ybqx1b6hyigy6wi2zgcn";
+        libName = "unic_ucd_version";
         authors = [
           "The UNIC Project Developers"
         ];
+        description = "UNIC — Unicode Character Database — Version";
+        repository = "https://github.com/open-i18n/rust-unic/";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "unic-common";
@@ -2012,6 +2445,9 @@ rec {
         authors = [
           "The Servo Project Developers"
         ];
+        description = "Implementation of the Unicode Bidirectional Algorithm";
+        repository = "https://github.com/servo/unicode-bidi";
+        license = "MIT / Apache-2.0";
         dependencies = [
           {
             name = "matches";
@@ -2019,7 +2455,10 @@ rec {
           }
         ];
         features = {
+          "flame" = [ "dep:flame" ];
           "flame_it" = [ "flame" "flamer" ];
+          "flamer" = [ "dep:flamer" ];
+          "serde" = [ "dep:serde" ];
           "with_serde" = [ "serde" ];
         };
         resolvedDefaultFeatures = [ "default" ];
@@ -2029,10 +2468,19 @@ rec {
         version = "0.1.19";
         edition = "2018";
         sha256 = "1yabhmg8zlcksda3ajly9hpbzqgbhknxwch8dwkfkaa1569r0ifm";
+        libName = "unicode_normalization";
         authors = [
           "kwantam <kwantam@gmail.com>"
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        description = "This crate provides functions for normalization of
+Unicode strings, including Canonical and Compatible
+Decomposition and Recomposition, as described in
+Unicode Standard Annex #15.
+";
+        homepage = "https://github.com/unicode-rs/unicode-normalization";
+        repository = "https://github.com/unicode-rs/unicode-normalization";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "tinyvec";
@@ -2050,10 +2498,17 @@ rec {
         version = "1.8.0";
         edition = "2018";
         sha256 = "0nrqfgxkh00wb5dhl0874z20789i2yjimp6ndgh4ay4yjjd895c8";
+        libName = "unicode_segmentation";
         authors = [
           "kwantam <kwantam@gmail.com>"
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        description = "This crate provides Grapheme Cluster, Word and Sentence boundaries
+according to Unicode Standard Annex #29 rules.
+";
+        homepage = "https://github.com/unicode-rs/unicode-segmentation";
+        repository = "https://github.com/unicode-rs/unicode-segmentation";
+        license = "MIT/Apache-2.0";
         features = {
         };
       };
@@ -2062,12 +2517,22 @@ rec {
         version = "0.1.8";
         edition = "2015";
         sha256 = "1qxizyi6xbcqyi4z79p523ywvmgsfcgfqb3zv3c8i6x1jcc5jdwk";
+        libName = "unicode_width";
         authors = [
           "kwantam <kwantam@gmail.com>"
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        description = "Determine displayed width of `char` and `str` types
+according to Unicode Standard Annex #11 rules.
+";
+        homepage = "https://github.com/unicode-rs/unicode-width";
+        repository = "https://github.com/unicode-rs/unicode-width";
+        license = "MIT/Apache-2.0";
         features = {
+          "compiler_builtins" = [ "dep:compiler_builtins" ];
+          "core" = [ "dep:core" ];
           "rustc-dep-of-std" = [ "std" "core" "compiler_builtins" ];
+          "std" = [ "dep:std" ];
         };
         resolvedDefaultFeatures = [ "default" ];
       };
@@ -2076,11 +2541,19 @@ rec {
         version = "0.2.2";
         edition = "2015";
         sha256 = "1wrkgcw557v311dkdb6n2hrix9dm2qdsb1zpw7pn79l03zb85jwc";
+        libName = "unicode_xid";
         authors = [
           "erick.tryzelaar <erick.tryzelaar@gmail.com>"
           "kwantam <kwantam@gmail.com>"
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        description = "Determine whether characters have the XID_Start
+or XID_Continue properties according to
+Unicode Standard Annex #31.
+";
+        homepage = "https://github.com/unicode-rs/unicode-xid";
+        repository = "https://github.com/unicode-rs/unicode-xid";
+        license = "MIT OR Apache-2.0";
         features = {
         };
         resolvedDefaultFeatures = [ "default" ];
@@ -2093,6 +2566,9 @@ rec {
         authors = [
           "The rust-url developers"
         ];
+        description = "URL library for Rust, based on the WHATWG URL Standard";
+        repository = "https://github.com/servo/rust-url";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "idna";
@@ -2108,8 +2584,12 @@ rec {
           }
         ];
         features = {
+          "encoding" = [ "dep:encoding" ];
           "heap_size" = [ "heapsize" ];
+          "heapsize" = [ "dep:heapsize" ];
           "query_encoding" = [ "encoding" ];
+          "rustc-serialize" = [ "dep:rustc-serialize" ];
+          "serde" = [ "dep:serde" ];
         };
       };
       "url_serde" = rec {
@@ -2120,6 +2600,9 @@ rec {
         authors = [
           "The rust-url developers"
         ];
+        description = "Serde support for URL types";
+        repository = "https://github.com/servo/rust-url";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "serde";
@@ -2165,8 +2648,13 @@ rec {
           "Sean McArthur <sean.monstar@gmail.com>"
           "Vadim Petrochenkov <>"
         ];
+        description = "A simple map based on a vector for small integer keys";
+        homepage = "https://github.com/contain-rs/vec-map";
+        repository = "https://github.com/contain-rs/vec-map";
+        license = "MIT/Apache-2.0";
         features = {
           "eders" = [ "serde" ];
+          "serde" = [ "dep:serde" ];
         };
       };
       "version_check" = rec {
@@ -2177,6 +2665,9 @@ rec {
         authors = [
           "Sergio Benitez <sb@sergio.bz>"
         ];
+        description = "Tiny crate to check the version of the installed/running rustc.";
+        repository = "https://github.com/SergioBenitez/version_check";
+        license = "MIT/Apache-2.0";
 
       };
       "walkdir" = rec {
@@ -2187,6 +2678,10 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "Recursively walk a directory.";
+        homepage = "https://github.com/BurntSushi/walkdir";
+        repository = "https://github.com/BurntSushi/walkdir";
+        license = "Unlicense/MIT";
         dependencies = [
           {
             name = "same-file";
@@ -2214,6 +2709,9 @@ rec {
         authors = [
           "Peter Atashian <retep998@gmail.com>"
         ];
+        description = "Raw FFI bindings for all of Windows API.";
+        repository = "https://github.com/retep998/winapi-rs";
+        license = "MIT/Apache-2.0";
         dependencies = [
           {
             name = "winapi-i686-pc-windows-gnu";
@@ -2236,9 +2734,13 @@ rec {
         version = "0.4.0";
         edition = "2015";
         sha256 = "1dmpa6mvcvzz16zg6d5vrfy4bxgg541wxrcip7cnshi06v38ffxc";
+        libName = "winapi_i686_pc_windows_gnu";
         authors = [
           "Peter Atashian <retep998@gmail.com>"
         ];
+        description = "Import libraries for the i686-pc-windows-gnu target. Please don't use this crate directly, depend on winapi instead.";
+        repository = "https://github.com/retep998/winapi-rs";
+        license = "MIT/Apache-2.0";
 
       };
       "winapi-util" = rec {
@@ -2246,9 +2748,14 @@ rec {
         version = "0.1.5";
         edition = "2018";
         sha256 = "0y71bp7f6d536czj40dhqk0d55wfbbwqfp2ymqf1an5ibgl6rv3h";
+        libName = "winapi_util";
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        description = "A dumping ground for high level safe wrappers over winapi.";
+        homepage = "https://github.com/BurntSushi/winapi-util";
+        repository = "https://github.com/BurntSushi/winapi-util";
+        license = "Unlicense/MIT";
         dependencies = [
           {
             name = "winapi";
@@ -2264,13 +2771,25 @@ rec {
         version = "0.4.0";
         edition = "2015";
         sha256 = "0gqq64czqb64kskjryj8isp62m2sgvx25yyj3kpc2myh85w24bki";
+        libName = "winapi_x86_64_pc_windows_gnu";
         authors = [
           "Peter Atashian <retep998@gmail.com>"
         ];
+        description = "Import libraries for the x86_64-pc-windows-gnu target. Please don't use this crate directly, depend on winapi instead.";
+        repository = "https://github.com/retep998/winapi-rs";
+        license = "MIT/Apache-2.0";
 
       };
     };
 
+    # The workspace members whose features are resolved together with `shareDependencies`.
+    sharedDependenciesPackageIds = lib.optionals shareDependencies [
+      "crate2nix"
+    ];
+
+    # The rustc options of the cargo profile, for all crates but build dependencies and proc macros.
+    profileRustcOpts = [ ];
+
     #
 # crate2nix/default.nix (excerpt start)
 #
@@ -2278,7 +2797,10 @@ rec {
   /* Target (platform) data for conditional dependencies.
     This corresponds roughly to what buildRustCrate is setting.
   */
-  defaultTarget = {
+  defaultTarget = targetForPlatform stdenv.hostPlatform;
+
+  /* The target data of the given nixpkgs platform, see `defaultTarget`. */
+  targetForPlatform = platform: {
     unix = true;
     windows = false;
     fuchsia = true;
@@ -2287,20 +2809,42 @@ rec {
     # This doesn't appear to be officially documented anywhere yet.
     # See https://github.com/rust-lang-nursery/rust-forge/issues/101.
     os =
-      if stdenv.hostPlatform.isDarwin
+      if platform.isDarwin
       then "macos"
-      else stdenv.hostPlatform.parsed.kernel.name;
-    arch = stdenv.hostPlatform.parsed.cpu.name;
+      else platform.parsed.kernel.name;
+    arch = platform.parsed.cpu.name;
     family = "unix";
-    env = "gnu";
+    env = if platform.isMusl then "musl" else "gnu";
     endian =
-      if stdenv.hostPlatform.parsed.cpu.significantByte.name == "littleEndian"
+      if platform.parsed.cpu.significantByte.name == "littleEndian"
       then "little" else "big";
-    pointer_width = toString stdenv.hostPlatform.parsed.cpu.bits;
-    vendor = stdenv.hostPlatform.parsed.vendor.name;
+    pointer_width = toString platform.parsed.cpu.bits;
+    vendor = platform.parsed.vendor.name;
     debug_assertions = false;
   };
 
+  /* Returns the `CARGO_CFG_*` environment variables that cargo sets for build
+    scripts when building for the given target.
+  */
+  cargoCfgEnv = { target, targetFeatures ? [ ] }:
+    assert (builtins.isAttrs target);
+    assert (builtins.isList targetFeatures);
+    {
+      CARGO_CFG_TARGET_OS = target.os;
+      CARGO_CFG_TARGET_ARCH = target.arch;
+      CARGO_CFG_TARGET_FAMILY = target.family;
+      CARGO_CFG_TARGET_ENV = target.env;
+      CARGO_CFG_TARGET_ENDIAN = target.endian;
+      CARGO_CFG_TARGET_POINTER_WIDTH = target.pointer_width;
+      CARGO_CFG_TARGET_VENDOR = target.vendor;
+    }
+    // lib.optionalAttrs (target.unix or false) { CARGO_CFG_UNIX = ""; }
+    // lib.optionalAttrs (target.windows or false) { CARGO_CFG_WINDOWS = ""; }
+    // lib.optionalAttrs (target.debug_assertions or false) { CARGO_CFG_DEBUG_ASSERTIONS = ""; }
+    // lib.optionalAttrs (targetFeatures != [ ]) {
+      CARGO_CFG_TARGET_FEATURE = lib.concatStringsSep "," targetFeatures;
+    };
+
   /* Filters common temp files and build files. */
   # TODO(pkolloch): Substitute with gitignore filter
   sourceFilter = name: type:
@@ -2428,12 +2972,52 @@ rec {
       ${lib.concatMapStringsSep "\n" (output: "ln -s ${crate.${output}} ${"$"}${output}") crate.outputs}
     '';
 
+  /* Returns the crate overrides to use when building for the given target triple.
+
+    Next to the usual override functions, an override can be an attribute set
+    with an optional `base` override and overrides by target triple in `perTarget`:
+
+      crateOverrides = defaultCrateOverrides // {
+        openssl-sys = {
+          base = attrs: { buildInputs = [ pkgs.openssl ]; };
+          perTarget."x86_64-apple-darwin" = attrs: {
+            buildInputs = attrs.buildInputs ++ [ pkgs.darwin.apple_sdk.frameworks.Security ];
+          };
+        };
+      };
+
+    The override for the target is applied on top of the base override.
+    Overrides can be functions or attribute sets.
+  */
+  crateOverridesForTarget = targetTriple: crateOverrides:
+    let
+      toFunction = override: if builtins.isFunction override then override else (_: override);
+      forTarget = override:
+        let
+          base = toFunction (override.base or { });
+          perTarget = toFunction (override.perTarget.${targetTriple} or { });
+        in
+        attrs:
+          let
+            baseAttrs = base attrs;
+          in
+          baseAttrs // perTarget (attrs // baseAttrs);
+    in
+    lib.mapAttrs
+      (name: override:
+        if builtins.isAttrs override && (override ? base || override ? perTarget)
+        then forTarget override
+        else override)
+      crateOverrides;
+
   /* A restricted overridable version of builtRustCratesWithFeatures. */
   buildRustCrateWithFeatures =
     { packageId
     , features ? rootFeatures
     , crateOverrides ? defaultCrateOverrides
     , buildRustCrateForPkgsFunc ? null
+      # Other packages to resolve features together with, see builtRustCratesWithFeatures.
+    , unifyFeaturesWith ? [ ]
     , runTests ? false
     , testCrateFlags ? [ ]
     , testInputs ? [ ]
@@ -2462,16 +3046,17 @@ rec {
                 then buildRustCrateForPkgs
                 else
                   pkgs: (buildRustCrateForPkgs pkgs).override {
-                    defaultCrateOverrides = crateOverrides;
+                    defaultCrateOverrides =
+                      crateOverridesForTarget pkgs.stdenv.hostPlatform.config crateOverrides;
                   }
               );
           builtRustCrates = builtRustCratesWithFeatures {
-            inherit packageId features;
+            inherit packageId features unifyFeaturesWith;
             buildRustCrateForPkgsFunc = buildRustCrateForPkgsFuncOverriden;
             runTests = false;
           };
           builtTestRustCrates = builtRustCratesWithFeatures {
-            inherit packageId features;
+            inherit packageId features unifyFeaturesWith;
             buildRustCrateForPkgsFunc = buildRustCrateForPkgsFuncOverriden;
             runTests = true;
           };
@@ -2483,7 +3068,9 @@ rec {
                 {
                   crate = drv;
                   testCrate = testDrv;
-                  inherit testCrateFlags testInputs testPreRun testPostRun;
+                  # The configured check inputs are only needed for running the tests.
+                  testInputs = testInputs ++ (crates.${packageId}.checkInputs or [ ]);
+                  inherit testCrateFlags testPreRun testPostRun;
                 }
             else drv;
         in
@@ -2493,6 +3080,10 @@ rec {
 
   /* Returns an attr set with packageId mapped to the result of buildRustCrateForPkgsFunc
     for the corresponding crate.
+
+    The features of the packages in unifyFeaturesWith are resolved together with the
+    ones of packageId, like `cargo build --workspace` does. Building several packages
+    with the same unifyFeaturesWith therefore builds every common dependency only once.
   */
   builtRustCratesWithFeatures =
     { packageId
@@ -2501,41 +3092,52 @@ rec {
     , buildRustCrateForPkgsFunc
     , runTests
     , target ? defaultTarget
+    , unifyFeaturesWith ? [ ]
     } @ args:
       assert (builtins.isAttrs crateConfigs);
       assert (builtins.isString packageId);
       assert (builtins.isList features);
       assert (builtins.isAttrs target);
       assert (builtins.isBool runTests);
+      assert (builtins.isList unifyFeaturesWith);
       let
         rootPackageId = packageId;
-        mergedFeatures = mergePackageFeatures
-          (
-            args // {
-              inherit rootPackageId;
-              target = target // { test = runTests; };
-            }
-          );
+        mergedFeatures = lib.foldl
+          (featuresByPackageId: packageId: mergePackageFeatures
+            (
+              args // {
+                inherit packageId rootPackageId featuresByPackageId;
+                target = target // { test = runTests; };
+              }
+            ))
+          { }
+          ([ rootPackageId ] ++ lib.remove rootPackageId unifyFeaturesWith);
         # Memoize built packages so that reappearing packages are only built once.
-        builtByPackageIdByPkgs = mkBuiltByPackageIdByPkgs pkgs;
-        mkBuiltByPackageIdByPkgs = pkgs:
+        builtByPackageIdByPkgs = mkBuiltByPackageIdByPkgs pkgs profileRustcOpts
+          (cargoCfgEnv { inherit target targetFeatures; });
+        # Like cargo, build scripts, their dependencies and proc macros ignore the profile options.
+        # They run on the build platform, so their CARGO_CFG_* variables describe it instead of `target`.
+        mkBuiltByPackageIdByPkgs = pkgs: profileRustcOpts: cfgEnv:
           let
             self = {
-              crates = lib.mapAttrs (packageId: value: buildByPackageIdForPkgsImpl self pkgs packageId) crateConfigs;
-              build = mkBuiltByPackageIdByPkgs pkgs.buildPackages;
+              crates = lib.mapAttrs (packageId: value: buildByPackageIdForPkgsImpl self pkgs profileRustcOpts cfgEnv packageId) crateConfigs;
+              build = mkBuiltByPackageIdByPkgs pkgs.buildPackages [ ]
+                (cargoCfgEnv { target = targetForPlatform pkgs.buildPackages.stdenv.hostPlatform; });
             };
           in
           self;
-        buildByPackageIdForPkgsImpl = self: pkgs: packageId:
+        buildByPackageIdForPkgsImpl = self: pkgs: profileRustcOpts: cfgEnv: packageId:
           let
             features = mergedFeatures."${packageId}" or [ ];
             crateConfig' = crateConfigs."${packageId}";
             crateConfig =
-              builtins.removeAttrs crateConfig' [ "resolvedDefaultFeatures" "devDependencies" ];
+              builtins.removeAttrs crateConfig' [ "resolvedDefaultFeatures" "devDependencies" "checkInputs" ];
             devDependencies =
               lib.optionals
                 (runTests && packageId == rootPackageId)
                 (crateConfig'.devDependencies or [ ]);
+            # Artifact dependencies without `lib = true` are not linked.
+            libraryDependencies = lib.filter (d: !(d ? artifact) || (d.artifact.lib or false));
             dependencies =
               dependencyDerivations {
                 inherit features target;
@@ -2544,16 +3146,27 @@ rec {
                   if crateConfigs.${depPackageId}.procMacro or false
                   then self.build.crates.${depPackageId}
                   else self.crates.${depPackageId};
-                dependencies =
-                  (crateConfig.dependencies or [ ])
-                  ++ devDependencies;
+                dependencies = libraryDependencies
+                  ((crateConfig.dependencies or [ ])
+                  ++ devDependencies);
               };
             buildDependencies =
               dependencyDerivations {
                 inherit features target;
                 buildByPackageId = depPackageId:
                   self.build.crates.${depPackageId};
-                dependencies = crateConfig.buildDependencies or [ ];
+                dependencies = libraryDependencies (crateConfig.buildDependencies or [ ]);
+              };
+            artifactEnv =
+              artifactDependencyEnv {
+                inherit crateConfigs;
+                buildByPackageId = depPackageId: self.crates.${depPackageId};
+                dependencies = filterEnabledDependenciesForThis (crateConfig.dependencies or [ ]);
+              }
+              // artifactDependencyEnv {
+                inherit crateConfigs;
+                buildByPackageId = depPackageId: self.build.crates.${depPackageId};
+                dependencies = filterEnabledDependenciesForThis (crateConfig.buildDependencies or [ ]);
               };
             filterEnabledDependenciesForThis = dependencies: filterEnabledDependencies {
               inherit dependencies features target;
@@ -2568,31 +3181,11 @@ rec {
                       ++ devDependencies
                     )
                 );
-            # Crate renames have the form:
-            #
-            # {
-            #    crate_name = [
-            #       { version = "1.2.3"; rename = "crate_name01"; }
-            #    ];
-            #    # ...
-            # }
-            crateRenames =
-              let
-                grouped =
-                  lib.groupBy
-                    (dependency: dependency.name)
-                    dependenciesWithRenames;
-                versionAndRename = dep:
-                  let
-                    package = crateConfigs."${dep.packageId}";
-                  in
-                  { inherit (dep) rename; version = package.version; };
-              in
-              lib.mapAttrs (name: choices: builtins.map versionAndRename choices) grouped;
+            crateRenames = crateRenamesFor crateConfigs dependenciesWithRenames;
           in
           buildRustCrateForPkgsFunc pkgs
             (
-              crateConfig // {
+              cfgEnv // artifactEnv // crateConfig // {
                 src = crateConfig.src or (
                   pkgs.fetchurl rec {
                     name = "${crateConfig.crateName}-${crateConfig.version}.tar.gz";
@@ -2604,7 +3197,12 @@ rec {
                       crateConfig.sha256;
                   }
                 );
-                extraRustcOpts = lib.lists.optional (targetFeatures != [ ]) "-C target-feature=${lib.concatMapStringsSep "," (x: "+${x}") targetFeatures}";
+                extraRustcOpts =
+                  (crateConfig.extraRustcOpts or [ ])
+                  # Like cargo, build tests with the default panic strategy.
+                  ++ lib.filter (opt: !(runTests && opt == "-C panic=abort"))
+                    (lib.optionals (!(crateConfig.procMacro or false)) profileRustcOpts)
+                  ++ lib.lists.optional (targetFeatures != [ ]) "-C target-feature=${lib.concatMapStringsSep "," (x: "+${x}") targetFeatures}";
                 inherit features dependencies buildDependencies crateRenames release;
               }
             );
@@ -2629,6 +3227,69 @@ rec {
       in
       map depDerivation enabledDependencies;
 
+  /* Returns the `CARGO_BIN_FILE_*` environment variables for the binaries of the
+    given artifact dependencies, i.e. dependencies with `artifact = "bin"`.
+
+    The binaries are the `crateBin` of the dependency. Other artifact kinds
+    are not supported.
+  */
+  artifactDependencyEnv = { crateConfigs, buildByPackageId, dependencies }:
+    assert (builtins.isAttrs crateConfigs);
+    assert (builtins.isList dependencies);
+    let
+      envName = name: lib.toUpper (lib.replaceStrings [ "-" ] [ "_" ] name);
+      binEnv = dependency:
+        let
+          name = dependency.rename or dependency.name;
+          crateConfig = crateConfigs.${dependency.packageId};
+          kinds = dependency.artifact.kinds or [ ];
+          allBins = map (bin: bin.name) (crateConfig.crateBin or [ ]);
+          selectedBins =
+            if builtins.elem "bin" kinds
+            then allBins
+            else
+              builtins.filter
+                (bin: builtins.elem "bin:${bin}" kinds)
+                allBins;
+          binPath = bin: "${buildByPackageId dependency.packageId}/bin/${bin}";
+        in
+        lib.listToAttrs
+          (map (bin: lib.nameValuePair "CARGO_BIN_FILE_${envName name}_${bin}" (binPath bin)) selectedBins)
+        // lib.optionalAttrs (builtins.elem dependency.name selectedBins) {
+          "CARGO_BIN_FILE_${envName name}" = binPath dependency.name;
+        };
+    in
+    lib.foldl (env: dependency: env // binEnv dependency) { }
+      (builtins.filter (d: d ? artifact) dependencies);
+
+  /* Returns the crate renames of the given renamed dependencies in the form
+    expected by buildRustCrate:
+
+    {
+       crate_name = [
+          { version = "1.2.3"; rename = "crate_name01"; }
+       ];
+       # ...
+    }
+
+    A crate can be present in several versions with different renames. Each
+    version/rename pair is only listed once, even if the dependency is e.g. both
+    a build and a normal dependency.
+  */
+  crateRenamesFor = crateConfigs: dependenciesWithRenames:
+    let
+      grouped =
+        lib.groupBy
+          (dependency: dependency.name)
+          dependenciesWithRenames;
+      versionAndRename = dep:
+        let
+          package = crateConfigs."${dep.packageId}";
+        in
+        { inherit (dep) rename; version = package.version; };
+    in
+    lib.mapAttrs (name: choices: lib.unique (builtins.map versionAndRename choices)) grouped;
+
   /* Returns a sanitized version of val with all values substituted that cannot
     be serialized as JSON.
   */
@@ -2740,7 +3401,10 @@ rec {
       let
         crateConfig = crateConfigs."${packageId}" or (builtins.throw "Package not found: ${packageId}");
         expandedFeatures = expandFeatures (crateConfig.features or { }) features;
-        enabledFeatures = enableFeatures (crateConfig.dependencies or [ ]) expandedFeatures;
+        # Optional build dependencies are enabled by features just like normal ones.
+        enabledFeatures = enableFeatures
+          ((crateConfig.dependencies or [ ]) ++ (crateConfig.buildDependencies or [ ]))
+          expandedFeatures;
         depWithResolvedFeatures = dependency:
           let
             packageId = dependency.packageId;
@@ -0,0 +1,299 @@
+//! Checking whether a generated build file is up to date.
+
+use anyhow::{format_err, Error};
+use difference::{Changeset, Difference};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{render, BuildInfo, GenerateConfig, GenerateInfo};
+
+/// The result of [check_up_to_date].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum CheckResult {
+    /// The build file matches what would be generated.
+    UpToDate,
+    /// The build file is missing or differs from what would be generated.
+    Stale {
+        /// The lines that differ.
+        diff: Vec<DiffLine>,
+    },
+}
+
+impl CheckResult {
+    /// Whether the build file matches what would be generated.
+    pub fn is_up_to_date(&self) -> bool {
+        *self == CheckResult::UpToDate
+    }
+}
+
+/// A line that differs between the existing and the generated build file.
+///
+/// Line numbers start at 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum DiffLine {
+    /// A line of the existing build file that would not be generated.
+    Removed {
+        /// The line number in the existing build file.
+        line: usize,
+        /// The line without the line break.
+        content: String,
+    },
+    /// A line that would be generated but is missing in the existing build file.
+    Added {
+        /// The line number in the generated build file.
+        line: usize,
+        /// The line without the line break.
+        content: String,
+    },
+}
+
+/// Checks whether `config.output` is what `crate2nix generate` would write for `config`.
+///
+/// Nothing is written and no hashes are prefetched, see [BuildInfo::for_config_offline].
+/// The comment header with the crate2nix version and command line is not compared.
+pub fn check_up_to_date(config: &GenerateConfig) -> Result<CheckResult, Error> {
+    let build_info = BuildInfo::for_config_offline(&GenerateInfo::default(), config)?;
+    let generated = render::CARGO_NIX.render(&build_info)?;
+    let existing = if config.output.exists() {
+        std::fs::read_to_string(&config.output)
+            .map_err(|e| format_err!("while reading {}: {}", config.output.to_string_lossy(), e))?
+    } else {
+        String::new()
+    };
+
+    let diff = diff_lines(&existing, &generated);
+    Ok(if diff.is_empty() {
+        CheckResult::UpToDate
+    } else {
+        CheckResult::Stale { diff }
+    })
+}
+
+/// Returns the lines that differ between `existing` and `generated`, ignoring their
+/// comment headers.
+///
+/// Within each block of changed lines, the removed lines come before the added ones.
+fn diff_lines(existing: &str, generated: &str) -> Vec<DiffLine> {
+    let (existing_offset, existing) = without_header(existing);
+    let (generated_offset, generated) = without_header(generated);
+
+    // The diff takes quadratic time and memory, so the common leading and trailing lines
+    // (most of a stale build file) are left out.
+    let prefix = common_prefix(&existing, &generated);
+    let suffix = common_suffix(&existing[prefix..], &generated[prefix..]);
+    // Every line gets a leading space so that an empty line is not mistaken for no lines.
+    let joined = |lines: &[&str]| lines.iter().map(|l| format!(" {}", l)).join("\n");
+    let changeset = Changeset::new(
+        &joined(&existing[prefix..existing.len() - suffix]),
+        &joined(&generated[prefix..generated.len() - suffix]),
+        "\n",
+    );
+
+    let mut existing_line = existing_offset + prefix;
+    let mut generated_line = generated_offset + prefix;
+    let mut diff = Vec::new();
+    for difference in &changeset.diffs {
+        match difference {
+            Difference::Same(lines) => {
+                let same = lines.split('\n').count();
+                existing_line += same;
+                generated_line += same;
+            }
+            Difference::Rem(lines) => {
+                for content in lines.split('\n') {
+                    existing_line += 1;
+                    diff.push(DiffLine::Removed {
+                        line: existing_line,
+                        content: content[1..].to_string(),
+                    });
+                }
+            }
+            Difference::Add(lines) => {
+                for content in lines.split('\n') {
+                    generated_line += 1;
+                    diff.push(DiffLine::Added {
+                        line: generated_line,
+                        content: content[1..].to_string(),
+                    });
+                }
+            }
+        }
+    }
+    diff
+}
+
+fn common_prefix(a: &[&str], b: &[&str]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix(a: &[&str], b: &[&str]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// The number of leading comment lines and the remaining lines.
+fn without_header(content: &str) -> (usize, Vec<&str>) {
+    let lines: Vec<&str> = content.lines().collect();
+    let header = lines.iter().take_while(|l| l.starts_with('#')).count();
+    (header, lines[header..].to_vec())
+}
+
+#[test]
+fn test_diff_lines() {
+    let existing = "# generated by 0.1\n{\n  a = 1;\n  b = 2;\n}\n";
+    let generated = "# generated by 0.2\n# with a longer header\n{\n  a = 1;\n  b = 3;\n}\n";
+    assert_eq!(diff_lines(existing, existing), vec![]);
+    assert_eq!(
+        diff_lines(existing, &existing.replace("0.1", "0.2")),
+        vec![]
+    );
+    assert_eq!(
+        diff_lines(existing, generated),
+        vec![
+            DiffLine::Removed {
+                line: 4,
+                content: "  b = 2;".to_string()
+            },
+            DiffLine::Added {
+                line: 5,
+                content: "  b = 3;".to_string()
+            },
+        ]
+    );
+    assert_eq!(
+        diff_lines("", "{\n}\n"),
+        vec![
+            DiffLine::Added {
+                line: 1,
+                content: "{".to_string()
+            },
+            DiffLine::Added {
+                line: 2,
+                content: "}".to_string()
+            },
+        ]
+    );
+    assert_eq!(
+        diff_lines("{\n\n}\n", "{\n  a = 1;\n}\n"),
+        vec![
+            DiffLine::Removed {
+                line: 2,
+                content: "".to_string()
+            },
+            DiffLine::Added {
+                line: 2,
+                content: "  a = 1;".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_diff_lines_turn_the_existing_into_the_generated_lines() {
+    // A minimal linear congruential generator for reproducible line soups.
+    let mut seed = 42_u64;
+    let mut random = |max: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % max
+    };
+    for _ in 0..200 {
+        let mut lines = |len: u64| {
+            let len = random(len);
+            (0..len)
+                .map(|_| format!("line {}", random(4)))
+                .collect::<Vec<_>>()
+        };
+        let existing = lines(12);
+        let generated = lines(12);
+
+        let diff = diff_lines(
+            &existing
+                .iter()
+                .map(|l| format!("{}\n", l))
+                .collect::<String>(),
+            &generated
+                .iter()
+                .map(|l| format!("{}\n", l))
+                .collect::<String>(),
+        );
+
+        // Removing and adding the lines gives the generated lines.
+        let mut patched = existing.clone();
+        for line in diff.iter().rev() {
+            if let DiffLine::Removed { line, .. } = line {
+                patched.remove(line - 1);
+            }
+        }
+        for line in &diff {
+            if let DiffLine::Added { line, content } = line {
+                patched.insert(line - 1, content.clone());
+            }
+        }
+        assert_eq!(patched, generated, "{:?}", diff);
+    }
+}
+
+#[test]
+fn test_diff_lines_of_large_files() {
+    // A quadratic table for all lines would take 3.2 GB.
+    let existing: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+    let generated = existing.replace("line 10000\n", "changed\n");
+    assert_eq!(
+        diff_lines(&existing, &generated),
+        vec![
+            DiffLine::Removed {
+                line: 10_001,
+                content: "line 10000".to_string()
+            },
+            DiffLine::Added {
+                line: 10_001,
+                content: "changed".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_check_up_to_date() {
+    let project = tempdir::TempDir::new("crate2nix_check_up_to_date").unwrap();
+    let cargo_toml = crate::test::write_lib_project(project.path(), "checked");
+    let config = crate::test::generate_config_for(&cargo_toml);
+
+    match check_up_to_date(&config).unwrap() {
+        CheckResult::Stale { diff } => assert!(diff
+            .iter()
+            .all(|line| matches!(line, DiffLine::Added { .. }))),
+        CheckResult::UpToDate => panic!("missing build file is up to date"),
+    }
+
+    let build_info = BuildInfo::for_config(&GenerateInfo::default(), &config).unwrap();
+    let generated = render::CARGO_NIX.render(&build_info).unwrap();
+    std::fs::write(&config.output, &generated).unwrap();
+    assert_eq!(check_up_to_date(&config).unwrap(), CheckResult::UpToDate);
+
+    std::fs::write(
+        &config.output,
+        generated.replace("release ? true", "release ? false"),
+    )
+    .unwrap();
+    match check_up_to_date(&config).unwrap() {
+        CheckResult::Stale { diff } => assert_eq!(
+            diff.iter()
+                .map(|line| match line {
+                    DiffLine::Removed { content, .. } => format!("-{}", content.trim()),
+                    DiffLine::Added { content, .. } => format!("+{}", content.trim()),
+                })
+                .collect::<Vec<_>>(),
+            vec!["-, release ? false", "+, release ? true"]
+        ),
+        CheckResult::UpToDate => panic!("modified build file is up to date"),
+    }
+    assert!(!project.path().join("crate-hashes.json").exists());
+
+    project.close().unwrap();
+}
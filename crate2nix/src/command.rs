@@ -1,7 +1,7 @@
 //! Utilities for dealing with spawned commands.
 
 use anyhow::{bail, format_err, Error};
-use std::process::{Child, Stdio};
+use std::process::{Child, ExitStatus, Stdio};
 use std::thread;
 use std::{
     io::{BufRead, Read},
@@ -16,6 +16,46 @@ use std::{
 pub fn run(caption: &str, command: &mut std::process::Command) -> Result<(), Error> {
     eprint!("{}: ", caption);
 
+    let (status, out) = capture(command)?;
+
+    if status.success() {
+        eprintln!("done.");
+        return Ok(());
+    }
+
+    eprintln!();
+    eprintln!("  {:?}", command);
+    for line in indented_lines(out)? {
+        println!("{}", line);
+    }
+
+    bail!(
+        "{:?}\n=> exited with: {}",
+        command,
+        status.code().unwrap_or(-1)
+    );
+}
+
+/// Runs the given command with output capturing without printing anything.
+///
+/// If the command does not return successfully, the error contains its indented output,
+/// e.g. for commands that run in parallel and must not interleave their output.
+pub fn run_captured(command: &mut std::process::Command) -> Result<(), Error> {
+    let (status, out) = capture(command)?;
+    if status.success() {
+        return Ok(());
+    }
+
+    bail!(
+        "{:?}\n=> exited with: {}\n{}",
+        command,
+        status.code().unwrap_or(-1),
+        indented_lines(out)?.join("\n")
+    );
+}
+
+/// Runs the command and returns its exit status and its combined stdout and stderr.
+fn capture(command: &mut std::process::Command) -> Result<(ExitStatus, Vec<u8>), Error> {
     let mut spawned: Child = command
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
@@ -36,27 +76,17 @@ pub fn run(caption: &str, command: &mut std::process::Command) -> Result<(), Err
     let status = spawned
         .wait()
         .map_err(|e| format_err!("while waiting for the {:?} to finish: {}", command, e))?;
+    Ok((status, out))
+}
 
-    if status.success() {
-        eprintln!("done.");
-        return Ok(());
-    }
-
-    eprintln!();
-    eprintln!("  {:?}", command);
-    let line_reader = BufReader::new(Cursor::new(out));
-    for line in line_reader.lines() {
-        println!(
-            "  {}",
-            line.map_err(|e| format_err!("while processing output lines: {}", e))?
-        );
-    }
-
-    bail!(
-        "{:?}\n=> exited with: {}",
-        command,
-        status.code().unwrap_or(-1)
-    );
+fn indented_lines(out: Vec<u8>) -> Result<Vec<String>, Error> {
+    BufReader::new(Cursor::new(out))
+        .lines()
+        .map(|line| {
+            line.map(|line| format!("  {}", line))
+                .map_err(|e| format_err!("while processing output lines: {}", e))
+        })
+        .collect()
 }
 
 fn pass_through(mut read: impl Read + Send + 'static, sender: mpsc::Sender<Vec<u8>>) {
@@ -7,7 +7,7 @@ use std::{
     fmt::Display,
     fs::File,
     io::{BufReader, BufWriter},
-    path::Path,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
@@ -37,9 +37,136 @@ impl Config {
 
 /// The `crate2nix.json` config data.
 #[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Config {
     /// Out of tree sources.
+    #[serde(default)]
     pub sources: BTreeMap<String, Source>,
+    /// Target features by target triple, e.g. `{ "x86_64-unknown-linux-gnu": ["avx2"] }`.
+    ///
+    /// The features for the target that is built for are passed to rustc via
+    /// `-C target-feature`. Other targets do not get any of them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub target_features: BTreeMap<String, Vec<String>>,
+    /// Custom unpacking by crate name, for crates with unusual source archives.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unpack: BTreeMap<String, Unpack>,
+    /// Shell commands that are run after patching the source, by crate name, e.g. to
+    /// fix quirks of vendored sources. Passed as `postPatch`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub post_patch: BTreeMap<String, String>,
+    /// Shell commands that are run before configuring, by crate name, e.g. to set up the
+    /// environment for a build script. Passed as `preConfigure`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pre_configure: BTreeMap<String, String>,
+    /// Shell commands that are run before building, by crate name, e.g. to generate code
+    /// that the crate expects. Passed as `preBuild`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pre_build: BTreeMap<String, String>,
+    /// Flags for the C compiler of all crates, e.g. for crates that compile bundled C code
+    /// with `cc`. Passed as `NIX_CFLAGS_COMPILE`, nix interpolations like
+    /// `-I${pkgs.zlib.dev}/include` are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nix_cflags_compile: Option<String>,
+    /// Flags for the C compiler by crate name, in addition to the `nixCflagsCompile`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_nix_cflags_compile: BTreeMap<String, String>,
+    /// Flags for the linker of all crates. Passed as `NIX_LDFLAGS`, nix interpolations
+    /// like `-L${pkgs.zlib}/lib` are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nix_ldflags: Option<String>,
+    /// Flags for the linker by crate name, in addition to the `nixLdflags`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_nix_ldflags: BTreeMap<String, String>,
+    /// Rustc options by crate type, e.g. `{ "cdylib": ["-C", "prefer-dynamic"] }`.
+    ///
+    /// Crates that emit one of the crate types get its options in `extraRustcOpts`, in
+    /// addition to the options of the cargo profiles. Since `buildRustCrate` passes
+    /// `extraRustcOpts` to every target, they also apply to the other targets of the crate.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_type_rustc_opts: BTreeMap<String, Vec<String>>,
+    /// Rustc options by crate name and crate type, in addition to the `crateTypeRustcOpts`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_crate_type_rustc_opts: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    /// The directory to cache the `cargo metadata` output in, relative to `crate2nix.json`.
+    /// Committing it allows to generate with `--offline`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_cache: Option<PathBuf>,
+    /// The `config.json` of registry indexes other than crates.io by index, e.g.
+    /// `{ "sparse+https://example.com/index/": { "dl": "https://example.com/crates" } }`.
+    ///
+    /// Used instead of reading the index, which makes generating `--offline` possible
+    /// for crates from these registries.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub registry_configs: BTreeMap<String, crate::registry::IndexConfig>,
+    /// The platforms that crates can be built on, by crate name. Passed as `meta.platforms`
+    /// and `meta.badPlatforms`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub platforms: BTreeMap<String, Platforms>,
+    /// The `lib.sourceTypes` of crates by crate name, e.g. `{ "ring": ["fromSource",
+    /// "binaryNativeCode"] }` for a crate that bundles prebuilt objects. Passed as
+    /// `meta.sourceProvenance`, other crates are built `fromSource`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub source_provenance: BTreeMap<String, Vec<String>>,
+    /// Whether to pass `--show-trace` to nix invocations by default.
+    ///
+    /// Can be overridden with `--show-trace` and `--no-show-trace`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_trace: Option<bool>,
+    /// Fetch git sources from known forges as archive tarballs instead of cloning them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_archives: Option<GitArchives>,
+    /// The nix systems to expose packages for in `packagesBySystem`, e.g. `["aarch64-linux"]`.
+    ///
+    /// Systems other than the one of the build platform are cross compiled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub systems: Vec<String>,
+    /// Packages of `pkgs` that are available when running the tests of any crate,
+    /// e.g. `["protobuf"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub check_inputs: Vec<String>,
+    /// Packages of `pkgs` that are available when running the tests of the crate with the
+    /// given name, in addition to the `checkInputs`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_check_inputs: BTreeMap<String, Vec<String>>,
+}
+
+/// Overrides how the source of a crate is unpacked.
+///
+/// All other crates keep the default unpacking of `buildRustCrate`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Unpack {
+    /// The shell command to unpack the source in `$curSrc`, passed as `unpackCmd`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unpack_cmd: Option<String>,
+    /// The directory containing the unpacked source, passed as `sourceRoot`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_root: Option<String>,
+}
+
+/// Fetching git sources as archive tarballs.
+///
+/// Sources from other hosts or with other URL schemes are still cloned.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GitArchives {
+    /// Archive URL patterns by host, e.g. for self-hosted Gitea instances. `{host}`, `{owner}`,
+    /// `{repo}` and `{rev}` are replaced, an empty pattern disables the built-in pattern.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub patterns: BTreeMap<String, String>,
+}
+
+/// The platforms that a crate can or cannot be built on, as nix systems like `x86_64-linux`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Platforms {
+    /// The supported platforms. All platforms if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+    /// The platforms that are not supported even if they are in `platforms`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bad_platforms: Vec<String>,
 }
 
 impl Config {
@@ -64,8 +191,8 @@ impl Config {
 
         let max_len = self
             .sources
-            .iter()
-            .map(|(n, _)| n.len())
+            .keys()
+            .map(|n| n.len())
             .max()
             .unwrap_or_default();
         for (name, source) in &self.sources {
@@ -80,6 +207,62 @@ impl Config {
             println!();
         }
     }
+
+    /// Whether to pass `--show-trace` to nix, the command line flags override `showTrace`.
+    pub fn show_trace_enabled(&self, show_trace: bool, no_show_trace: bool) -> bool {
+        if show_trace || no_show_trace {
+            show_trace
+        } else {
+            self.show_trace.unwrap_or(true)
+        }
+    }
+
+    /// The directory to cache the `cargo metadata` output in: the one from the command line
+    /// or else the `metadataCache` relative to the directory of `crate2nix_json`.
+    pub fn metadata_cache_dir(
+        &self,
+        crate2nix_json: &Path,
+        command_line: Option<PathBuf>,
+    ) -> Option<PathBuf> {
+        command_line.or_else(|| {
+            self.metadata_cache.as_ref().map(|dir| {
+                crate2nix_json
+                    .parent()
+                    .unwrap_or_else(|| Path::new("."))
+                    .join(dir)
+            })
+        })
+    }
+}
+
+/// The settings that `crate2nix generate` uses after merging the command line flags,
+/// `crate2nix.json` and the cargo profiles, as printed by `--print-effective-config`.
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfig<'a> {
+    /// The generation configuration, including the settings taken from `crate2nix.json`.
+    pub generate: &'a crate::GenerateConfig,
+    /// The `crate2nix.json` configuration.
+    pub crate2nix_json: &'a Config,
+    /// The rustc options for the cargo profiles of the workspace.
+    pub profile_rustc_opts: crate::profile::ProfileRustcOpts,
+    /// Whether nix is called with `--show-trace`.
+    pub show_trace: bool,
+}
+
+impl<'a> EffectiveConfig<'a> {
+    /// Collects the effective settings, reading the cargo profiles from the manifests.
+    pub fn new(
+        generate: &'a crate::GenerateConfig,
+        crate2nix_json: &'a Config,
+        show_trace: bool,
+    ) -> Result<EffectiveConfig<'a>, Error> {
+        Ok(EffectiveConfig {
+            generate,
+            crate2nix_json,
+            profile_rustc_opts: crate::profile::ProfileRustcOpts::for_config(generate)?,
+            show_trace,
+        })
+    }
 }
 
 /// An out of tree source.
@@ -155,7 +338,7 @@ impl Source {
             Source::CratesIo { name, .. } => Some(name),
             Source::Git { url, .. } => {
                 let path = url.path();
-                let after_last_slash = path.split('/').last().unwrap_or(path);
+                let after_last_slash = path.split('/').next_back().unwrap_or(path);
                 let without_dot_git = after_last_slash
                     .strip_suffix(".git")
                     .unwrap_or(after_last_slash);
@@ -163,7 +346,7 @@ impl Source {
             }
             Source::Nix {
                 attr: Some(attr), ..
-            } => attr.split('.').last().or(if attr.trim().is_empty() {
+            } => attr.split('.').next_back().or(if attr.trim().is_empty() {
                 None
             } else {
                 Some(attr.trim())
@@ -233,3 +416,45 @@ impl FromStr for SourceType {
         }
     }
 }
+
+#[test]
+fn test_effective_config_prefers_command_line_flags() {
+    let project = tempdir::TempDir::new("crate2nix_effective_config").unwrap();
+    let crate2nix_json = project.path().join("crate2nix.json");
+    std::fs::write(
+        &crate2nix_json,
+        r#"{ "metadataCache": "committed-cache", "showTrace": false }"#,
+    )
+    .unwrap();
+    let config = Config::read_from_or_default(&crate2nix_json).unwrap();
+
+    let effective_config = |metadata_cache: Option<PathBuf>, show_trace: bool| {
+        let generate = crate::GenerateConfig {
+            cargo_toml: vec![project.path().join("Cargo.toml")],
+            metadata_cache: config.metadata_cache_dir(&crate2nix_json, metadata_cache),
+            ..crate::test::generate_config()
+        };
+        let show_trace = config.show_trace_enabled(show_trace, false);
+        serde_json::to_value(EffectiveConfig::new(&generate, &config, show_trace).unwrap()).unwrap()
+    };
+
+    let from_file = effective_config(None, false);
+    assert_eq!(
+        from_file["generate"]["metadata_cache"],
+        serde_json::json!(project.path().join("committed-cache"))
+    );
+    assert_eq!(from_file["show_trace"], false);
+
+    let from_command_line = effective_config(Some("cli-cache".into()), true);
+    assert_eq!(
+        from_command_line["generate"]["metadata_cache"],
+        serde_json::json!("cli-cache")
+    );
+    assert_eq!(from_command_line["show_trace"], true);
+    assert_eq!(
+        from_command_line["crate2nix_json"]["metadataCache"],
+        serde_json::json!("committed-cache")
+    );
+
+    project.close().unwrap();
+}
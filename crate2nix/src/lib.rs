@@ -13,8 +13,10 @@ use std::path::PathBuf;
 use std::{
     collections::{BTreeMap, HashMap, HashSet, VecDeque},
     path::Path,
+    time::Instant,
 };
 
+use anyhow::bail;
 use anyhow::format_err;
 use anyhow::Context;
 use anyhow::Error;
@@ -26,20 +28,31 @@ use serde::Serialize;
 use crate::metadata::IndexedMetadata;
 use crate::resolve::{CrateDerivation, ResolvedSource};
 use itertools::Itertools;
-use resolve::CratesIoSource;
+use resolve::{CratesIoSource, RegistrySource};
 
+pub mod check;
 mod command;
 pub mod config;
+pub mod licenses;
 mod lock;
 mod metadata;
+mod metadata_cache;
 pub mod nix_build;
+pub mod nixpkgs_compat;
 mod prefetch;
+mod profile;
+pub mod registry;
 pub mod render;
 mod resolve;
+pub mod rust_platform;
 pub mod sources;
+pub mod stats;
 #[cfg(test)]
+#[allow(missing_docs)]
 pub mod test;
+pub mod update;
 pub mod util;
+pub mod warnings;
 
 /// The resolved build info and the input for rendering the build.nix.tera template.
 #[derive(Debug, Deserialize, Serialize)]
@@ -48,6 +61,9 @@ pub struct BuildInfo {
     pub root_package_id: Option<PackageId>,
     /// Workspaces member package IDs by package names.
     pub workspace_members: BTreeMap<String, PackageId>,
+    /// The workspace members that are built by default, i.e. the `default-members` of the
+    /// workspace or all workspace members.
+    pub default_workspace_members: BTreeMap<String, PackageId>,
     /// Build info for all crates needed for this build.
     pub crates: Vec<CrateDerivation>,
     /// For convenience include the source for tests.
@@ -56,11 +72,43 @@ pub struct BuildInfo {
     pub info: GenerateInfo,
     /// The generation configuration.
     pub config: GenerateConfig,
+    /// The `crate2nix.json` configuration.
+    pub crate2nix_json: crate::config::Config,
+    /// The `buildRustCrate` attributes supported by the targeted nixpkgs release.
+    pub build_rust_crate_attributes: nixpkgs_compat::BuildRustCrateAttributes,
+    /// The rustc options for the cargo profiles of the workspace.
+    pub profile_rustc_opts: profile::ProfileRustcOpts,
+    /// Timings and prefetch counts of the generation.
+    #[serde(skip)]
+    pub stats: stats::Stats,
 }
 
 impl BuildInfo {
     /// Return the `NixBuildInfo` data ready for rendering the nix build file.
     pub fn for_config(info: &GenerateInfo, config: &GenerateConfig) -> Result<BuildInfo, Error> {
+        BuildInfo::resolve(info, config, config.offline)
+    }
+
+    /// Like `for_config` but without network access: `cargo metadata` runs with `--offline`,
+    /// yanked crates are not checked and only known hashes are used.
+    ///
+    /// Fails if a hash is unknown. Does not write the crate hashes file.
+    pub fn for_config_offline(
+        info: &GenerateInfo,
+        config: &GenerateConfig,
+    ) -> Result<BuildInfo, Error> {
+        let mut config = config.clone();
+        config.other_metadata_options.push("--offline".to_string());
+        BuildInfo::resolve(info, &config, true)
+    }
+
+    /// Resolves the crates that the build needs without looking up their sources in
+    /// registries or prefetching their hashes, e.g. for reporting their licenses.
+    pub fn for_config_without_sources(
+        info: &GenerateInfo,
+        config: &GenerateConfig,
+    ) -> Result<BuildInfo, Error> {
+        let started = Instant::now();
         let merged = {
             let mut metadatas = Vec::new();
             for cargo_toml in &config.cargo_toml {
@@ -68,7 +116,9 @@ impl BuildInfo {
             }
             metadata::MergedMetadata::merge(metadatas)?
         };
+        let metadata_ms = stats::elapsed_ms(started);
 
+        let started = Instant::now();
         let indexed_metadata = IndexedMetadata::new_from_merged(merged).map_err(|e| {
             format_err!(
                 "while indexing metadata for {:#?}: {}",
@@ -80,15 +130,114 @@ impl BuildInfo {
                 e
             )
         })?;
-        let mut default_nix = BuildInfo::new(info, config, indexed_metadata)?;
+        let crate2nix_json = crate::config::Config::read_from_or_default(
+            &config
+                .crate_hashes_json
+                .parent()
+                .expect("crate-hashes.json has parent dir")
+                .join("crate2nix.json"),
+        )?;
+
+        let mut default_nix = BuildInfo::new(info, config, crate2nix_json, indexed_metadata)?;
 
         default_nix.prune_unneeded_crates();
+        default_nix.stats.timings.metadata_ms = metadata_ms;
+        default_nix.stats.timings.resolve_ms = stats::elapsed_ms(started);
+        Ok(default_nix)
+    }
 
-        prefetch_and_fill_crates_sha256(config, &mut default_nix)?;
+    fn resolve(
+        info: &GenerateInfo,
+        config: &GenerateConfig,
+        offline: bool,
+    ) -> Result<BuildInfo, Error> {
+        let mut default_nix = BuildInfo::for_config_without_sources(info, config)?;
+
+        let lock_file_checksums = extract_hashes_from_lockfile(config, &mut default_nix)?;
+
+        let started = Instant::now();
+        let mut index_reader = registry::IndexReader::default();
+        fill_registry_sources(
+            &mut default_nix,
+            &mut index_reader,
+            &lock_file_checksums,
+            offline,
+        )?;
+        if !offline {
+            registry::check_yanked(
+                config.yanked_policy,
+                &mut index_reader,
+                default_nix.registry_crates(&registry::RegistryIndex::crates_io()),
+            )?;
+        }
+        default_nix.stats.timings.resolve_ms += stats::elapsed_ms(started);
+
+        let started = Instant::now();
+        prefetch_and_fill_crates_sha256(config, &mut default_nix, lock_file_checksums, offline)?;
+        default_nix.stats.timings.prefetch_ms = stats::elapsed_ms(started);
 
         Ok(default_nix)
     }
 
+    /// The crates that are downloaded from registries, with their index, name and version.
+    fn registry_crates<'a>(
+        &'a self,
+        crates_io: &'a registry::RegistryIndex,
+    ) -> Vec<(&'a registry::RegistryIndex, &'a str, &'a semver::Version)> {
+        self.crates
+            .iter()
+            .filter_map(|c| match &c.source {
+                ResolvedSource::CratesIo(CratesIoSource { name, version, .. }) => {
+                    Some((crates_io, name.as_str(), version))
+                }
+                ResolvedSource::Registry(RegistrySource {
+                    index,
+                    name,
+                    version,
+                    ..
+                }) => Some((index, name.as_str(), version)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The sources of the crates that are downloaded from registries, ready for `fetchurl`.
+    ///
+    /// Sorted by name and version, without duplicates. Fails if a hash is unknown.
+    pub fn fetchurl_sources(&self) -> Result<Vec<FetchurlSource>, Error> {
+        let mut sources = self
+            .crates
+            .iter()
+            .filter_map(|c| match &c.source {
+                ResolvedSource::CratesIo(source) => Some((
+                    &source.name,
+                    &source.version,
+                    Some(source.url()),
+                    &source.sha256,
+                )),
+                ResolvedSource::Registry(source) => Some((
+                    &source.name,
+                    &source.version,
+                    source.url.clone(),
+                    &source.sha256,
+                )),
+                _ => None,
+            })
+            .map(|(name, version, url, sha256)| {
+                let missing = |what| format_err!("missing {} for {} {}", what, name, version);
+                Ok(FetchurlSource {
+                    name: name.clone(),
+                    version: version.clone(),
+                    url: url.ok_or_else(|| missing("download URL"))?,
+                    sha256: sha256.clone().ok_or_else(|| missing("sha256"))?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        sources.sort();
+        sources.dedup();
+        Ok(sources)
+    }
+
     fn prune_unneeded_crates(&mut self) {
         let mut queue: VecDeque<&PackageId> = self
             .root_package_id
@@ -122,65 +271,176 @@ impl BuildInfo {
     fn new(
         info: &GenerateInfo,
         config: &GenerateConfig,
+        crate2nix_json: crate::config::Config,
         metadata: IndexedMetadata,
     ) -> Result<BuildInfo, Error> {
-        let crate2nix_json = crate::config::Config::read_from_or_default(
-            &config
-                .crate_hashes_json
-                .parent()
-                .expect("crate-hashes.json has parent dir")
-                .join("crate2nix.json"),
-        )?;
-
+        let workspace_members: BTreeMap<String, PackageId> = metadata
+            .workspace_members
+            .iter()
+            .flat_map(|pkg_id| {
+                metadata
+                    .pkgs_by_id
+                    .get(pkg_id)
+                    .map(|pkg| (pkg.name.clone(), pkg_id.clone()))
+            })
+            .collect();
+        let default_workspace_members =
+            default_workspace_members(&config.cargo_toml, &metadata, &workspace_members);
+        let crates = metadata
+            .pkgs_by_id
+            .values()
+            .map(|package| CrateDerivation::resolve(config, &crate2nix_json, &metadata, package))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let build_rust_crate_attributes =
+            nixpkgs_compat::BuildRustCrateAttributes::for_release(config.nixpkgs_compat.as_ref());
+        build_rust_crate_attributes.check_config(config)?;
         Ok(BuildInfo {
             root_package_id: metadata.root.clone(),
-            workspace_members: metadata
-                .workspace_members
-                .iter()
-                .flat_map(|pkg_id| {
-                    metadata
-                        .pkgs_by_id
-                        .get(pkg_id)
-                        .map(|pkg| (pkg.name.clone(), pkg_id.clone()))
-                })
-                .collect(),
-            crates: metadata
-                .pkgs_by_id
-                .values()
-                .map(|package| {
-                    CrateDerivation::resolve(config, &crate2nix_json, &metadata, package)
-                })
-                .collect::<Result<_, Error>>()?,
+            workspace_members,
+            default_workspace_members,
+            crates,
             indexed_metadata: metadata,
             info: info.clone(),
             config: config.clone(),
+            crate2nix_json,
+            build_rust_crate_attributes,
+            profile_rustc_opts: profile::ProfileRustcOpts::for_config(config)?,
+            stats: stats::Stats::default(),
         })
     }
 }
 
+/// Returns the workspace members that are listed in the `workspace.default-members` of the
+/// given manifests, or all workspace members if there are none.
+fn default_workspace_members(
+    cargo_tomls: &[PathBuf],
+    metadata: &IndexedMetadata,
+    workspace_members: &BTreeMap<String, PackageId>,
+) -> BTreeMap<String, PackageId> {
+    let canonicalize = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let default_member_dirs: Vec<PathBuf> = cargo_tomls
+        .iter()
+        .flat_map(|cargo_toml| {
+            let workspace_dir = cargo_toml.parent().unwrap_or_else(|| Path::new("."));
+            let manifest: toml::Value = std::fs::read_to_string(cargo_toml)
+                .ok()
+                .and_then(|content| content.parse().ok())
+                .unwrap_or_else(|| toml::Value::Table(Default::default()));
+            manifest
+                .get("workspace")
+                .and_then(|w| w.get("default-members"))
+                .and_then(|m| m.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|m| m.as_str())
+                .map(|m| canonicalize(&workspace_dir.join(m)))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let defaults: BTreeMap<String, PackageId> = workspace_members
+        .iter()
+        .filter(|(_, pkg_id)| {
+            metadata
+                .pkgs_by_id
+                .get(pkg_id)
+                .and_then(|pkg| pkg.manifest_path.parent())
+                .map(|dir| default_member_dirs.contains(&canonicalize(dir.as_ref())))
+                .unwrap_or(false)
+        })
+        .map(|(name, pkg_id)| (name.clone(), pkg_id.clone()))
+        .collect();
+    if defaults.is_empty() {
+        workspace_members.clone()
+    } else {
+        defaults
+    }
+}
+
 /// Call `cargo metadata` and return result.
 fn cargo_metadata(config: &GenerateConfig, cargo_toml: &Path) -> Result<Metadata, Error> {
-    let mut cmd = cargo_metadata::MetadataCommand::new();
-    let mut other_options = config.other_metadata_options.clone();
-    other_options.push("--locked".into());
-    cmd.manifest_path(&cargo_toml)
-        .other_options(&*other_options);
-    cmd.exec().map_err(|e| {
-        format_err!(
-            "while retrieving metadata about {}: {}",
-            &cargo_toml.to_string_lossy(),
-            e
-        )
-    })
+    metadata_cache::cargo_metadata(config, cargo_toml)
+}
+
+/// Look up download URLs and checksums of crates from registries other than crates.io.
+///
+/// Works with git as well as sparse indexes. The checksums of `Cargo.lock` and the
+/// `registryConfigs` of `crate2nix.json` take precedence over the index. With `offline`,
+/// the index is never read and missing data is an error.
+fn fill_registry_sources(
+    default_nix: &mut BuildInfo,
+    index_reader: &mut registry::IndexReader,
+    lock_file_checksums: &HashMap<PackageId, String>,
+    offline: bool,
+) -> Result<(), Error> {
+    for package in default_nix.crates.iter_mut() {
+        if let ResolvedSource::Registry(source) = &mut package.source {
+            let RegistrySource {
+                index,
+                registry,
+                name,
+                version,
+                url,
+                sha256,
+            } = source;
+            let registry = registry.clone().unwrap_or_else(|| index.to_string());
+            let checksum = match lock_file_checksums.get(&package.package_id) {
+                Some(checksum) => checksum.clone(),
+                None if offline => bail!(
+                    "Generating offline needs the checksum of {} {} from registry {} \
+                     in Cargo.lock.",
+                    name,
+                    version,
+                    registry
+                ),
+                None => {
+                    index_reader
+                        .entry(index, name, version)
+                        .map_err(|e| {
+                            format_err!(
+                                "while looking up {} {} in registry {}: {}",
+                                name,
+                                version,
+                                registry,
+                                e
+                            )
+                        })?
+                        .cksum
+                }
+            };
+            let index_config = match default_nix
+                .crate2nix_json
+                .registry_configs
+                .get(&index.to_string())
+            {
+                Some(index_config) => index_config.clone(),
+                None if offline => bail!(
+                    "Generating offline needs the config.json of registry {} \
+                     in crate2nix.json, e.g. \"registryConfigs\": {{ \"{}\": {{ \"dl\": \"...\" }} }}.",
+                    registry,
+                    index
+                ),
+                None => index_reader.config(index)?,
+            };
+            *url = Some(index_config.download_url(name, version, &checksum));
+            if sha256.is_none() {
+                let bytes = hex::decode(&checksum)
+                    .map_err(|e| format_err!("while decoding '{}': {}", checksum, e))?;
+                *sha256 = Some(nix_base32::to_nix_base32(&bytes));
+            }
+        }
+    }
+
+    Ok(())
 }
 
 /// Prefetch hashes when necessary.
 fn prefetch_and_fill_crates_sha256(
     config: &GenerateConfig,
     default_nix: &mut BuildInfo,
+    mut from_lock_file: HashMap<PackageId, String>,
+    offline: bool,
 ) -> Result<(), Error> {
-    let mut from_lock_file: HashMap<PackageId, String> =
-        extract_hashes_from_lockfile(&config, default_nix)?;
     for (_package_id, hash) in from_lock_file.iter_mut() {
         let bytes =
             hex::decode(&hash).map_err(|e| format_err!("while decoding '{}': {}", hash, e))?;
@@ -192,6 +452,8 @@ fn prefetch_and_fill_crates_sha256(
         &from_lock_file,
         &default_nix.crates,
         &default_nix.indexed_metadata.id_shortener,
+        offline,
+        &mut default_nix.stats,
     )
     .map_err(|e| format_err!("while prefetching crates for calculating sha256: {}", e))?;
 
@@ -225,7 +487,7 @@ fn extract_hashes_from_lockfile(
     let mut hashes: HashMap<PackageId, String> = HashMap::new();
 
     for cargo_toml in &config.cargo_toml {
-        let lock_file_path = cargo_toml.parent().unwrap().join("Cargo.lock");
+        let lock_file_path = config.lock_file(cargo_toml);
         let lock_file = crate::lock::EncodableResolve::load_lock_file(&lock_file_path)?;
         lock_file
             .get_hashes_by_package_id(&mut hashes)
@@ -235,16 +497,23 @@ fn extract_hashes_from_lockfile(
             ))?;
     }
 
-    let hashes_with_shortened_ids: HashMap<PackageId, String> = hashes
-        .into_iter()
-        .map(|(package_id, hash)| {
-            (
-                default_nix
-                    .indexed_metadata
-                    .id_shortener
-                    .shorten_owned(package_id),
-                hash,
-            )
+    // Lock files identify packages as "name version (source)", which is not the format of
+    // the package ids of newer cargo versions.
+    let hashes_with_shortened_ids: HashMap<PackageId, String> = default_nix
+        .indexed_metadata
+        .pkgs_by_id
+        .iter()
+        .filter_map(|(package_id, package)| {
+            let lock_file_id = PackageId {
+                repr: format!(
+                    "{} {} ({})",
+                    package.name,
+                    package.version,
+                    package.source.as_ref()?.repr
+                ),
+            };
+            let hash = hashes.get(&lock_file_id)?;
+            Some((package_id.clone(), hash.clone()))
         })
         .collect();
 
@@ -258,15 +527,40 @@ fn extract_hashes_from_lockfile(
         missing_hashes.push(format!("{} {}", package.crate_name, package.version));
     }
     if !missing_hashes.is_empty() {
-        eprintln!(
-            "Did not find all crates.io hashes in Cargo.lock. Hashes for e.g. {} are missing.\n\
-             This is probably a bug.",
-            missing_hashes.iter().take(10).join(", ")
+        warnings::warn(
+            warnings::WarningKind::MissingLockChecksums,
+            format!(
+                "Did not find all crates.io hashes in Cargo.lock. Hashes for e.g. {} are missing.\n\
+                 This is probably a bug.",
+                missing_hashes.iter().take(10).join(", ")
+            ),
         );
     }
     Ok(hashes_with_shortened_ids)
 }
 
+/// A crate source that can be fetched with `fetchurl`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct FetchurlSource {
+    /// The name of the crate.
+    pub name: String,
+    /// The version of the crate.
+    pub version: semver::Version,
+    /// The download URL of the crate archive.
+    pub url: String,
+    /// The nix base32 sha256 hash of the crate archive.
+    pub sha256: String,
+}
+
+/// The input for rendering the `fetchurl` sources list.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FetchurlSources {
+    /// The generation info.
+    pub info: GenerateInfo,
+    /// The sources, sorted by name and version.
+    pub sources: Vec<FetchurlSource>,
+}
+
 /// Some info about the crate2nix invocation.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct GenerateInfo {
@@ -303,4 +597,199 @@ pub struct GenerateConfig {
     pub other_metadata_options: Vec<String>,
     /// Whether to read a `crate-hashes.json` file.
     pub read_crate_hashes: bool,
+    /// The nixpkgs release to generate for. Attributes that its `buildRustCrate` does not
+    /// support are omitted. If `None`, all attributes are used.
+    pub nixpkgs_compat: Option<nixpkgs_compat::NixpkgsRelease>,
+    /// What to do if yanked crate versions are used.
+    pub yanked_policy: registry::YankedPolicy,
+    /// The C library to build against.
+    pub libc: Libc,
+    /// Whether to support artifact dependencies, e.g. `artifact = "bin"`.
+    ///
+    /// This needs a nightly cargo for `-Z bindeps`.
+    pub artifact_dependencies: bool,
+    /// Whether to emit a top-level `all` attribute that joins the default workspace members.
+    pub all_attribute: bool,
+    /// Whether to emit `meta.license` with the nixpkgs licenses of the SPDX license expressions.
+    pub meta_license: bool,
+    /// Whether to emit `meta.sourceProvenance` for every crate, `fromSource` unless
+    /// `sourceProvenance` in `crate2nix.json` says otherwise.
+    pub meta_source_provenance: bool,
+    /// Whether to omit `meta.license` and `meta.sourceProvenance` for crates that declare
+    /// `publish = false` and for path dependencies outside of the workspace.
+    pub meta_skip_unpublished: bool,
+    /// How often to retry `cargo metadata` on transient failures.
+    pub metadata_retries: u32,
+    /// The directory to cache the `cargo metadata` output in.
+    pub metadata_cache: Option<PathBuf>,
+    /// The `Cargo.lock` of the first `Cargo.toml` if it is not next to it.
+    ///
+    /// It is used as it is, i.e. `cargo metadata` is called with `--locked`.
+    pub lockfile: Option<PathBuf>,
+    /// Whether to call `cargo metadata` with `--locked` and a temporary `CARGO_TARGET_DIR`
+    /// so that it does not modify the workspace.
+    pub isolated_metadata: bool,
+    /// The nix expression for the path of the `buildRustCrate` to use instead of the one
+    /// of nixpkgs.
+    pub build_rust_crate_path: Option<String>,
+    /// Whether to generate without network access and without running `cargo metadata`.
+    ///
+    /// The metadata is read from `metadata_cache` and only known hashes are used.
+    pub offline: bool,
+}
+
+impl GenerateConfig {
+    /// The configuration with the defaults of `crate2nix generate` for the given manifests
+    /// and output files.
+    pub fn new(
+        cargo_toml: Vec<PathBuf>,
+        output: PathBuf,
+        crate_hashes_json: PathBuf,
+    ) -> GenerateConfig {
+        GenerateConfig {
+            cargo_toml,
+            use_cargo_lock_checksums: true,
+            output,
+            crate_hashes_json,
+            nixpkgs_path: "<nixpkgs>".to_string(),
+            other_metadata_options: vec!["--all-features".to_string()],
+            read_crate_hashes: true,
+            nixpkgs_compat: None,
+            yanked_policy: registry::YankedPolicy::default(),
+            libc: Libc::Glibc,
+            artifact_dependencies: false,
+            all_attribute: false,
+            meta_license: false,
+            meta_source_provenance: false,
+            meta_skip_unpublished: false,
+            metadata_retries: 2,
+            metadata_cache: None,
+            lockfile: None,
+            isolated_metadata: true,
+            build_rust_crate_path: None,
+            offline: false,
+        }
+    }
+
+    /// The `Cargo.lock` that is used for the given `Cargo.toml`.
+    pub fn lock_file(&self, cargo_toml: &Path) -> PathBuf {
+        match &self.lockfile {
+            Some(lockfile) if self.cargo_toml.first().map(PathBuf::as_path) == Some(cargo_toml) => {
+                lockfile.clone()
+            }
+            _ => cargo_toml.parent().unwrap().join("Cargo.lock"),
+        }
+    }
+}
+
+/// The C library to build against, selecting the nixpkgs package set that is used by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Libc {
+    /// glibc as used by the default package set.
+    #[default]
+    Glibc,
+    /// musl with static linking, i.e. `pkgsStatic`.
+    Musl,
+    /// musl with dynamic linking, i.e. `pkgsMusl`.
+    MuslDynamic,
+}
+
+impl std::str::FromStr for Libc {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "glibc" => Ok(Libc::Glibc),
+            "musl" => Ok(Libc::Musl),
+            "musl-dynamic" => Ok(Libc::MuslDynamic),
+            _ => Err(format_err!(
+                "unknown libc '{}', expected 'glibc', 'musl' or 'musl-dynamic'",
+                s
+            )),
+        }
+    }
+}
+
+#[test]
+fn test_registry_sources_are_filled_offline_from_config_and_lock_file() {
+    let dir = tempdir::TempDir::new("crate2nix_offline_registry").unwrap();
+    let index_url = test::write_local_git_registry(dir.path(), &[("private_crate", "1.0.0")]);
+    let project_dir = dir.path().join("project");
+    let metadata = test::local_registry_project(
+        dir.path(),
+        &project_dir,
+        &index_url,
+        &[("private_crate", "1")],
+    );
+    let config = GenerateConfig {
+        cargo_toml: vec![project_dir.join("Cargo.toml")],
+        output: project_dir.join("Cargo.nix"),
+        ..test::generate_config()
+    };
+    let mut build_info = BuildInfo::new(
+        &GenerateInfo::default(),
+        &config,
+        crate::config::Config::default(),
+        IndexedMetadata::new_from(metadata).unwrap(),
+    )
+    .unwrap();
+    let lock_file_checksums = extract_hashes_from_lockfile(&config, &mut build_info).unwrap();
+
+    // Any attempt to read the index fails from here on.
+    std::fs::remove_dir_all(dir.path().join("index")).unwrap();
+    let fill = |build_info: &mut BuildInfo, checksums: &HashMap<PackageId, String>| {
+        fill_registry_sources(
+            build_info,
+            &mut registry::IndexReader::default(),
+            checksums,
+            true,
+        )
+    };
+
+    let error = fill(&mut build_info, &lock_file_checksums).unwrap_err();
+    assert!(
+        error.to_string().contains("\"registryConfigs\""),
+        "{}",
+        error
+    );
+
+    let dl = format!(
+        "{}{{crate}}-{{version}}.crate",
+        index_url.join("../dl/").unwrap()
+    );
+    build_info.crate2nix_json.registry_configs.insert(
+        format!("registry+{}", index_url),
+        registry::IndexConfig {
+            dl: dl.clone(),
+            api: None,
+        },
+    );
+    let error = fill(&mut build_info, &HashMap::new()).unwrap_err();
+    assert!(error.to_string().contains("checksum"), "{}", error);
+
+    fill(&mut build_info, &lock_file_checksums).unwrap();
+    let private_crate = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "private_crate")
+        .unwrap();
+    let checksum = lock_file_checksums.get(&private_crate.package_id).unwrap();
+    match &private_crate.source {
+        ResolvedSource::Registry(source) => {
+            assert_eq!(
+                source.url.as_deref(),
+                Some(
+                    dl.replace("{crate}-{version}", "private_crate-1.0.0")
+                        .as_str()
+                )
+            );
+            assert_eq!(
+                source.sha256.as_deref(),
+                Some(nix_base32::to_nix_base32(&hex::decode(checksum).unwrap()).as_str())
+            );
+        }
+        source => panic!("unexpected source {:?}", source),
+    }
+
+    dir.close().unwrap();
 }
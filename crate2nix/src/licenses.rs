@@ -0,0 +1,319 @@
+//! Collecting the license files of all crates, e.g. for compliance bundles.
+//!
+//! The license files are looked up in the crate sources that `cargo metadata`
+//! has already fetched.
+//!
+//! The SPDX license expressions can also be mapped to the licenses of nixpkgs for `meta.license`.
+
+use anyhow::{format_err, Error};
+use cargo_metadata::Package;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::warnings::{warn, WarningKind};
+
+/// The license information of one crate.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CrateLicense {
+    /// The crate name.
+    pub name: String,
+    /// The crate version.
+    pub version: String,
+    /// The SPDX license expression from the manifest.
+    pub license: Option<String>,
+    /// The license files found in the crate source.
+    pub license_files: Vec<PathBuf>,
+}
+
+impl CrateLicense {
+    /// Returns the license information of the given package.
+    ///
+    /// Files starting with `LICENSE`, `LICENCE`, `COPYING`, `NOTICE` or `UNLICENSE`
+    /// in the crate directory are used as well as the `license-file` of the manifest.
+    pub fn for_package(package: &Package) -> CrateLicense {
+        let crate_dir = package
+            .manifest_path
+            .parent()
+            .map(|p| PathBuf::from(p.as_str()))
+            .unwrap_or_default();
+        let mut license_files: Vec<PathBuf> = std::fs::read_dir(&crate_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+                    .map(|entry| entry.path())
+                    .filter(|path| is_license_file_name(path))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(license_file) = &package.license_file {
+            let license_file = crate_dir.join(license_file.as_str());
+            if license_file.is_file() && !license_files.contains(&license_file) {
+                license_files.push(license_file);
+            }
+        }
+        license_files.sort();
+
+        CrateLicense {
+            name: package.name.clone(),
+            version: package.version.to_string(),
+            license: package.license.clone(),
+            license_files,
+        }
+    }
+}
+
+fn is_license_file_name(path: &Path) -> bool {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+    ["LICENSE", "LICENCE", "COPYING", "NOTICE", "UNLICENSE"]
+        .iter()
+        .any(|prefix| file_name.starts_with(prefix))
+}
+
+/// The attribute names in `lib.licenses` of nixpkgs by SPDX identifier.
+///
+/// The deprecated SPDX identifiers map to the current names since the nixpkgs
+/// aliases `gpl2`, `gpl3`, `lgpl21` and `lgpl3` are deprecated as well.
+const NIXPKGS_LICENSES: &[(&str, &str)] = &[
+    ("0BSD", "bsd0"),
+    ("AGPL-3.0", "agpl3Only"),
+    ("AGPL-3.0-only", "agpl3Only"),
+    ("AGPL-3.0-or-later", "agpl3Plus"),
+    ("Apache-2.0", "asl20"),
+    ("Artistic-2.0", "artistic2"),
+    ("BSD-1-Clause", "bsd1"),
+    ("BSD-2-Clause", "bsd2"),
+    ("BSD-3-Clause", "bsd3"),
+    ("BSL-1.0", "boost"),
+    ("CC-BY-4.0", "cc-by-40"),
+    ("CC0-1.0", "cc0"),
+    ("GPL-2.0", "gpl2Only"),
+    ("GPL-2.0+", "gpl2Plus"),
+    ("GPL-2.0-only", "gpl2Only"),
+    ("GPL-2.0-or-later", "gpl2Plus"),
+    ("GPL-3.0", "gpl3Only"),
+    ("GPL-3.0+", "gpl3Plus"),
+    ("GPL-3.0-only", "gpl3Only"),
+    ("GPL-3.0-or-later", "gpl3Plus"),
+    ("ISC", "isc"),
+    ("LGPL-2.1", "lgpl21Only"),
+    ("LGPL-2.1+", "lgpl21Plus"),
+    ("LGPL-2.1-only", "lgpl21Only"),
+    ("LGPL-2.1-or-later", "lgpl21Plus"),
+    ("LGPL-3.0", "lgpl3Only"),
+    ("LGPL-3.0+", "lgpl3Plus"),
+    ("LGPL-3.0-only", "lgpl3Only"),
+    ("LGPL-3.0-or-later", "lgpl3Plus"),
+    ("MIT", "mit"),
+    ("MIT-0", "mit0"),
+    ("MPL-2.0", "mpl20"),
+    ("OpenSSL", "openssl"),
+    ("Unicode-3.0", "unicode-30"),
+    ("Unicode-DFS-2016", "unicode-dfs-2016"),
+    ("Unlicense", "unlicense"),
+    ("WTFPL", "wtfpl"),
+    ("Zlib", "zlib"),
+];
+
+/// The licenses of `NIXPKGS_LICENSES` that older nixpkgs releases do not have yet.
+const OPTIONAL_NIXPKGS_LICENSES: &[&str] = &["mit0", "unicode-30", "unicode-dfs-2016"];
+
+/// Returns the nix expressions for the licenses in the given SPDX license expression.
+///
+/// `OR`, `AND` and the legacy `/` are all treated as a list of licenses while the
+/// exceptions after `WITH` are dropped. Licenses that are not known are returned as
+/// `{ spdxId = "..."; }`, which is also the fallback for licenses that the nixpkgs
+/// release might not have.
+pub fn nix_licenses(spdx: &str) -> Vec<String> {
+    let tokens = spdx.replace(['(', ')', '/'], " ");
+    let mut tokens = tokens.split_whitespace();
+    let mut licenses = Vec::new();
+    while let Some(token) = tokens.next() {
+        let license = match token {
+            "OR" | "AND" => continue,
+            "WITH" => {
+                tokens.next();
+                continue;
+            }
+            id => {
+                let unknown = format!("{{ spdxId = {}; }}", crate::render::escape_nix_string(id));
+                match NIXPKGS_LICENSES.iter().find(|(spdx_id, _)| *spdx_id == id) {
+                    Some((_, name)) => {
+                        let attr = if name.contains('-') {
+                            format!("\"{}\"", name)
+                        } else {
+                            name.to_string()
+                        };
+                        if OPTIONAL_NIXPKGS_LICENSES.contains(name) {
+                            format!(
+                                "(if lib.licenses ? {} then lib.licenses.{} else {})",
+                                attr, attr, unknown
+                            )
+                        } else {
+                            format!("lib.licenses.{}", attr)
+                        }
+                    }
+                    None => unknown,
+                }
+            }
+        };
+        if !licenses.contains(&license) {
+            licenses.push(license);
+        }
+    }
+    licenses
+}
+
+/// Returns the license information of all crates of the build, i.e. the projects and
+/// the crates they depend on.
+///
+/// Reports a warning for every crate without license file.
+pub fn collect(build_info: &crate::BuildInfo) -> Vec<CrateLicense> {
+    let mut licenses: Vec<CrateLicense> = build_info
+        .crates
+        .iter()
+        .filter_map(|c| build_info.indexed_metadata.pkgs_by_id.get(&c.package_id))
+        .map(CrateLicense::for_package)
+        .collect();
+    licenses.sort();
+    licenses.dedup();
+
+    for license in licenses.iter().filter(|l| l.license_files.is_empty()) {
+        warn(
+            WarningKind::MissingLicenseFile,
+            match &license.license {
+                Some(spdx) => format!(
+                    "{} {} has no license file, only the SPDX expression '{}'",
+                    license.name, license.version, spdx
+                ),
+                None => format!(
+                    "{} {} has neither a license file nor a license",
+                    license.name, license.version
+                ),
+            },
+        );
+    }
+
+    licenses
+}
+
+/// Copies the license files into `dir/<name>-<version>/` and writes a `licenses.json`
+/// manifest with the paths relative to `dir`.
+pub fn write_bundle(licenses: &[CrateLicense], dir: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format_err!("while creating {}: {}", dir.to_string_lossy(), e))?;
+    let mut manifest = Vec::new();
+    for license in licenses {
+        let crate_dir = PathBuf::from(format!("{}-{}", license.name, license.version));
+        let mut bundled_files = Vec::new();
+        if !license.license_files.is_empty() {
+            std::fs::create_dir_all(dir.join(&crate_dir))?;
+        }
+        for license_file in &license.license_files {
+            let file_name = license_file
+                .file_name()
+                .ok_or_else(|| format_err!("no file name in {}", license_file.to_string_lossy()))?;
+            let bundled_file = crate_dir.join(file_name);
+            std::fs::copy(license_file, dir.join(&bundled_file)).map_err(|e| {
+                format_err!("while copying {}: {}", license_file.to_string_lossy(), e)
+            })?;
+            bundled_files.push(bundled_file);
+        }
+        manifest.push(CrateLicense {
+            license_files: bundled_files,
+            ..license.clone()
+        });
+    }
+
+    crate::util::write_atomically(dir.join("licenses.json"), |file| {
+        Ok(serde_json::to_writer_pretty(file, &manifest)?)
+    })
+}
+
+#[test]
+fn test_nix_licenses() {
+    assert_eq!(
+        nix_licenses("MIT OR Apache-2.0"),
+        vec!["lib.licenses.mit", "lib.licenses.asl20"]
+    );
+    assert_eq!(
+        nix_licenses("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+        vec![
+            "lib.licenses.mit",
+            "lib.licenses.asl20",
+            "(if lib.licenses ? \"unicode-dfs-2016\" then lib.licenses.\"unicode-dfs-2016\" \
+             else { spdxId = \"Unicode-DFS-2016\"; })"
+        ]
+    );
+    assert_eq!(
+        nix_licenses("MIT/Apache-2.0 OR MIT"),
+        vec!["lib.licenses.mit", "lib.licenses.asl20"]
+    );
+    assert_eq!(
+        nix_licenses("Apache-2.0 WITH LLVM-exception OR Custom-1.0"),
+        vec!["lib.licenses.asl20", "{ spdxId = \"Custom-1.0\"; }"]
+    );
+    assert_eq!(
+        nix_licenses("GPL-2.0 OR GPL-3.0+ OR LGPL-2.1-or-later"),
+        vec![
+            "lib.licenses.gpl2Only",
+            "lib.licenses.gpl3Plus",
+            "lib.licenses.lgpl21Plus"
+        ]
+    );
+    assert_eq!(
+        nix_licenses("MIT-0"),
+        vec!["(if lib.licenses ? mit0 then lib.licenses.mit0 else { spdxId = \"MIT-0\"; })"]
+    );
+}
+
+#[test]
+fn test_license_files_are_discovered() {
+    let mut package = crate::test::package("some_crate", "1.0.0");
+    let crate_dir = PathBuf::from(package.manifest_path.parent().unwrap().as_str());
+    for file in &["LICENSE-MIT", "LICENSE-APACHE", "README.md", "legal.txt"] {
+        std::fs::write(crate_dir.join(file), "text").unwrap();
+    }
+    std::fs::create_dir(crate_dir.join("licenses")).unwrap();
+    package.license = Some("MIT OR Apache-2.0".to_string());
+    package.license_file = Some("legal.txt".into());
+
+    let license = CrateLicense::for_package(&package);
+    assert_eq!(license.license.as_deref(), Some("MIT OR Apache-2.0"));
+    assert_eq!(
+        license.license_files,
+        vec![
+            crate_dir.join("LICENSE-APACHE"),
+            crate_dir.join("LICENSE-MIT"),
+            crate_dir.join("legal.txt"),
+        ]
+    );
+
+    package.close().unwrap();
+}
+
+#[test]
+fn test_only_the_crates_of_the_build_are_collected() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("used")
+        .update_package(|p| p.license = Some("MIT".to_string()));
+    env.add_package_and_node("unused");
+
+    let mut build_info = env.build_info(crate::config::Config::default());
+    build_info.prune_unneeded_crates();
+    let licenses = collect(&build_info);
+    assert_eq!(
+        licenses
+            .iter()
+            .map(|l| (l.name.as_str(), l.license.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![("main", None), ("used", Some("MIT"))]
+    );
+
+    env.close();
+}
@@ -5,8 +5,12 @@ use structopt::StructOpt;
 use anyhow::format_err;
 use anyhow::{bail, Error};
 use crate2nix::{
+    check::{CheckResult, DiffLine},
     config::{Config, NixFile},
+    registry::YankedPolicy,
     render,
+    rust_platform::Backend,
+    warnings::{DenyWarnings, WarningKind},
 };
 use semver::Version;
 use serde::Deserialize;
@@ -89,6 +93,109 @@ pub enum Opt {
         )]
         nixpkgs_path: String,
 
+        #[structopt(
+            long = "build-rust-crate-path",
+            help = "A nix expression for the path of the buildRustCrate to use instead of \
+                    pkgs.buildRustCrate, e.g. './nix/build-rust-crate'. \
+                    It is called with pkgs.callPackage."
+        )]
+        build_rust_crate_path: Option<String>,
+
+        #[structopt(
+            long = "nixpkgs-compat",
+            help = "The nixpkgs release to generate for, e.g. '19.09'. \
+                    Attributes that its buildRustCrate does not support are omitted. \
+                    By default, the output targets recent nixpkgs versions."
+        )]
+        nixpkgs_compat: Option<crate2nix::nixpkgs_compat::NixpkgsRelease>,
+
+        #[structopt(
+            long = "libc",
+            help = "The C library to build against: 'glibc', 'musl' (statically linked, pkgsStatic) \
+                    or 'musl-dynamic' (pkgsMusl). This selects the default nixpkgs package set.",
+            default_value = "glibc"
+        )]
+        libc: crate2nix::Libc,
+
+        #[structopt(
+            long = "artifact-dependencies",
+            help = "Support artifact dependencies, e.g. `artifact = \"bin\"`. \
+                    Their binaries are passed to build scripts as CARGO_BIN_FILE_* variables. \
+                    Needs a nightly cargo since `cargo metadata` is called with `-Z bindeps`."
+        )]
+        artifact_dependencies: bool,
+
+        #[structopt(
+            long = "all-attribute",
+            help = "Emit a top-level `all` attribute that joins the outputs of the default \
+                    workspace members, i.e. the `default-members` of the workspace or all members."
+        )]
+        all_attribute: bool,
+
+        #[structopt(
+            long = "meta-license",
+            help = "Emit `meta.license` for every crate with a license as a list of nixpkgs \
+                    licenses, e.g. `[ lib.licenses.mit lib.licenses.asl20 ]` for `MIT OR Apache-2.0`. \
+                    Unknown licenses are emitted as `{ spdxId = ...; }`."
+        )]
+        meta_license: bool,
+
+        #[structopt(
+            long = "meta-source-provenance",
+            help = "Emit `meta.sourceProvenance` for every crate, `[ lib.sourceTypes.fromSource ]` \
+                    unless `sourceProvenance` in crate2nix.json marks the crate otherwise, \
+                    e.g. as `binaryNativeCode` for crates that bundle prebuilt binaries."
+        )]
+        meta_source_provenance: bool,
+
+        #[structopt(
+            long = "meta-skip-unpublished",
+            help = "Omit `meta.license` and `meta.sourceProvenance` for crates that declare \
+                    `publish = false` and for path dependencies outside of the workspace."
+        )]
+        meta_skip_unpublished: bool,
+
+        #[structopt(
+            long = "metadata-retries",
+            help = "How often to retry `cargo metadata` on transient failures, e.g. network errors.",
+            default_value = "2"
+        )]
+        metadata_retries: u32,
+
+        #[structopt(
+            long = "metadata-cache",
+            parse(from_os_str),
+            help = "Cache the `cargo metadata` output in this directory. \
+                    Entries are invalidated when a manifest or the Cargo.lock changes."
+        )]
+        metadata_cache: Option<PathBuf>,
+
+        #[structopt(
+            long = "offline",
+            help = "Generate without network access and without running `cargo metadata`. \
+                    The metadata is read from the metadata cache, which has to be up to date, \
+                    and all crate hashes have to be known already. \
+                    crate2nix.json and Cargo.lock alone are not enough: the lock file lacks \
+                    the features, targets and dependency kinds of the crates."
+        )]
+        offline: bool,
+
+        #[structopt(
+            long = "lockfile",
+            parse(from_os_str),
+            help = "The Cargo.lock to resolve against instead of the one next to the Cargo.toml. \
+                    It is used as it is, i.e. with `cargo metadata --locked`."
+        )]
+        lockfile: Option<PathBuf>,
+
+        #[structopt(
+            long = "no-isolated-metadata",
+            help = "Call `cargo metadata` without --locked and with the default target directory. \
+                    By default, a temporary CARGO_TARGET_DIR is used and the Cargo.lock must be \
+                    up to date so that the workspace is not modified."
+        )]
+        no_isolated_metadata: bool,
+
         #[structopt(
             short = "h",
             long = "crate-hashes",
@@ -111,8 +218,198 @@ pub enum Opt {
                     If there are any prefetches, their hashes will still be written into crate-hashes.json."
         )]
         dont_read_crate_hashes: bool,
+
+        #[structopt(
+            long = "no-symlink",
+            help = "Copy the fetched out-of-tree sources into the crate2nix-sources directory \
+                    instead of symlinking it into the nix store."
+        )]
+        no_symlink: bool,
+
+        #[structopt(
+            long = "prefetch-only",
+            help = "Only resolve and prefetch the crates, updating the crate hash cache file, \
+                    and exit without writing the output. \
+                    Useful to warm the cache in a separate step that has network access."
+        )]
+        prefetch_only: bool,
+
+        #[structopt(
+            long = "check-only",
+            help = "Only check that the output is up to date, without network access \
+                    and without writing anything. Fails and prints the differing lines if it is not."
+        )]
+        check_only: bool,
+
+        #[structopt(
+            long = "print-effective-config",
+            help = "Print the settings that would be used after merging the command line flags, \
+                    crate2nix.json and the cargo profiles as JSON and exit without generating. \
+                    Neither nix nor cargo is run, so the manifests of out-of-tree sources \
+                    are not listed."
+        )]
+        print_effective_config: bool,
+
+        #[structopt(
+            long = "validate-eval",
+            help = "Check that the output evaluates with `nix eval` after writing it. \
+                    Much faster than building it."
+        )]
+        validate_eval: bool,
+
+        #[structopt(
+            long = "verify-build",
+            help = "Build all workspace members of the output with `nix build` after writing it."
+        )]
+        verify_build: bool,
+
+        #[structopt(
+            long = "verify-jobs",
+            help = "The maximal number of nix processes that --validate-eval and --verify-build \
+                    run at the same time.",
+            default_value = "1"
+        )]
+        verify_jobs: usize,
+
+        #[structopt(
+            long = "nix-version",
+            help = "The version of nix to construct `nix` command lines for, e.g. '2.3'. \
+                    Detected with `nix --version` by default."
+        )]
+        nix_version: Option<crate2nix::nix_build::NixVersion>,
+
+        #[structopt(
+            long = "show-trace",
+            overrides_with = "no-show-trace",
+            help = "Pass --show-trace to nix invocations. \
+                    This is the default unless `showTrace` is false in crate2nix.json."
+        )]
+        show_trace: bool,
+
+        #[structopt(
+            long = "no-show-trace",
+            overrides_with = "show-trace",
+            help = "Do not pass --show-trace to nix invocations for shorter error output."
+        )]
+        no_show_trace: bool,
+
+        #[structopt(
+            long = "fail-fast",
+            overrides_with = "no-fail-fast",
+            help = "Stop --validate-eval and --verify-build at the first failure. This is the default."
+        )]
+        fail_fast: bool,
+
+        #[structopt(
+            long = "no-fail-fast",
+            overrides_with = "fail-fast",
+            help = "Continue --validate-eval and --verify-build after a failure \
+                    and report all failures at the end."
+        )]
+        no_fail_fast: bool,
+
+        #[structopt(
+            long = "backend",
+            help = "The kind of build to generate: 'buildrustcrate' generates one derivation \
+                    per crate, 'rustplatform' a single rustPlatform.buildRustPackage \
+                    derivation which vendors all dependencies with one cargoHash.",
+            default_value = "buildrustcrate"
+        )]
+        backend: Backend,
+
+        #[structopt(
+            long = "cargo-hash",
+            help = "The cargoHash for the 'rustplatform' backend. \
+                    Prefetched with nix-build if not given."
+        )]
+        cargo_hash: Option<String>,
+
+        #[structopt(
+            long = "fail-on-yanked",
+            conflicts_with = "include-yanked",
+            help = "Exit with an error if any yanked crate versions are used. \
+                    By default, a warning is reported for every yanked crate version, \
+                    unless generating --offline."
+        )]
+        fail_on_yanked: bool,
+
+        #[structopt(
+            long = "include-yanked",
+            help = "Allow yanked crate versions silently without checking the registry index."
+        )]
+        include_yanked: bool,
+
+        #[structopt(
+            long = "deny-warnings",
+            help = "Exit with an error if any warnings were reported. \
+                    The output is still generated and all warnings are reported."
+        )]
+        deny_warnings: bool,
+
+        #[structopt(
+            long = "deny",
+            help = "Exit with an error if warnings of this kind were reported, \
+                    e.g. 'missing-cargo-lock'. Can be specified multiple times."
+        )]
+        deny: Vec<WarningKind>,
+
+        #[structopt(
+            long = "stats-file",
+            parse(from_os_str),
+            help = "Write timings of the metadata, resolve, prefetch and render phases \
+                    and the numbers of prefetched and cached sources as JSON to this file."
+        )]
+        stats_file: Option<PathBuf>,
     },
 
+    #[structopt(
+        name = "licenses",
+        about = "Lists the licenses and license files of all crates, \
+                 or bundles the license files into a directory."
+    )]
+    Licenses {
+        #[structopt(
+            short = "f",
+            long = "cargo-toml",
+            parse(from_os_str),
+            help = "The path to the Cargo.toml of the project.",
+            default_value = "./Cargo.toml"
+        )]
+        cargo_toml: Vec<PathBuf>,
+
+        #[structopt(
+            long = "bundle",
+            parse(from_os_str),
+            help = "Copy the license files into this directory, one subdirectory per crate, \
+                    together with a licenses.json manifest. \
+                    By default, the manifest is printed to stdout."
+        )]
+        bundle: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "pin-git",
+        about = "Pins a git branch to its current revision and prints the source \
+                 for the sources of crate2nix.json."
+    )]
+    PinGit {
+        /// The URL of the git repository.
+        ///
+        /// E.g. https://github.com/kolloch/crate2nix.git
+        #[serde(with = "url_serde")]
+        url: url::Url,
+
+        #[structopt(long = "branch", help = "The branch to pin.")]
+        branch: String,
+    },
+
+    #[structopt(
+        name = "sources",
+        about = "Prints the sources of all crates that are downloaded from registries, \
+                 the same as `crate2nix source registry`."
+    )]
+    Sources(RegistrySources),
+
     #[structopt(name = "source", about = "Manage out of tree sources for crate2nix.")]
     Source {
         #[structopt(
@@ -128,6 +425,50 @@ pub enum Opt {
         command: SourceCommands,
     },
 
+    #[structopt(
+        name = "regenerate-sources-nix",
+        about = "Rewrites crate2nix-sources.nix next to crate2nix.json from the current template, \
+                 e.g. after upgrading crate2nix. Refuses to overwrite a file without the \
+                 @generated marker."
+    )]
+    RegenerateSourcesNix {
+        #[structopt(
+            short = "c",
+            long = "config",
+            parse(from_os_str),
+            help = "The path to the crate2nix.json file (same directory as Cargo.nix ...).",
+            default_value = "./crate2nix.json"
+        )]
+        crate2nix_json: PathBuf,
+    },
+
+    #[structopt(
+        name = "update",
+        about = "Updates a single crate in Cargo.lock with `cargo update -p` and regenerates the \
+                 build file with the arguments recorded in its header. \
+                 Only the sources of changed crates are prefetched."
+    )]
+    Update {
+        #[structopt(help = "The name of the crate to update.")]
+        crate_name: String,
+
+        #[structopt(
+            long = "precise",
+            help = "Update the crate to exactly this version, passed to `cargo update --precise`."
+        )]
+        precise: Option<String>,
+
+        #[structopt(
+            short = "o",
+            long = "output",
+            parse(from_os_str),
+            help = "The build file to regenerate. \
+                    Run from the directory that it was generated in.",
+            default_value = "./Cargo.nix"
+        )]
+        output: PathBuf,
+    },
+
     #[structopt(
         name = "completions",
         about = "Generates auto-completions for the shell."
@@ -173,17 +514,118 @@ pub enum SourceCommands {
         about = "Fetch all sources with nix.\n\
                  This is usually called automatically and mostly useful for testing."
     )]
-    Fetch,
+    Fetch {
+        #[structopt(
+            long = "no-symlink",
+            help = "Copy the sources into the crate2nix-sources directory \
+                    instead of symlinking it into the nix store."
+        )]
+        no_symlink: bool,
+
+        #[structopt(
+            long = "nix-version",
+            help = "The version of nix to construct `nix` command lines for, e.g. '2.3'. \
+                    Detected with `nix --version` by default."
+        )]
+        nix_version: Option<crate2nix::nix_build::NixVersion>,
+
+        #[structopt(
+            long = "show-trace",
+            overrides_with = "no-show-trace",
+            help = "Pass --show-trace to nix invocations. \
+                    This is the default unless `showTrace` is false in crate2nix.json."
+        )]
+        show_trace: bool,
+
+        #[structopt(
+            long = "no-show-trace",
+            overrides_with = "show-trace",
+            help = "Do not pass --show-trace to nix invocations for shorter error output."
+        )]
+        no_show_trace: bool,
+    },
     #[structopt(
         name = "generate",
         about = "Generate crate2nix-sources.nix.\n\
                  This is usually called automatically and mostly useful for testing."
     )]
     Generate,
+
+    #[structopt(
+        name = "registry",
+        about = "Prints the sources of all crates that are downloaded from registries \
+                 without generating a build, e.g. for a custom build on top of them."
+    )]
+    Registry(RegistrySources),
+}
+
+/// The options of `crate2nix source registry` and its alias `crate2nix sources`.
+#[derive(Debug, StructOpt, Deserialize, Serialize)]
+pub struct RegistrySources {
+    #[structopt(
+        short = "f",
+        long = "cargo-toml",
+        parse(from_os_str),
+        help = "The path to the Cargo.toml of the project.",
+        default_value = "./Cargo.toml"
+    )]
+    cargo_toml: PathBuf,
+
+    #[structopt(
+        short = "h",
+        long = "crate-hashes",
+        parse(from_os_str),
+        help = "The path to the crate hash cache file.",
+        default_value = "./crate-hashes.json"
+    )]
+    crate_hashes: PathBuf,
+
+    #[structopt(
+        short = "o",
+        long = "output",
+        parse(from_os_str),
+        help = "The path of the output file. Printed to stdout by default."
+    )]
+    output: Option<PathBuf>,
+
+    #[structopt(
+        long = "format",
+        help = "The output format: 'nix-list' prints a nix list of \
+                { name, version, url, sha256 } attribute sets for fetchurl.",
+        default_value = "nix-list"
+    )]
+    format: SourcesFormat,
+}
+
+impl RegistrySources {
+    pub fn execute(self, generate_info: crate2nix::GenerateInfo) -> Result<(), Error> {
+        let RegistrySources {
+            cargo_toml,
+            crate_hashes,
+            output,
+            format: SourcesFormat::NixList,
+        } = self;
+        let generate_config =
+            crate2nix::GenerateConfig::new(vec![cargo_toml], DEFAULT_OUTPUT.into(), crate_hashes);
+        let build_info = crate2nix::BuildInfo::for_config(&generate_info, &generate_config)?;
+        let sources = crate2nix::FetchurlSources {
+            info: generate_info,
+            sources: build_info.fetchurl_sources()?,
+        };
+        match output {
+            Some(output) => render::FETCHURL_SOURCES_NIX.write_to_file(&output, &sources)?,
+            None => print!("{}", render::FETCHURL_SOURCES_NIX.render(&sources)?),
+        }
+        Ok(())
+    }
 }
 
 impl SourceCommands {
-    pub fn execute(self, crate2nix_json: &Path) -> Result<(), Error> {
+    pub fn execute(
+        self,
+        crate2nix_json: &Path,
+        generate_info: crate2nix::GenerateInfo,
+    ) -> Result<(), Error> {
         match self {
             SourceCommands::Add { command, .. } => command.execute(crate2nix_json),
             SourceCommands::List => {
@@ -210,8 +652,20 @@ impl SourceCommands {
                 }
                 Ok(())
             }
-            SourceCommands::Fetch => {
-                let sources = crate2nix::sources::FetchedSources::new(crate2nix_json);
+            SourceCommands::Fetch {
+                no_symlink,
+                nix_version,
+                show_trace,
+                no_show_trace,
+            } => {
+                let config = Config::read_from_or_default(crate2nix_json)?;
+                let nix = nix_version
+                    .map(crate2nix::nix_build::Nix::new)
+                    .unwrap_or_else(crate2nix::nix_build::Nix::detect)
+                    .show_trace(config.show_trace_enabled(show_trace, no_show_trace));
+                let sources = crate2nix::sources::FetchedSources::new(crate2nix_json)
+                    .copied(no_symlink)
+                    .nix(nix);
                 let output = sources.fetch()?;
                 println!("Fetched sources into {}", output.to_string_lossy());
                 Ok(())
@@ -220,6 +674,7 @@ impl SourceCommands {
                 let sources = crate2nix::sources::FetchedSources::new(crate2nix_json);
                 sources.regenerate_sources_nix()
             }
+            SourceCommands::Registry(registry_sources) => registry_sources.execute(generate_info),
         }
     }
 }
@@ -356,14 +811,48 @@ impl SourceAddingCommands {
     }
 }
 
+/// The output formats of `crate2nix source registry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SourcesFormat {
+    /// A nix list of `{ name, version, url, sha256 }` attribute sets.
+    NixList,
+}
+
+impl FromStr for SourcesFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nix-list" => Ok(SourcesFormat::NixList),
+            _ => bail!("unknown format '{}', expected 'nix-list'", s),
+        }
+    }
+}
+
 fn main() -> anyhow::Result<()> {
-    let opt = Opt::from_args();
+    run(Opt::from_args(), crate2nix::GenerateInfo::default())
+}
+
+fn run(opt: Opt, generate_info: crate2nix::GenerateInfo) -> anyhow::Result<()> {
     match opt {
         Opt::Generate {
             crate2nix_json,
             mut cargo_toml,
             output: opt_output,
             nixpkgs_path,
+            build_rust_crate_path,
+            nixpkgs_compat,
+            libc,
+            artifact_dependencies,
+            all_attribute,
+            meta_license,
+            meta_source_provenance,
+            meta_skip_unpublished,
+            metadata_retries,
+            metadata_cache,
+            offline,
+            lockfile,
+            no_isolated_metadata,
             crate_hashes,
             all_features,
             default_features,
@@ -371,23 +860,39 @@ fn main() -> anyhow::Result<()> {
             features,
             no_cargo_lock_checksums,
             dont_read_crate_hashes,
+            no_symlink,
+            prefetch_only,
+            check_only,
+            print_effective_config,
+            validate_eval,
+            verify_build,
+            verify_jobs,
+            nix_version,
+            show_trace,
+            no_show_trace,
+            fail_fast,
+            no_fail_fast,
+            backend,
+            cargo_hash,
+            fail_on_yanked,
+            include_yanked,
+            deny_warnings,
+            deny,
+            stats_file,
         } => {
             let config = crate2nix::config::Config::read_from_or_default(&crate2nix_json)?;
 
-            if !config.sources.is_empty() {
-                let fetched_sources = crate2nix::sources::FetchedSources::new(&crate2nix_json);
-                let cargo_tomls = fetched_sources.get_cargo_tomls()?;
-                cargo_toml.extend(cargo_tomls);
-            }
-
-            if cargo_toml.is_empty() {
+            // The manifests of out-of-tree sources are only known after fetching them.
+            if cargo_toml.is_empty() && config.sources.is_empty() {
                 cargo_toml.push("./Cargo.toml".into());
             }
 
             let output: PathBuf = opt_output
                 .map(|v| Ok(v) as Result<_, Error>)
                 .unwrap_or_else(|| {
-                    crate2nix::render::check_generated_by_crate2nix(DEFAULT_OUTPUT)?;
+                    if !print_effective_config {
+                        crate2nix::render::check_generated_by_crate2nix(DEFAULT_OUTPUT)?;
+                    }
                     Ok(DEFAULT_OUTPUT.into())
                 })?;
 
@@ -398,8 +903,6 @@ fn main() -> anyhow::Result<()> {
                     .join("crate-hashes.json")
             });
 
-            let generate_info = crate2nix::GenerateInfo::default();
-
             let feature_metadata_options = || {
                 let mut options = Vec::new();
 
@@ -439,30 +942,254 @@ fn main() -> anyhow::Result<()> {
                     options.push(features.join(" "));
                 }
 
+                if artifact_dependencies {
+                    options.push("-Zbindeps".to_string());
+                }
+
                 Ok(options)
             };
 
-            let generate_config = crate2nix::GenerateConfig {
-                cargo_toml,
-                output: output.clone(),
+            let mut generate_config = crate2nix::GenerateConfig {
                 nixpkgs_path,
-                crate_hashes_json,
                 other_metadata_options: feature_metadata_options()?,
                 use_cargo_lock_checksums: !no_cargo_lock_checksums,
                 read_crate_hashes: !dont_read_crate_hashes,
+                nixpkgs_compat,
+                yanked_policy: if fail_on_yanked {
+                    YankedPolicy::Fail
+                } else if include_yanked {
+                    YankedPolicy::Include
+                } else {
+                    YankedPolicy::Warn
+                },
+                libc,
+                artifact_dependencies,
+                all_attribute,
+                meta_license,
+                meta_source_provenance,
+                meta_skip_unpublished,
+                metadata_retries,
+                metadata_cache: config.metadata_cache_dir(&crate2nix_json, metadata_cache),
+                lockfile,
+                isolated_metadata: !no_isolated_metadata,
+                build_rust_crate_path,
+                offline,
+                ..crate2nix::GenerateConfig::new(cargo_toml, output.clone(), crate_hashes_json)
             };
-            let build_info = crate2nix::BuildInfo::for_config(&generate_info, &generate_config)?;
-            render::CARGO_NIX.write_to_file(&output, &build_info)?;
+            if print_effective_config {
+                let effective_config = crate2nix::config::EffectiveConfig::new(
+                    &generate_config,
+                    &config,
+                    config.show_trace_enabled(show_trace, no_show_trace),
+                )?;
+                println!("{}", serde_json::to_string_pretty(&effective_config)?);
+                return Ok(());
+            }
+
+            let nix = nix_version
+                .map(crate2nix::nix_build::Nix::new)
+                .unwrap_or_else(crate2nix::nix_build::Nix::detect)
+                .show_trace(config.show_trace_enabled(show_trace, no_show_trace));
+
+            if check_only && backend == Backend::RustPlatform {
+                bail!("--check-only is not supported by the 'rustplatform' backend")
+            }
+            if !config.sources.is_empty() {
+                let fetched_sources = crate2nix::sources::FetchedSources::new(&crate2nix_json)
+                    .copied(no_symlink)
+                    .nix(nix.clone());
+                // Checking must not fetch, so it uses the sources fetched by the last generate.
+                let cargo_tomls = if check_only {
+                    fetched_sources.get_fetched_cargo_tomls()?
+                } else {
+                    fetched_sources.get_cargo_tomls()?
+                };
+                generate_config.cargo_toml.extend(cargo_tomls);
+                if generate_config.cargo_toml.is_empty() {
+                    generate_config.cargo_toml.push("./Cargo.toml".into());
+                }
+            }
+            if check_only {
+                return match crate2nix::check::check_up_to_date(&generate_config)? {
+                    CheckResult::UpToDate => {
+                        eprintln!("{} is up to date.", output.to_string_lossy());
+                        Ok(())
+                    }
+                    CheckResult::Stale { diff } => {
+                        for line in diff {
+                            match line {
+                                DiffLine::Removed { line, content } => {
+                                    println!("{:>5} - {}", line, content)
+                                }
+                                DiffLine::Added { line, content } => {
+                                    println!("{:>5} + {}", line, content)
+                                }
+                            }
+                        }
+                        bail!(
+                            "{} is not up to date, please run crate2nix generate.",
+                            output.to_string_lossy()
+                        )
+                    }
+                };
+            }
+
+            let verify_build_attrs: Vec<String> = match backend {
+                Backend::BuildRustCrate => {
+                    let mut build_info =
+                        crate2nix::BuildInfo::for_config(&generate_info, &generate_config)?;
+                    if prefetch_only {
+                        eprintln!(
+                            "Prefetched all crates, not writing {}.",
+                            output.to_string_lossy()
+                        );
+                    } else {
+                        let started = std::time::Instant::now();
+                        render::CARGO_NIX.write_to_file(&output, &build_info)?;
+                        build_info.stats.timings.render_ms =
+                            Some(crate2nix::stats::elapsed_ms(started));
+                    }
+                    if let Some(stats_file) = &stats_file {
+                        build_info.stats.write_to(stats_file)?;
+                    }
+                    build_info
+                        .workspace_members
+                        .keys()
+                        .map(|name| format!("workspaceMembers.{}.build", name))
+                        .collect()
+                }
+                Backend::RustPlatform if prefetch_only => {
+                    bail!("--prefetch-only is not supported by the 'rustplatform' backend")
+                }
+                Backend::RustPlatform if stats_file.is_some() => {
+                    bail!("--stats-file is not supported by the 'rustplatform' backend")
+                }
+                Backend::RustPlatform if verify_build => {
+                    bail!("--verify-build is not supported by the 'rustplatform' backend")
+                }
+                Backend::RustPlatform => {
+                    let build = crate2nix::rust_platform::RustPlatformBuild::for_config(
+                        &generate_info,
+                        &generate_config,
+                        &nix,
+                        cargo_hash,
+                    )?;
+                    render::RUST_PLATFORM_NIX.write_to_file(&output, &build)?;
+                    Vec::new()
+                }
+            };
+
+            // Both phases share the bound on concurrent nix processes.
+            let nix_processes = crate2nix::nix_build::NixProcesses::new(verify_jobs);
+            if validate_eval && !prefetch_only {
+                crate2nix::nix_build::validate_eval(
+                    &nix,
+                    &output,
+                    &verify_build_attrs,
+                    fail_fast || !no_fail_fast,
+                    &nix_processes,
+                )?;
+            }
+
+            if verify_build && !prefetch_only {
+                crate2nix::nix_build::verify_build(
+                    &nix,
+                    &output,
+                    &verify_build_attrs,
+                    fail_fast || !no_fail_fast,
+                    &nix_processes,
+                )?;
+            }
+
+            DenyWarnings {
+                all: deny_warnings,
+                kinds: deny.into_iter().collect(),
+            }
+            .check(&crate2nix::warnings::take())?;
+        }
+        Opt::Licenses { cargo_toml, bundle } => {
+            let generate_config = crate2nix::GenerateConfig::new(
+                cargo_toml,
+                DEFAULT_OUTPUT.into(),
+                "./crate-hashes.json".into(),
+            );
+            let build_info =
+                crate2nix::BuildInfo::for_config_without_sources(&generate_info, &generate_config)?;
+            let licenses = crate2nix::licenses::collect(&build_info);
+            match bundle {
+                Some(dir) => {
+                    crate2nix::licenses::write_bundle(&licenses, &dir)?;
+                    eprintln!(
+                        "Bundled the licenses of {} crates into {}.",
+                        licenses.len(),
+                        dir.to_string_lossy()
+                    );
+                }
+                None => println!("{}", serde_json::to_string_pretty(&licenses)?),
+            }
+        }
+        Opt::PinGit { url, branch } => {
+            let source = crate2nix::sources::pinned_git_source(url, &branch)?;
+            let name = source.name().unwrap_or(&branch).to_string();
+            let mut sources = std::collections::BTreeMap::new();
+            sources.insert(name, source);
+            println!("{}", serde_json::to_string_pretty(&sources)?);
         }
         Opt::Completions { shell, output } => {
             let shell = FromStr::from_str(&shell).map_err(|s| format_err!("{}", s))?;
             Opt::clap().gen_completions(env!("CARGO_PKG_NAME"), shell, output);
         }
+        Opt::Sources(registry_sources) => registry_sources.execute(generate_info)?,
         Opt::Source {
             crate2nix_json,
             command,
         } => {
-            command.execute(&crate2nix_json)?;
+            command.execute(&crate2nix_json, generate_info)?;
+        }
+        Opt::Update {
+            crate_name,
+            precise,
+            output,
+        } => {
+            let crate2nix_arguments = crate2nix::update::generate_arguments(&output)?;
+            let generate = Opt::from_iter_safe(
+                std::iter::once(env!("CARGO_PKG_NAME").to_string())
+                    .chain(crate2nix_arguments.iter().cloned()),
+            )
+            .map_err(|e| {
+                format_err!("while parsing the arguments recorded in the header: {}", e)
+            })?;
+            let cargo_toml = match &generate {
+                Opt::Generate {
+                    lockfile: Some(_), ..
+                } => bail!("Updating is not supported with --lockfile."),
+                Opt::Generate { cargo_toml, .. } => cargo_toml
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| "./Cargo.toml".into()),
+                _ => bail!(
+                    "{} was not generated by crate2nix generate.",
+                    output.to_string_lossy()
+                ),
+            };
+            crate2nix::update::cargo_update(&cargo_toml, &crate_name, precise.as_deref())?;
+            run(
+                generate,
+                crate2nix::GenerateInfo {
+                    crate2nix_arguments,
+                    ..generate_info
+                },
+            )?;
+        }
+        Opt::RegenerateSourcesNix { crate2nix_json } => {
+            let sources = crate2nix::sources::FetchedSources::new(&crate2nix_json);
+            sources.regenerate_sources_nix()?;
+            eprintln!(
+                "Regenerated {}.",
+                crate2nix_json
+                    .with_file_name("crate2nix-sources.nix")
+                    .to_string_lossy()
+            );
         }
     }
 
@@ -12,6 +12,8 @@ use itertools::Itertools;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::warnings::{warn, WarningKind};
+
 /// The merged metadata of potentially multiple sources.
 #[derive(Debug)]
 pub struct MergedMetadata {
@@ -38,7 +40,10 @@ impl MergedMetadata {
                 if metadata.workspace_members != vec![root.clone()] {
                     // Usually, cargo metadata also puts the root into workspace_members.
                     // Therefore, I only saw this warning in unit tests.
-                    eprintln!("WARNING: root missing from workspace_members.");
+                    warn(
+                        WarningKind::RootNotWorkspaceMember,
+                        "root missing from workspace_members.",
+                    );
                 }
             }
             workspace_members.extend(metadata.workspace_members);
@@ -57,7 +62,7 @@ impl MergedMetadata {
         }
 
         let root = if workspace_members.len() <= 1 {
-            workspace_members.get(0).cloned()
+            workspace_members.first().cloned()
         } else {
             None
         };
@@ -112,16 +117,16 @@ impl IndexedMetadata {
             .map(|node| {
                 (
                     id_shortener.shorten(&node.id),
-                    id_shortener.shorten_in_node(&node),
+                    id_shortener.shorten_in_node(node),
                 )
             })
             .collect();
 
         Ok(IndexedMetadata {
-            root: root.as_ref().map(|id| id_shortener.shorten(&id)),
+            root: root.as_ref().map(|id| id_shortener.shorten(id)),
             workspace_members: workspace_members
                 .iter()
-                .map(|id| id_shortener.shorten(&id))
+                .map(|id| id_shortener.shorten(id))
                 .collect(),
             pkgs_by_id,
             nodes_by_id,
@@ -132,7 +137,7 @@ impl IndexedMetadata {
     #[cfg(test)]
     pub fn root_package(&self) -> Option<&Package> {
         let root = self.root.as_ref()?;
-        self.pkgs_by_id.get(&root)
+        self.pkgs_by_id.get(root)
     }
 }
 
@@ -173,9 +178,12 @@ impl PackageIdShortener {
             } else if packages.iter().map(|p| &p.version).unique().count() == packages.len() {
                 UniqueComponent::NameVersion
             } else {
-                eprintln!(
-                    "Using same version of crate from different sources: {:#?}",
-                    packages.iter().map(|p| &p.id.repr).collect::<Vec<_>>()
+                warn(
+                    WarningKind::AmbiguousPackageVersion,
+                    format!(
+                        "Using same version of crate from different sources: {:#?}",
+                        packages.iter().map(|p| &p.id.repr).collect::<Vec<_>>()
+                    ),
                 );
                 UniqueComponent::PackageId
             };
@@ -200,16 +208,16 @@ impl PackageIdShortener {
     }
 
     pub fn lengthen_ref<'a>(&'a self, package_id: &'a PackageId) -> &'a PackageId {
-        self.reverse.get(&package_id).unwrap_or(&package_id)
+        self.reverse.get(package_id).unwrap_or(package_id)
     }
 
     pub fn shorten_ref<'a>(&'a self, package_id: &'a PackageId) -> &'a PackageId {
-        self.substitution.get(&package_id).unwrap_or(&package_id)
+        self.substitution.get(package_id).unwrap_or(package_id)
     }
 
     pub fn shorten(&self, package_id: &PackageId) -> PackageId {
         self.substitution
-            .get(&package_id)
+            .get(package_id)
             .cloned()
             .unwrap_or_else(|| package_id.clone())
     }
@@ -0,0 +1,762 @@
+//! Calling `cargo metadata` with retries and an optional cache of its output.
+//!
+//! Cache entries are keyed by the manifest, its lock file and the `cargo metadata`
+//! options. The manifests of all local packages in the cached metadata are checked
+//! as well so that changes to workspace members invalidate the entry.
+//!
+//! The entries do not depend on the location of the project, so that a cache can be
+//! committed and used for generating offline, i.e. without running `cargo metadata`.
+
+use anyhow::{bail, format_err, Error};
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_metadata::{Metadata, MetadataCommand, PackageId};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::GenerateConfig;
+
+/// A cached `cargo metadata` output.
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedMetadata {
+    /// The directory of the manifest when the entry was written. The paths in the entry
+    /// are moved to the current directory of the manifest when it is read.
+    manifest_dir: PathBuf,
+    /// The SHA-256 of the manifests of all local packages.
+    local_manifests: BTreeMap<PathBuf, String>,
+    metadata: Metadata,
+}
+
+impl CachedMetadata {
+    /// Moves the paths below `manifest_dir` in the entry to the same paths below `to`.
+    fn relocate(&mut self, to: &Path) {
+        let from = std::mem::replace(&mut self.manifest_dir, to.to_path_buf());
+        let relocate_path = |path: &mut Utf8PathBuf| {
+            if let Some(moved) = moved(Path::new(path.as_str()), &from, to) {
+                if let Some(moved) = moved.to_str() {
+                    *path = moved.into();
+                }
+            }
+        };
+        let relocate_id = |id: &mut PackageId| id.repr = relocate_package_id(&id.repr, &from, to);
+
+        let metadata = &mut self.metadata;
+        relocate_path(&mut metadata.workspace_root);
+        relocate_path(&mut metadata.target_directory);
+        metadata.workspace_members.iter_mut().for_each(relocate_id);
+        for package in &mut metadata.packages {
+            relocate_id(&mut package.id);
+            relocate_path(&mut package.manifest_path);
+            package.license_file.iter_mut().for_each(relocate_path);
+            package.readme.iter_mut().for_each(relocate_path);
+            for target in &mut package.targets {
+                relocate_path(&mut target.src_path);
+            }
+            for dependency in &mut package.dependencies {
+                dependency.path.iter_mut().for_each(relocate_path);
+            }
+        }
+        if let Some(resolve) = &mut metadata.resolve {
+            resolve.root.iter_mut().for_each(relocate_id);
+            for node in &mut resolve.nodes {
+                relocate_id(&mut node.id);
+                node.dependencies.iter_mut().for_each(relocate_id);
+                for dep in &mut node.deps {
+                    relocate_id(&mut dep.pkg);
+                }
+            }
+        }
+
+        self.local_manifests = std::mem::take(&mut self.local_manifests)
+            .into_iter()
+            .map(|(manifest, hash)| {
+                let manifest = moved(&manifest, &from, to).unwrap_or(manifest);
+                (manifest, hash)
+            })
+            .collect();
+    }
+}
+
+/// The key of a cache entry, hashed in this serialization.
+#[derive(Debug, Serialize)]
+struct CacheKey<'a> {
+    /// The manifest path relative to the cache directory.
+    manifest: Option<PathBuf>,
+    manifest_sha256: String,
+    lock_file_sha256: Option<String>,
+    options: &'a [String],
+}
+
+/// Calls `cargo metadata` for the given manifest.
+///
+/// Transient failures are retried `config.metadata_retries` times. If
+/// `config.metadata_cache` is set, the output is read from and stored in that directory.
+/// With `config.isolated_metadata`, the lock file and target directory of the workspace
+/// are left untouched. With `config.offline`, the output is only read from the cache.
+///
+/// An explicit `config.lockfile` is copied in place of the `Cargo.lock` of the workspace
+/// while `cargo metadata` runs, since stable cargo has no option to read another one.
+pub fn cargo_metadata(config: &GenerateConfig, cargo_toml: &Path) -> Result<Metadata, Error> {
+    let lock_file = config.lock_file(cargo_toml);
+    let workspace_lock_file = cargo_toml.with_file_name("Cargo.lock");
+    let swap_lock_file = lock_file != workspace_lock_file;
+    let other_options = metadata_options(config, cargo_toml);
+    let run = || {
+        let _swapped = if swap_lock_file {
+            Some(SwappedLockFile::new(&lock_file, &workspace_lock_file)?)
+        } else {
+            None
+        };
+        with_retries(config.metadata_retries, || {
+            let mut cmd = MetadataCommand::new();
+            cmd.manifest_path(cargo_toml).other_options(&*other_options);
+            if config.isolated_metadata || swap_lock_file {
+                exec_isolated(&cmd)
+            } else {
+                cmd.exec()
+            }
+        })
+        .map_err(|e| {
+            format_err!(
+                "while retrieving metadata about {}: {}",
+                &cargo_toml.to_string_lossy(),
+                e
+            )
+        })
+    };
+
+    match &config.metadata_cache {
+        Some(cache_dir) if config.offline => {
+            cached_only(cache_dir, cargo_toml, &lock_file, &other_options)
+        }
+        Some(cache_dir) => cached(cache_dir, cargo_toml, &lock_file, &other_options, run),
+        None if config.offline => bail!(
+            "Generating offline needs a metadata cache, \
+             see --metadata-cache or metadataCache in crate2nix.json."
+        ),
+        None => run(),
+    }
+}
+
+/// The options to pass to `cargo metadata` for the given manifest.
+fn metadata_options(config: &GenerateConfig, cargo_toml: &Path) -> Vec<String> {
+    let mut options = config.other_metadata_options.clone();
+    // An explicit lock file must be used as it is.
+    if config.isolated_metadata
+        || config.lock_file(cargo_toml) != cargo_toml.with_file_name("Cargo.lock")
+    {
+        options.push("--locked".into());
+    }
+    options
+}
+
+/// A lock file that is copied in place of the `Cargo.lock` of a workspace.
+///
+/// The original `Cargo.lock` is restored, or removed if there was none, when dropped.
+struct SwappedLockFile {
+    workspace_lock_file: PathBuf,
+    original: Option<Vec<u8>>,
+}
+
+impl SwappedLockFile {
+    fn new(lock_file: &Path, workspace_lock_file: &Path) -> Result<SwappedLockFile, Error> {
+        let original = match std::fs::read(workspace_lock_file) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => bail!(
+                "while reading {}: {}",
+                workspace_lock_file.to_string_lossy(),
+                e
+            ),
+        };
+        std::fs::copy(lock_file, workspace_lock_file).map_err(|e| {
+            format_err!(
+                "while copying {} to {}: {}",
+                lock_file.to_string_lossy(),
+                workspace_lock_file.to_string_lossy(),
+                e
+            )
+        })?;
+        Ok(SwappedLockFile {
+            workspace_lock_file: workspace_lock_file.to_path_buf(),
+            original,
+        })
+    }
+}
+
+impl Drop for SwappedLockFile {
+    fn drop(&mut self) {
+        let restored = match &self.original {
+            Some(content) => std::fs::write(&self.workspace_lock_file, content),
+            None => std::fs::remove_file(&self.workspace_lock_file),
+        };
+        if let Err(e) = restored {
+            eprintln!(
+                "Could not restore {}: {}",
+                self.workspace_lock_file.to_string_lossy(),
+                e
+            );
+        }
+    }
+}
+
+/// Runs `cmd` with a temporary `CARGO_TARGET_DIR` so that nothing is written to the
+/// target directory of the workspace.
+fn exec_isolated(cmd: &MetadataCommand) -> cargo_metadata::Result<Metadata> {
+    let target_dir =
+        std::env::temp_dir().join(format!("crate2nix-metadata-{}", std::process::id()));
+    let output = cmd
+        .cargo_command()
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .output();
+    let _ = std::fs::remove_dir_all(&target_dir);
+    let output = output?;
+    if !output.status.success() {
+        return Err(cargo_metadata::Error::CargoMetadata {
+            stderr: String::from_utf8(output.stderr)?,
+        });
+    }
+    let stdout = std::str::from_utf8(&output.stdout)?
+        .lines()
+        .find(|line| line.starts_with('{'))
+        .ok_or(cargo_metadata::Error::NoJson)?;
+    MetadataCommand::parse(stdout)
+}
+
+/// Runs `exec` and retries it up to `retries` times on transient failures.
+fn with_retries(
+    retries: u32,
+    mut exec: impl FnMut() -> cargo_metadata::Result<Metadata>,
+) -> cargo_metadata::Result<Metadata> {
+    let mut attempt = 0;
+    loop {
+        match exec() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                eprintln!(
+                    "cargo metadata failed, retrying ({}/{}): {}",
+                    attempt, retries, e
+                );
+                std::thread::sleep(Duration::from_secs(u64::from(attempt)));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether the error is likely to go away when trying again, e.g. a network error.
+fn is_transient(error: &cargo_metadata::Error) -> bool {
+    match error {
+        cargo_metadata::Error::CargoMetadata { stderr } => [
+            "spurious network error",
+            "failed to download",
+            "failed to fetch",
+            "failed to update",
+            "failed to get",
+            "timed out",
+            "Couldn't resolve host",
+            "failed to acquire",
+        ]
+        .iter()
+        .any(|pattern| stderr.contains(pattern)),
+        cargo_metadata::Error::Io(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns the cached metadata for `cargo_toml` if it is up to date or calls `run`
+/// and caches its result.
+fn cached(
+    cache_dir: &Path,
+    cargo_toml: &Path,
+    lock_file: &Path,
+    options: &[String],
+    run: impl FnOnce() -> Result<Metadata, Error>,
+) -> Result<Metadata, Error> {
+    std::fs::create_dir_all(cache_dir)
+        .map_err(|e| format_err!("while creating {}: {}", cache_dir.to_string_lossy(), e))?;
+    let cache_file = cache_file(cache_dir, cargo_toml, lock_file, options)?;
+    if let Some(metadata) = read_cached(&cache_file, cargo_toml)? {
+        return Ok(metadata);
+    }
+
+    let metadata = run()?;
+    let local_manifests = metadata
+        .packages
+        .iter()
+        .filter(|p| p.source.is_none())
+        .map(|p| {
+            let manifest = PathBuf::from(p.manifest_path.as_str());
+            let hash = content_hash(&manifest)?;
+            Ok((manifest, hash))
+        })
+        .collect::<Result<_, Error>>()?;
+    let cached = CachedMetadata {
+        manifest_dir: manifest_dir(cargo_toml)?,
+        local_manifests,
+        metadata,
+    };
+    crate::util::write_atomically(&cache_file, |file| {
+        Ok(serde_json::to_writer(file, &cached)?)
+    })?;
+    Ok(cached.metadata)
+}
+
+/// Returns the cached metadata for `cargo_toml` and fails if there is none that is up
+/// to date.
+fn cached_only(
+    cache_dir: &Path,
+    cargo_toml: &Path,
+    lock_file: &Path,
+    options: &[String],
+) -> Result<Metadata, Error> {
+    let cache_file = cache_file(cache_dir, cargo_toml, lock_file, options)?;
+    read_cached(&cache_file, cargo_toml)?.ok_or_else(|| {
+        format_err!(
+            "No up to date metadata for {} in the metadata cache {}. \
+             Generate once without --offline to fill it.",
+            cargo_toml.to_string_lossy(),
+            cache_dir.to_string_lossy()
+        )
+    })
+}
+
+/// The cache file for the manifest, its lock file and the `cargo metadata` options.
+fn cache_file(
+    cache_dir: &Path,
+    cargo_toml: &Path,
+    lock_file: &Path,
+    options: &[String],
+) -> Result<PathBuf, Error> {
+    Ok(cache_dir.join(format!(
+        "{}.json",
+        cache_key(cache_dir, cargo_toml, lock_file, options)?
+    )))
+}
+
+/// Reads the cache entry in `cache_file` if it exists and is up to date.
+fn read_cached(cache_file: &Path, cargo_toml: &Path) -> Result<Option<Metadata>, Error> {
+    let content = match std::fs::read_to_string(cache_file) {
+        Ok(content) => content,
+        Err(_) => return Ok(None),
+    };
+    let mut cached = match serde_json::from_str::<CachedMetadata>(&content) {
+        Ok(cached) => cached,
+        Err(_) => return Ok(None),
+    };
+    let current_dir = manifest_dir(cargo_toml)?;
+    if cached.manifest_dir != current_dir {
+        cached.relocate(&current_dir);
+    }
+
+    let up_to_date = cached
+        .local_manifests
+        .iter()
+        .all(|(manifest, hash)| content_hash(manifest).ok().as_ref() == Some(hash));
+    Ok(if up_to_date {
+        Some(cached.metadata)
+    } else {
+        None
+    })
+}
+
+/// Returns `path` below `to` instead of `from`, if it is below `from`.
+fn moved(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    let rest = path.strip_prefix(from).ok()?;
+    Some(if rest.as_os_str().is_empty() {
+        to.to_path_buf()
+    } else {
+        to.join(rest)
+    })
+}
+
+/// Moves the `path+file://` URL of a local package ID from below `from` to below `to`.
+///
+/// Both the `name version (path+file:///dir)` and the `path+file:///dir#name@version`
+/// forms of cargo are supported.
+fn relocate_package_id(repr: &str, from: &Path, to: &Path) -> String {
+    const PREFIX: &str = "path+file://";
+    let start = match repr.find(PREFIX) {
+        Some(index) => index + PREFIX.len(),
+        None => return repr.to_string(),
+    };
+    let end = repr[start..]
+        .find([')', '#'])
+        .map(|index| start + index)
+        .unwrap_or_else(|| repr.len());
+    match moved(Path::new(&repr[start..end]), from, to) {
+        Some(moved) => format!(
+            "{}{}{}",
+            &repr[..start],
+            moved.to_string_lossy(),
+            &repr[end..]
+        ),
+        None => repr.to_string(),
+    }
+}
+
+/// The canonical directory of the manifest.
+fn manifest_dir(cargo_toml: &Path) -> Result<PathBuf, Error> {
+    let cargo_toml = cargo_toml.canonicalize().map_err(|e| {
+        format_err!(
+            "while canonicalizing {}: {}",
+            cargo_toml.to_string_lossy(),
+            e
+        )
+    })?;
+    Ok(cargo_toml
+        .parent()
+        .expect("canonical manifest path has parent")
+        .to_path_buf())
+}
+
+/// The cache key of the manifest, its lock file and the `cargo metadata` options.
+///
+/// The manifest path is only used relative to the cache directory.
+fn cache_key(
+    cache_dir: &Path,
+    cargo_toml: &Path,
+    lock_file: &Path,
+    options: &[String],
+) -> Result<String, Error> {
+    let cargo_toml = manifest_dir(cargo_toml)?.join(
+        cargo_toml
+            .file_name()
+            .ok_or_else(|| format_err!("no file name in {}", cargo_toml.to_string_lossy()))?,
+    );
+    let cache_dir = cache_dir
+        .canonicalize()
+        .unwrap_or_else(|_| cache_dir.to_path_buf());
+
+    let key = CacheKey {
+        manifest: pathdiff::diff_paths(&cargo_toml, &cache_dir),
+        manifest_sha256: content_hash(&cargo_toml)?,
+        lock_file_sha256: content_hash(lock_file).ok(),
+        options,
+    };
+    Ok(crate::util::sha256_hex(&serde_json::to_vec(&key)?))
+}
+
+/// The SHA-256 of the file content.
+fn content_hash(path: &Path) -> Result<String, Error> {
+    let content = std::fs::read(path)
+        .map_err(|e| format_err!("while reading {}: {}", path.to_string_lossy(), e))?;
+    Ok(crate::util::sha256_hex(&content))
+}
+
+#[test]
+fn test_cached_metadata_is_used_for_unchanged_inputs() {
+    let project = tempdir::TempDir::new("crate2nix_metadata_cache").unwrap();
+    let cargo_toml = project.path().join("Cargo.toml");
+    std::fs::write(&cargo_toml, "[package]\nname = \"cached\"\n").unwrap();
+    std::fs::write(project.path().join("Cargo.lock"), "version = 3\n").unwrap();
+    let cache_dir = project.path().join("cache");
+    let lock_file = project.path().join("Cargo.lock");
+
+    let mut runs = 0;
+    let mut cached_metadata = || {
+        cached(
+            &cache_dir,
+            &cargo_toml,
+            &lock_file,
+            &["--locked".to_string()],
+            || {
+                runs += 1;
+                Ok(crate::test::empty_metadata())
+            },
+        )
+        .unwrap()
+    };
+    cached_metadata();
+    cached_metadata();
+    assert_eq!(runs, 1);
+
+    std::fs::write(project.path().join("Cargo.lock"), "version = 4\n").unwrap();
+    cached(
+        &cache_dir,
+        &cargo_toml,
+        &lock_file,
+        &["--locked".to_string()],
+        || {
+            runs += 1;
+            Ok(crate::test::empty_metadata())
+        },
+    )
+    .unwrap();
+    assert_eq!(runs, 2, "changed Cargo.lock did not invalidate the cache");
+
+    project.close().unwrap();
+}
+
+#[test]
+fn test_only_transient_errors_are_retried() {
+    let mut attempts = 0;
+    let result = with_retries(2, || {
+        attempts += 1;
+        Err(cargo_metadata::Error::CargoMetadata {
+            stderr: "the lock file needs to be updated but --locked was passed".to_string(),
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+}
+
+#[test]
+fn test_explicit_lock_file_is_used() {
+    let project = tempdir::TempDir::new("crate2nix_explicit_lock_file").unwrap();
+    let cargo_toml = project.path().join("app").join("Cargo.toml");
+    std::fs::create_dir_all(cargo_toml.parent().unwrap()).unwrap();
+    std::fs::write(&cargo_toml, "[package]\nname = \"app\"\n").unwrap();
+    let lock_file = project.path().join("locks").join("app").join("Cargo.lock");
+    std::fs::create_dir_all(lock_file.parent().unwrap()).unwrap();
+    std::fs::write(&lock_file, "version = 3\n").unwrap();
+
+    let config = GenerateConfig {
+        cargo_toml: vec![cargo_toml.clone()],
+        lockfile: Some(lock_file.clone()),
+        ..crate::test::generate_config()
+    };
+    assert_eq!(config.lock_file(&cargo_toml), lock_file);
+    assert!(metadata_options(&config, &cargo_toml).contains(&"--locked".to_string()));
+
+    let other_cargo_toml = project.path().join("other").join("Cargo.toml");
+    assert_eq!(
+        config.lock_file(&other_cargo_toml),
+        project.path().join("other").join("Cargo.lock")
+    );
+
+    let options = metadata_options(&config, &cargo_toml);
+    let cache_dir = project.path().join("cache");
+    let key = cache_key(
+        &cache_dir,
+        &cargo_toml,
+        &config.lock_file(&cargo_toml),
+        &options,
+    )
+    .unwrap();
+    std::fs::write(&lock_file, "version = 4\n").unwrap();
+    assert_ne!(
+        key,
+        cache_key(
+            &cache_dir,
+            &cargo_toml,
+            &config.lock_file(&cargo_toml),
+            &options
+        )
+        .unwrap(),
+        "changed lock file did not change the cache key"
+    );
+
+    project.close().unwrap();
+}
+
+#[test]
+fn test_versions_of_explicit_lock_file_are_used() {
+    use std::process::Command;
+
+    let project = tempdir::TempDir::new("crate2nix_explicit_lock_file_versions").unwrap();
+    let write = |path: &str, content: &str| {
+        let path = project.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(project.path().join("dep"))
+            .args([
+                "-c",
+                "user.name=crate2nix",
+                "-c",
+                "user.email=crate2nix@example.com",
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    };
+
+    // A git dependency with version 0.1.0 in its first and 0.2.0 in its second commit.
+    write("dep/src/lib.rs", "");
+    git(&["init", "--quiet"]);
+    let mut revs = Vec::new();
+    for version in ["0.1.0", "0.2.0"] {
+        write(
+            "dep/Cargo.toml",
+            &format!("[package]\nname = \"dep\"\nversion = \"{}\"\n", version),
+        );
+        git(&["add", "--all"]);
+        git(&["commit", "--quiet", "-m", version]);
+        revs.push(git(&["rev-parse", "HEAD"]));
+    }
+    let dep_url = url::Url::from_directory_path(project.path().join("dep")).unwrap();
+    let dep_url = dep_url.as_str().trim_end_matches('/');
+    write(
+        "app/Cargo.toml",
+        &format!(
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+             [dependencies]\ndep = {{ git = \"{}\" }}\n",
+            dep_url
+        ),
+    );
+    write("app/src/lib.rs", "");
+    let lock = |version: &str, rev: &str| {
+        format!(
+            "version = 3\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n\
+             dependencies = [\n \"dep\",\n]\n\n\
+             [[package]]\nname = \"dep\"\nversion = \"{}\"\nsource = \"git+{}#{}\"\n",
+            version, dep_url, rev
+        )
+    };
+    let workspace_lock = lock("0.2.0", &revs[1]);
+    write("app/Cargo.lock", &workspace_lock);
+    write("locks/app.lock", &lock("0.1.0", &revs[0]));
+
+    let cargo_toml = project.path().join("app").join("Cargo.toml");
+    let dep_version = |lockfile: Option<PathBuf>| {
+        let config = GenerateConfig {
+            cargo_toml: vec![cargo_toml.clone()],
+            lockfile,
+            ..crate::test::generate_config()
+        };
+        let metadata = cargo_metadata(&config, &cargo_toml).unwrap();
+        metadata
+            .packages
+            .iter()
+            .find(|p| p.name == "dep")
+            .unwrap()
+            .version
+            .to_string()
+    };
+
+    assert_eq!(
+        dep_version(Some(project.path().join("locks/app.lock"))),
+        "0.1.0"
+    );
+    assert_eq!(
+        std::fs::read_to_string(project.path().join("app/Cargo.lock")).unwrap(),
+        workspace_lock
+    );
+    assert_eq!(dep_version(None), "0.2.0");
+
+    project.close().unwrap();
+}
+
+#[test]
+fn test_cache_key_is_stable() {
+    let project = tempdir::TempDir::new("crate2nix_stable_cache_key").unwrap();
+    let cargo_toml = project.path().join("app").join("Cargo.toml");
+    std::fs::create_dir_all(cargo_toml.parent().unwrap()).unwrap();
+    std::fs::write(&cargo_toml, "[package]\nname = \"app\"\n").unwrap();
+    let lock_file = project.path().join("app").join("Cargo.lock");
+    std::fs::write(&lock_file, "version = 3\n").unwrap();
+
+    // The SHA-256 of the canonical key, independent of the Rust release and the project path.
+    assert_eq!(
+        cache_key(
+            &project.path().join("cache"),
+            &cargo_toml,
+            &lock_file,
+            &["--locked".to_string()]
+        )
+        .unwrap(),
+        "16b02d5155b83c874e3decd31f241ad1f2e9e0aa51634583ef8faf9750796d59"
+    );
+
+    project.close().unwrap();
+}
+
+#[test]
+fn test_relocate_package_id() {
+    let from = Path::new("/old/project");
+    let to = Path::new("/new/project");
+    assert_eq!(
+        relocate_package_id("app 0.1.0 (path+file:///old/project)", from, to),
+        "app 0.1.0 (path+file:///new/project)"
+    );
+    assert_eq!(
+        relocate_package_id("path+file:///old/project/dep#0.1.0", from, to),
+        "path+file:///new/project/dep#0.1.0"
+    );
+    assert_eq!(
+        relocate_package_id("path+file:///old/projects/other#0.1.0", from, to),
+        "path+file:///old/projects/other#0.1.0"
+    );
+    let registry = "registry+https://github.com/rust-lang/crates.io-index#libc@0.2.0";
+    assert_eq!(relocate_package_id(registry, from, to), registry);
+}
+
+#[test]
+fn test_isolated_metadata_leaves_workspace_untouched() {
+    let project = tempdir::TempDir::new("crate2nix_isolated_metadata").unwrap();
+    let cargo_toml = crate::test::write_lib_project(project.path(), "isolated");
+    let lock = std::fs::read_to_string(project.path().join("Cargo.lock")).unwrap();
+    let config = crate::test::generate_config_for(&cargo_toml);
+    let metadata = cargo_metadata(&config, &cargo_toml).unwrap();
+
+    assert_ne!(
+        PathBuf::from(metadata.target_directory.as_str()),
+        project.path().join("target")
+    );
+    assert_eq!(
+        std::fs::read(project.path().join("Cargo.lock")).unwrap(),
+        lock.as_bytes()
+    );
+
+    // Without isolation, cargo rewrites the lock file, e.g. in a newer format version.
+    let config = GenerateConfig {
+        isolated_metadata: false,
+        ..config
+    };
+    cargo_metadata(&config, &cargo_toml).unwrap();
+    assert_ne!(
+        std::fs::read(project.path().join("Cargo.lock")).unwrap(),
+        lock.as_bytes()
+    );
+
+    project.close().unwrap();
+}
+
+#[test]
+fn test_offline_generation_from_committed_cache() {
+    let generate = |project: &Path, offline: bool| {
+        let config = GenerateConfig {
+            metadata_cache: Some(project.join("metadata-cache")),
+            offline,
+            ..crate::test::generate_config_for(&project.join("Cargo.toml"))
+        };
+        crate::BuildInfo::for_config(&crate::GenerateInfo::default(), &config)
+            .and_then(|build_info| crate::render::CARGO_NIX.render(&build_info))
+    };
+
+    let original = tempdir::TempDir::new("crate2nix_offline_original").unwrap();
+    let project = original.path().join("project");
+    crate::test::write_lib_project(&project, "offline");
+    let online = generate(&project, false).unwrap();
+
+    let checkout = tempdir::TempDir::new("crate2nix_offline_checkout").unwrap();
+    fs_extra::dir::copy(
+        &project,
+        checkout.path(),
+        &fs_extra::dir::CopyOptions::new(),
+    )
+    .unwrap();
+    let moved_project = checkout.path().join("project");
+    assert_eq!(generate(&moved_project, true).unwrap(), online);
+
+    std::fs::write(
+        moved_project.join("Cargo.toml"),
+        "[package]\nname = \"offline\"\nversion = \"0.2.0\"\nedition = \"2018\"\n",
+    )
+    .unwrap();
+    let error = generate(&moved_project, true).unwrap_err().to_string();
+    assert!(error.contains("No up to date metadata"), "{}", error);
+
+    original.close().unwrap();
+    checkout.close().unwrap();
+}
@@ -4,13 +4,271 @@ use std::io::BufRead;
 use std::io::Write;
 use std::path::Path;
 use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex, OnceLock};
 
 use anyhow::bail;
 use anyhow::format_err;
 use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+/// A version of nix such as `2.3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NixVersion {
+    /// The major version.
+    pub major: u16,
+    /// The minor version.
+    pub minor: u16,
+}
+
+impl NixVersion {
+    /// The first version with the new `nix` CLI which has to be enabled with the
+    /// `nix-command` experimental feature.
+    const NEW_CLI: NixVersion = NixVersion { major: 2, minor: 4 };
+
+    /// Detects the version of the installed nix with `nix --version`.
+    pub fn detect() -> Result<NixVersion, Error> {
+        let output = Command::new("nix")
+            .arg("--version")
+            .output()
+            .map_err(|e| format_err!("while spawning nix --version: {}", e))?;
+        if !output.status.success() {
+            bail!(
+                "nix --version exited with: {}",
+                output.status.code().unwrap_or(-1)
+            );
+        }
+        String::from_utf8_lossy(&output.stdout).parse()
+    }
+}
+
+impl FromStr for NixVersion {
+    type Err = Error;
+
+    /// Parses versions like `2.3`, `2.18.1` or the output of `nix --version`,
+    /// e.g. `nix (Nix) 2.18.1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || -> Option<NixVersion> {
+            let version = s.split_whitespace().last()?;
+            let mut parts = version.split('.');
+            Some(NixVersion {
+                major: parts.next()?.parse().ok()?,
+                minor: parts.next()?.parse().ok()?,
+            })
+        };
+        parse()
+            .ok_or_else(|| format_err!("invalid nix version '{}', expected e.g. '2.18'", s.trim()))
+    }
+}
+
+impl std::fmt::Display for NixVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl Serialize for NixVersion {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for NixVersion {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Builds `nix` command lines which the given nix version understands.
+#[derive(Debug, Clone)]
+pub struct Nix {
+    /// The nix version, detected when the first command is built if it was not given.
+    version: OnceLock<NixVersion>,
+    show_trace: bool,
+}
+
+impl Nix {
+    /// Commands for the given nix version.
+    pub fn new(version: NixVersion) -> Nix {
+        Nix {
+            version: OnceLock::from(version),
+            show_trace: true,
+        }
+    }
+
+    /// Whether to pass `--show-trace`, which is the default.
+    pub fn show_trace(mut self, show_trace: bool) -> Nix {
+        self.show_trace = show_trace;
+        self
+    }
+
+    /// Commands for the installed nix version, assuming a recent nix if it cannot be detected.
+    ///
+    /// `nix --version` only runs once a command is built.
+    pub fn detect() -> Nix {
+        Nix {
+            version: OnceLock::new(),
+            show_trace: true,
+        }
+    }
+
+    /// The given or detected nix version.
+    fn version(&self) -> NixVersion {
+        *self.version.get_or_init(|| {
+            NixVersion::detect().unwrap_or_else(|e| {
+                eprintln!(
+                    "Could not detect the nix version, assuming a recent nix: {}",
+                    e
+                );
+                NixVersion {
+                    major: 2,
+                    minor: 18,
+                }
+            })
+        })
+    }
+
+    /// A `nix` command running the given subcommand.
+    fn nix(&self, subcommand: &str) -> Command {
+        let mut command = Command::new("nix");
+        if self.version() >= NixVersion::NEW_CLI {
+            command.args(["--extra-experimental-features", "nix-command"]);
+        }
+        if self.show_trace {
+            command.arg("--show-trace");
+        }
+        command.arg(subcommand);
+        command
+    }
+
+    /// A `nix build` command for the attribute of the nix file.
+    pub fn build(&self, nix_file: impl AsRef<Path>, nix_attr: &str) -> Command {
+        let mut command = self.nix("build");
+        command.arg("-f").arg(nix_file.as_ref()).arg(nix_attr);
+        command
+    }
+
+    /// A command building the derivation of a nix expression without an output link.
+    ///
+    /// Relative paths in the expression are resolved against the working directory.
+    pub fn build_expr(&self, expr: &str) -> Command {
+        if self.version() >= NixVersion::NEW_CLI {
+            let mut command = self.nix("build");
+            command.args(["--impure", "--no-link", "--expr", expr]);
+            command
+        } else {
+            let mut command = Command::new("nix-build");
+            if self.show_trace {
+                command.arg("--show-trace");
+            }
+            command.args(["--no-out-link", "-E", expr]);
+            command
+        }
+    }
+
+    /// A command which instantiates the derivations of the nix file and prints its
+    /// top-level attribute names.
+    pub fn eval_attr_names(&self, nix_file: impl AsRef<Path>) -> Command {
+        let nix_file = nix_file.as_ref();
+        if self.version() >= NixVersion::NEW_CLI {
+            let mut command = self.nix("eval");
+            command
+                .arg("--file")
+                .arg(nix_file)
+                .args(["--apply", FORCE_DERIVATIONS]);
+            command
+        } else {
+            let mut command = Command::new("nix-instantiate");
+            if self.show_trace {
+                command.arg("--show-trace");
+            }
+            command.args(["--eval", "--strict", "-E"]).arg(format!(
+                "({}) (let f = import {}; in if builtins.isFunction f then f {{ }} else f)",
+                FORCE_DERIVATIONS,
+                crate::render::escape_nix_string(&nix_file.to_string_lossy())
+            ));
+            command
+        }
+    }
+
+    /// A command which instantiates the derivation of the attribute of the nix file,
+    /// e.g. `workspaceMembers.main.build`.
+    pub fn eval_drv_path(&self, nix_file: impl AsRef<Path>, nix_attr: &str) -> Command {
+        if self.version() >= NixVersion::NEW_CLI {
+            let mut command = self.nix("eval");
+            command
+                .arg("--file")
+                .arg(nix_file.as_ref())
+                .arg(format!("{}.drvPath", nix_attr));
+            command
+        } else {
+            let mut command = Command::new("nix-instantiate");
+            if self.show_trace {
+                command.arg("--show-trace");
+            }
+            command.arg(nix_file.as_ref()).args(["-A", nix_attr]);
+            command
+        }
+    }
+}
+
+/// Bounds the number of nix processes that `--validate-eval` and `--verify-build` run at
+/// the same time.
+#[derive(Debug)]
+pub struct NixProcesses {
+    max: usize,
+    running: Mutex<usize>,
+    finished: Condvar,
+}
+
+impl NixProcesses {
+    /// Allows up to `max` nix processes at the same time, but at least one.
+    pub fn new(max: usize) -> NixProcesses {
+        NixProcesses {
+            max: max.max(1),
+            running: Mutex::new(0),
+            finished: Condvar::new(),
+        }
+    }
+
+    /// Runs `f` as soon as fewer than the maximal number of nix processes are running.
+    fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let mut running = self
+            .finished
+            .wait_while(self.running.lock().unwrap(), |running| *running >= self.max)
+            .unwrap();
+        *running += 1;
+        drop(running);
+        let result = f();
+        *self.running.lock().unwrap() -= 1;
+        self.finished.notify_one();
+        result
+    }
+}
+
+/// A nix function that forces the `drvPath` of the derivations in a build file, e.g.
+/// `rootCrate.build`, and returns its top-level attribute names.
+///
+/// Only listing the attribute names would not evaluate the values at all. The internals
+/// and the builds for other systems are left out, and so are the workspace members, which
+/// [validate_eval] evaluates one by one.
+const FORCE_DERIVATIONS: &str = "out: let \
+    drvPaths = depth: value: \
+      if !(builtins.isAttrs value) then [ ] \
+      else if value.type or null == \"derivation\" then [ value.drvPath ] \
+      else if depth > 0 then builtins.concatLists (map \
+        (name: drvPaths (depth - 1) value.${name}) (builtins.attrNames value)) \
+      else [ ]; \
+  in builtins.deepSeq \
+    (drvPaths 3 (builtins.removeAttrs out [ \"internal\" \"packagesBySystem\" \"appsBySystem\" \
+      \"workspaceMembers\" \"allWorkspaceMembers\" ])) \
+    (builtins.attrNames out)";
 
 /// Call `nix build` in the given directory on the `default.nix` in that directory.
 pub fn nix_build(
+    nix: &Nix,
     project_dir: impl AsRef<Path>,
     nix_attr: &str,
     features: &[&str],
@@ -20,17 +278,9 @@ pub fn nix_build(
 
     let result = crate::command::run(
         &format!("Building {}", project_dir),
-        Command::new("nix")
+        nix.build("default.nix", nix_attr)
             .current_dir(&project_dir)
-            .args(&[
-                "--show-trace",
-                "build",
-                "-f",
-                "default.nix",
-                nix_attr,
-                "--arg",
-                "rootFeatures",
-            ])
+            .args(["--arg", "rootFeatures"])
             .arg(format!(
                 "[ {} ]",
                 features
@@ -42,12 +292,176 @@ pub fn nix_build(
     );
 
     if result.is_err() {
-        dump_with_lines(&project_dir_path.join("default.nix"))?;
+        dump_with_lines(project_dir_path.join("default.nix"))?;
     }
 
     result
 }
 
+/// Checks that the given nix file evaluates by listing its top-level attributes with `nix eval`
+/// and by instantiating the derivations of the given attributes, e.g. the workspace members.
+///
+/// This is much faster than building and catches e.g. syntax errors and undefined variables.
+/// The attributes are evaluated with up to as many `nix eval` processes at the same time as
+/// `processes` allows.
+pub fn validate_eval(
+    nix: &Nix,
+    nix_file: impl AsRef<Path>,
+    attrs: &[String],
+    fail_fast: bool,
+    processes: &NixProcesses,
+) -> Result<(), Error> {
+    validate_eval_with(
+        nix,
+        nix_file.as_ref(),
+        attrs,
+        fail_fast,
+        processes,
+        crate::command::run_captured,
+        &mut std::io::stderr(),
+    )
+}
+
+fn validate_eval_with(
+    nix: &Nix,
+    nix_file: &Path,
+    attrs: &[String],
+    fail_fast: bool,
+    processes: &NixProcesses,
+    run: impl Fn(&mut Command) -> Result<(), Error> + Sync,
+    output: &mut impl Write,
+) -> Result<(), Error> {
+    let top_level = processes.run(|| run(&mut nix.eval_attr_names(nix_file)));
+    writeln!(
+        output,
+        "Evaluating {}: {}",
+        nix_file.to_string_lossy(),
+        if top_level.is_ok() {
+            "done."
+        } else {
+            "failed."
+        }
+    )?;
+    top_level
+        .and_then(|()| {
+            run_all(
+                ("Evaluating", "evaluations"),
+                attrs,
+                fail_fast,
+                processes,
+                |attr| run(&mut nix.eval_drv_path(nix_file, attr)),
+                output,
+            )
+        })
+        .map_err(|e| {
+            format_err!(
+                "{} does not evaluate, please report this as a bug: {}",
+                nix_file.to_string_lossy(),
+                e
+            )
+        })
+}
+
+/// Builds the given attributes of the nix file with `nix build`, running up to as many
+/// builds at the same time as `processes` allows.
+///
+/// With `fail_fast`, no further builds are started after the first failing build. Otherwise,
+/// the remaining attributes are built as well and all failures are reported at the end.
+pub fn verify_build(
+    nix: &Nix,
+    nix_file: impl AsRef<Path>,
+    attrs: &[String],
+    fail_fast: bool,
+    processes: &NixProcesses,
+) -> Result<(), Error> {
+    let nix_file = nix_file.as_ref();
+    run_all(
+        ("Building", "builds"),
+        attrs,
+        fail_fast,
+        processes,
+        |attr| crate::command::run_captured(nix.build(nix_file, attr).arg("--no-link")),
+        &mut std::io::stderr(),
+    )
+}
+
+/// Runs `run` for the attributes on as many threads as `processes` allows.
+///
+/// The runs do not print anything themselves. Their results are sent to the calling
+/// thread, which reports each one on its own line to `output`, starting with `verb`.
+/// The failures are counted as `runs`, e.g. "builds".
+fn run_all(
+    (verb, runs): (&str, &str),
+    attrs: &[String],
+    fail_fast: bool,
+    processes: &NixProcesses,
+    run: impl Fn(&str) -> Result<(), Error> + Sync,
+    output: &mut impl Write,
+) -> Result<(), Error> {
+    let next_attr = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let mut errors = Vec::new();
+    std::thread::scope(|scope| -> Result<(), Error> {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..processes.max.min(attrs.len().max(1)) {
+            let sender = sender.clone();
+            let (next_attr, failed, run) = (&next_attr, &failed, &run);
+            scope.spawn(move || {
+                while !(fail_fast && failed.load(Ordering::SeqCst)) {
+                    let idx = next_attr.fetch_add(1, Ordering::SeqCst);
+                    let attr = match attrs.get(idx) {
+                        Some(attr) => attr,
+                        None => break,
+                    };
+                    let result = processes.run(|| run(attr));
+                    if result.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    if sender.send((idx, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for (idx, result) in receiver {
+            match result {
+                Ok(()) => writeln!(output, "{} {}: done.", verb, attrs[idx])?,
+                Err(e) => {
+                    writeln!(output, "{} {}: failed.", verb, attrs[idx])?;
+                    errors.push((idx, e));
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    errors.sort_by_key(|(idx, _)| *idx);
+    if fail_fast {
+        return match errors.into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Ok(()),
+        };
+    }
+
+    let failures: Vec<String> = errors
+        .into_iter()
+        .map(|(idx, e)| format!("{}: {}", attrs[idx], e))
+        .collect();
+    if !failures.is_empty() {
+        bail!(
+            "{} of {} {} failed:\n{}",
+            failures.len(),
+            attrs.len(),
+            runs,
+            failures.join("\n")
+        );
+    }
+
+    Ok(())
+}
+
 /// Dump the content of the specified file with line numbers to stdout.
 pub fn dump_with_lines(file_path: impl AsRef<Path>) -> Result<(), Error> {
     let file_path = file_path.as_ref().to_string_lossy().to_string();
@@ -80,3 +494,368 @@ pub fn run_cmd(cmd_path: impl AsRef<Path>) -> Result<String, Error> {
     String::from_utf8(output.stdout)
         .map_err(|_e| format_err!("output of {} is not UTF8!", cmd_path))
 }
+
+#[test]
+fn test_validate_eval_fails_for_broken_output() {
+    let version = match NixVersion::detect() {
+        Ok(version) => version,
+        Err(e) => {
+            eprintln!(
+                "SKIPPED test_validate_eval_fails_for_broken_output, nix is not available: {}",
+                e
+            );
+            return;
+        }
+    };
+    let nix = Nix::new(version);
+    let dir = tempdir::TempDir::new("crate2nix_validate_eval").unwrap();
+    let nix_file = dir.path().join("Cargo.nix");
+    let derivation = |builder: &str| {
+        format!(
+            "derivation {{ name = \"main\"; system = \"x86_64-linux\"; builder = {}; }}",
+            builder
+        )
+    };
+
+    std::fs::write(
+        &nix_file,
+        format!(
+            "{{ }}: {{ rootCrate = {{ build = {}; }}; }}",
+            derivation("\"/bin/sh\"")
+        ),
+    )
+    .unwrap();
+    let processes = NixProcesses::new(1);
+    validate_eval(&nix, &nix_file, &[], true, &processes).unwrap();
+
+    // Rendered from a template with a missing semicolon.
+    std::fs::write(&nix_file, "{ pkgs ? null }: { broken = pkgs }").unwrap();
+    assert!(validate_eval(&nix, &nix_file, &[], true, &processes).is_err());
+
+    // The attribute names can be listed but the derivation does not evaluate.
+    std::fs::write(
+        &nix_file,
+        format!(
+            "{{ }}: {{ workspaceMembers.main = {{ build = {}; }}; }}",
+            derivation("throw \"broken\"")
+        ),
+    )
+    .unwrap();
+    let members = vec!["workspaceMembers.main.build".to_string()];
+    assert!(validate_eval(&nix, &nix_file, &members, true, &processes).is_err());
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_run_all_fail_fast() {
+    use std::sync::Mutex;
+
+    let attrs = vec!["failing".to_string(), "working".to_string()];
+    let build_all = |fail_fast: bool, built: &Mutex<Vec<String>>, output: &mut Vec<u8>| {
+        run_all(
+            ("Building", "builds"),
+            &attrs,
+            fail_fast,
+            &NixProcesses::new(1),
+            |attr| {
+                built.lock().unwrap().push(attr.to_string());
+                if attr == "failing" {
+                    bail!("build of {} failed", attr);
+                }
+                Ok(())
+            },
+            output,
+        )
+    };
+
+    let built = Mutex::new(Vec::new());
+    let mut output = Vec::new();
+    assert!(build_all(true, &built, &mut output).is_err());
+    assert_eq!(built.into_inner().unwrap(), vec!["failing"]);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Building failing: failed.\n"
+    );
+
+    let built = Mutex::new(Vec::new());
+    let mut output = Vec::new();
+    let error = build_all(false, &built, &mut output).unwrap_err();
+    assert_eq!(built.into_inner().unwrap(), vec!["failing", "working"]);
+    assert_eq!(
+        error.to_string(),
+        "1 of 2 builds failed:\nfailing: build of failing failed"
+    );
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Building failing: failed.\nBuilding working: done.\n"
+    );
+}
+
+/// A stub for running nix processes that records how many of them run at the same time.
+///
+/// Every process waits until `jobs` processes are running, which only returns if that
+/// many run at the same time. Additional concurrent processes would raise the maximum.
+#[cfg(test)]
+struct ConcurrencyProbe {
+    running: AtomicUsize,
+    max_running: AtomicUsize,
+    all_running: std::sync::Barrier,
+}
+
+#[cfg(test)]
+impl ConcurrencyProbe {
+    fn new(jobs: usize) -> ConcurrencyProbe {
+        ConcurrencyProbe {
+            running: AtomicUsize::new(0),
+            max_running: AtomicUsize::new(0),
+            all_running: std::sync::Barrier::new(jobs.max(1)),
+        }
+    }
+
+    fn run(&self) -> Result<(), Error> {
+        let now_running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_running.fetch_max(now_running, Ordering::SeqCst);
+        self.all_running.wait();
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        self.all_running.wait();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_run_all_bounds_concurrent_builds() {
+    let attrs: Vec<String> = (0..6).map(|i| format!("member{}", i)).collect();
+    let max_running_builds = |jobs: usize| {
+        let probe = ConcurrencyProbe::new(jobs);
+        let mut output = Vec::new();
+        run_all(
+            ("Building", "builds"),
+            &attrs,
+            false,
+            &NixProcesses::new(jobs),
+            |_attr| probe.run(),
+            &mut output,
+        )
+        .unwrap();
+
+        // Every build is reported on a line of its own.
+        let mut lines: Vec<_> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        let expected: Vec<_> = attrs
+            .iter()
+            .map(|attr| format!("Building {}: done.", attr))
+            .collect();
+        assert_eq!(lines, expected);
+
+        probe.max_running.into_inner()
+    };
+
+    assert_eq!(max_running_builds(1), 1);
+    assert_eq!(max_running_builds(2), 2);
+    assert_eq!(max_running_builds(3), 3);
+    assert_eq!(max_running_builds(0), 1);
+}
+
+#[test]
+fn test_validate_eval_bounds_concurrent_evaluations() {
+    use std::sync::Mutex;
+
+    let nix = Nix::new("2.18".parse().unwrap());
+    let attrs: Vec<String> = (0..6)
+        .map(|i| format!("workspaceMembers.member{}.build", i))
+        .collect();
+    let max_running_evals = |jobs: usize| {
+        let probe = ConcurrencyProbe::new(jobs);
+        let evaluated = Mutex::new(Vec::new());
+        let mut output = Vec::new();
+        validate_eval_with(
+            &nix,
+            Path::new("Cargo.nix"),
+            &attrs,
+            false,
+            &NixProcesses::new(jobs),
+            |command| {
+                let attr = command.get_args().last().unwrap().to_string_lossy();
+                evaluated.lock().unwrap().push(attr.to_string());
+                // The top-level attributes are evaluated on their own before the members.
+                if attr.starts_with("workspaceMembers.") {
+                    probe.run()
+                } else {
+                    Ok(())
+                }
+            },
+            &mut output,
+        )
+        .unwrap();
+
+        let mut evaluated = evaluated.into_inner().unwrap();
+        assert!(evaluated.remove(0).contains("builtins.deepSeq"));
+        evaluated.sort();
+        let expected: Vec<_> = attrs
+            .iter()
+            .map(|attr| format!("{}.drvPath", attr))
+            .collect();
+        assert_eq!(evaluated, expected);
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.starts_with("Evaluating Cargo.nix: done.\n"),
+            "{}",
+            output
+        );
+        assert_eq!(output.lines().count(), 1 + attrs.len(), "{}", output);
+
+        probe.max_running.into_inner()
+    };
+
+    assert_eq!(max_running_evals(1), 1);
+    assert_eq!(max_running_evals(2), 2);
+    assert_eq!(max_running_evals(3), 3);
+    assert_eq!(max_running_evals(0), 1);
+}
+
+#[test]
+fn test_nix_version_from_str() {
+    let version = |s: &str| s.parse::<NixVersion>().unwrap();
+    assert_eq!(version("2.3"), NixVersion { major: 2, minor: 3 });
+    assert_eq!(
+        version("nix (Nix) 2.18.1\n"),
+        NixVersion {
+            major: 2,
+            minor: 18
+        }
+    );
+    assert!("latest".parse::<NixVersion>().is_err());
+}
+
+#[test]
+fn test_nix_version_is_detected_on_first_command() {
+    let version = NixVersion { major: 2, minor: 3 };
+    assert_eq!(Nix::new(version).version.get(), Some(&version));
+
+    // Creating the commands for the installed nix does not run `nix --version` yet.
+    let detected = Nix::detect();
+    assert_eq!(detected.version.get(), None);
+    let _ = detected.build("default.nix", "hello");
+    assert!(detected.version.get().is_some());
+}
+
+#[test]
+fn test_command_shapes_per_nix_version() {
+    let args = |command: &Command| {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+    };
+    let legacy = Nix::new("2.3".parse().unwrap());
+    let recent = Nix::new("2.18".parse().unwrap());
+
+    assert_eq!(
+        args(&legacy.build("sources.nix", "fetchedSources")),
+        vec![
+            "nix",
+            "--show-trace",
+            "build",
+            "-f",
+            "sources.nix",
+            "fetchedSources"
+        ]
+    );
+    assert_eq!(
+        args(&recent.build("sources.nix", "fetchedSources")),
+        vec![
+            "nix",
+            "--extra-experimental-features",
+            "nix-command",
+            "--show-trace",
+            "build",
+            "-f",
+            "sources.nix",
+            "fetchedSources"
+        ]
+    );
+
+    assert_eq!(
+        args(&recent.eval_attr_names("Cargo.nix"))[..6],
+        [
+            "nix",
+            "--extra-experimental-features",
+            "nix-command",
+            "--show-trace",
+            "eval",
+            "--file"
+        ]
+    );
+    let legacy_eval = args(&legacy.eval_attr_names("Cargo.nix"));
+    assert_eq!(legacy_eval[0], "nix-instantiate");
+    assert!(legacy_eval.last().unwrap().contains("import \"Cargo.nix\""));
+    // Both force the derivations instead of only listing the attribute names.
+    let recent_eval = args(&recent.eval_attr_names("Cargo.nix"));
+    for eval in [recent_eval.last().unwrap(), legacy_eval.last().unwrap()] {
+        assert!(eval.contains("builtins.deepSeq"), "{}", eval);
+        assert!(eval.contains("value.drvPath"), "{}", eval);
+    }
+
+    assert_eq!(
+        args(&recent.eval_drv_path("Cargo.nix", "workspaceMembers.main.build"))[4..],
+        [
+            "eval",
+            "--file",
+            "Cargo.nix",
+            "workspaceMembers.main.build.drvPath"
+        ]
+    );
+    assert_eq!(
+        args(&legacy.eval_drv_path("Cargo.nix", "workspaceMembers.main.build")),
+        vec![
+            "nix-instantiate",
+            "--show-trace",
+            "Cargo.nix",
+            "-A",
+            "workspaceMembers.main.build"
+        ]
+    );
+
+    assert_eq!(
+        args(&legacy.build_expr("import ./.")),
+        vec![
+            "nix-build",
+            "--show-trace",
+            "--no-out-link",
+            "-E",
+            "import ./."
+        ]
+    );
+    assert_eq!(
+        args(&recent.build_expr("import ./."))[3..],
+        [
+            "--show-trace",
+            "build",
+            "--impure",
+            "--no-link",
+            "--expr",
+            "import ./."
+        ]
+    );
+}
+
+#[test]
+fn test_show_trace_can_be_disabled() {
+    let has_show_trace = |command: Command| command.get_args().any(|arg| arg == "--show-trace");
+    for version in ["2.3", "2.18"] {
+        let nix = Nix::new(version.parse().unwrap());
+        assert!(has_show_trace(nix.build("Cargo.nix", "all")));
+        assert!(has_show_trace(nix.eval_attr_names("Cargo.nix")));
+        assert!(has_show_trace(nix.build_expr("import ./.")));
+
+        let nix = nix.show_trace(false);
+        assert!(!has_show_trace(nix.build("Cargo.nix", "all")));
+        assert!(!has_show_trace(nix.eval_attr_names("Cargo.nix")));
+        assert!(!has_show_trace(nix.build_expr("import ./.")));
+    }
+}
@@ -0,0 +1,158 @@
+//! Compatibility with the `buildRustCrate` of older nixpkgs releases.
+//!
+//! Some attributes that we pass to `buildRustCrate` are not accepted by older
+//! nixpkgs releases. When generating for such a release, they are omitted.
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A nixpkgs release such as `20.03`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NixpkgsRelease {
+    /// The two-digit year of the release.
+    pub year: u16,
+    /// The month of the release.
+    pub month: u16,
+}
+
+impl NixpkgsRelease {
+    const fn new(year: u16, month: u16) -> NixpkgsRelease {
+        NixpkgsRelease { year, month }
+    }
+}
+
+impl FromStr for NixpkgsRelease {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || -> Option<NixpkgsRelease> {
+            let (year, month) = s.split_once('.')?;
+            let release = NixpkgsRelease::new(year.parse().ok()?, month.parse().ok()?);
+            if (1..=12).contains(&release.month) {
+                Some(release)
+            } else {
+                None
+            }
+        };
+        parse().ok_or_else(|| format_err!("invalid nixpkgs release '{}', expected e.g. '20.03'", s))
+    }
+}
+
+impl Display for NixpkgsRelease {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}.{:02}", self.year, self.month)
+    }
+}
+
+impl Serialize for NixpkgsRelease {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for NixpkgsRelease {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The `buildRustCrate` attributes which are not supported by all nixpkgs releases.
+///
+/// Each attribute is only rendered if it is `true`. The releases are the first ones
+/// whose `pkgs/build-support/rust/build-rust-crate` (or `lib`, for the `meta` attributes)
+/// supports the attribute. Attributes that are not listed here, such as `procMacro`,
+/// `extraRustcOpts`, `authors` or the `preConfigure` and `preBuild` hooks, are
+/// supported by all releases.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildRustCrateAttributes {
+    /// `edition`, since nixpkgs 19.03.
+    pub edition: bool,
+    /// `crateBin` as a list of `{ name, path }` attribute sets, since nixpkgs 20.03.
+    ///
+    /// Without it, `buildRustCrate` detects the binaries by itself. The binaries of
+    /// dependencies are then removed after unpacking so that they are not built.
+    pub crate_bin: bool,
+    /// Arbitrary derivation attributes such as `unpackCmd`, `sourceRoot`, `postPatch`,
+    /// `checkInputs`, `NIX_CFLAGS_COMPILE` or `meta`, which `buildRustCrate` passes on to
+    /// `mkDerivation` since nixpkgs 20.03.
+    pub derivation_attributes: bool,
+    /// `meta.mainProgram`, since nixpkgs 21.05.
+    pub main_program: bool,
+    /// `meta.sourceProvenance` with `lib.sourceTypes`, since nixpkgs 22.05.
+    pub source_provenance: bool,
+    /// `description`, `homepage`, `license` and `repository` for the `CARGO_PKG_*`
+    /// variables, since nixpkgs 23.05.
+    pub package_metadata: bool,
+}
+
+const EDITION: NixpkgsRelease = NixpkgsRelease::new(19, 3);
+const CRATE_BIN: NixpkgsRelease = NixpkgsRelease::new(20, 3);
+const DERIVATION_ATTRIBUTES: NixpkgsRelease = NixpkgsRelease::new(20, 3);
+const MAIN_PROGRAM: NixpkgsRelease = NixpkgsRelease::new(21, 5);
+const SOURCE_PROVENANCE: NixpkgsRelease = NixpkgsRelease::new(22, 5);
+const PACKAGE_METADATA: NixpkgsRelease = NixpkgsRelease::new(23, 5);
+
+impl BuildRustCrateAttributes {
+    /// The attributes supported by the given nixpkgs release, or all attributes
+    /// if no release is given.
+    ///
+    /// ```
+    /// use crate2nix::nixpkgs_compat::BuildRustCrateAttributes;
+    /// let old = BuildRustCrateAttributes::for_release(Some(&"19.09".parse().unwrap()));
+    /// assert!(old.edition);
+    /// assert!(!old.crate_bin);
+    /// let current = BuildRustCrateAttributes::for_release(None);
+    /// assert!(current.edition && current.crate_bin);
+    /// ```
+    pub fn for_release(release: Option<&NixpkgsRelease>) -> BuildRustCrateAttributes {
+        let since = |first: NixpkgsRelease| release.map(|r| *r >= first).unwrap_or(true);
+        BuildRustCrateAttributes {
+            edition: since(EDITION),
+            crate_bin: since(CRATE_BIN),
+            derivation_attributes: since(DERIVATION_ATTRIBUTES),
+            main_program: since(MAIN_PROGRAM),
+            source_provenance: since(SOURCE_PROVENANCE),
+            package_metadata: since(PACKAGE_METADATA),
+        }
+    }
+
+    /// Fails if the configuration needs a feature that cannot be left out for
+    /// these attributes.
+    pub fn check_config(&self, config: &crate::GenerateConfig) -> Result<(), Error> {
+        if config.artifact_dependencies && !self.crate_bin {
+            bail!(
+                "--artifact-dependencies needs `crateBin` lists, i.e. nixpkgs {} or later",
+                CRATE_BIN
+            );
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_parse_nixpkgs_release() {
+    let release: NixpkgsRelease = "19.09".parse().unwrap();
+    assert_eq!(release, NixpkgsRelease::new(19, 9));
+    assert_eq!(release.to_string(), "19.09");
+    assert!(release < "20.03".parse().unwrap());
+    assert!("unstable".parse::<NixpkgsRelease>().is_err());
+    assert!("20.13".parse::<NixpkgsRelease>().is_err());
+}
+
+#[test]
+fn test_check_config_for_old_release() {
+    let old = BuildRustCrateAttributes::for_release(Some(&"21.11".parse().unwrap()));
+    assert!(old.crate_bin && old.main_program && !old.source_provenance);
+    assert!(old.check_config(&crate::test::generate_config()).is_ok());
+
+    let artifacts = crate::GenerateConfig {
+        artifact_dependencies: true,
+        ..crate::test::generate_config()
+    };
+    let older = BuildRustCrateAttributes::for_release(Some(&"19.09".parse().unwrap()));
+    assert!(older.check_config(&artifacts).is_err());
+    assert!(old.check_config(&artifacts).is_ok());
+}
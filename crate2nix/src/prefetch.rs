@@ -4,7 +4,7 @@ use std::io::Write;
 use std::process::Command;
 
 use crate::metadata::PackageIdShortener;
-use crate::resolve::{CrateDerivation, CratesIoSource, GitSource, ResolvedSource};
+use crate::resolve::{CrateDerivation, CratesIoSource, GitSource, RegistrySource, ResolvedSource};
 use crate::GenerateConfig;
 use anyhow::bail;
 use anyhow::format_err;
@@ -37,12 +37,17 @@ struct SourcePrefetchBundle<'a> {
 
 /// Uses `nix-prefetch` to get the hashes of the sources for the given packages if they come from crates.io.
 ///
-/// Uses and updates the existing hashes in the `config.crate_hash_json` file.
+/// Uses and updates the existing hashes in the `config.crate_hash_json` file, see
+/// `crate_hashes_key` for its keys. The numbers of prefetched and already known hashes are recorded in `stats`.
+///
+/// If `offline` is set, only existing hashes are used and the file is left untouched.
 pub fn prefetch(
     config: &GenerateConfig,
     from_lock_file: &HashMap<PackageId, String>,
     crate_derivations: &[CrateDerivation],
     id_shortener: &PackageIdShortener,
+    offline: bool,
+    stats: &mut crate::stats::Stats,
 ) -> Result<BTreeMap<PackageId, String>, Error> {
     let hashes_string: String = if config.read_crate_hashes {
         std::fs::read_to_string(&config.crate_hashes_json).unwrap_or_else(|_| "{}".to_string())
@@ -52,8 +57,9 @@ pub fn prefetch(
 
     let old_prefetched_hashes: BTreeMap<PackageId, String> = serde_json::from_str(&hashes_string)?;
 
-    // Only copy used hashes over to the new map.
+    // Only copy used hashes over to the new maps.
     let mut hashes = BTreeMap::<PackageId, String>::new();
+    let mut stored_hashes = BTreeMap::<PackageId, String>::new();
 
     // Multiple packages might be fetched from the same source.
     //
@@ -88,7 +94,7 @@ pub fn prefetch(
                         })
                         .or_else(|| {
                             old_prefetched_hashes
-                                .get(id_shortener.lengthen_ref(&p.package_id))
+                                .get(&crate_hashes_key(id_shortener, p))
                                 .map(|hash| HashWithSource {
                                     sha256: hash.clone(),
                                     source: HashSource::Prefetched,
@@ -120,6 +126,8 @@ pub fn prefetch(
         .iter()
         .filter(|SourcePrefetchBundle { hash, .. }| hash.is_none())
         .count();
+    stats.sources_prefetched = without_hash_num;
+    stats.sources_cached = prefetchable_sources.len() - without_hash_num;
 
     let mut idx = 1;
     for SourcePrefetchBundle {
@@ -130,13 +138,13 @@ pub fn prefetch(
     {
         let (sha256, hash_source) = if let Some(HashWithSource { sha256, source }) = hash {
             (sha256.trim().to_string(), source)
-        } else {
-            eprintln!(
-                "Prefetching {:>4}/{}: {}",
-                idx,
-                without_hash_num,
-                source.to_string()
+        } else if offline {
+            bail!(
+                "No hash known for {}, please run crate2nix generate.",
+                source
             );
+        } else {
+            eprintln!("Prefetching {:>4}/{}: {}", idx, without_hash_num, source);
             idx += 1;
             (source.prefetch()?, HashSource::Prefetched)
         };
@@ -147,14 +155,15 @@ pub fn prefetch(
                     id_shortener.lengthen_ref(&package.package_id).clone(),
                     sha256.clone(),
                 );
+                stored_hashes.insert(crate_hashes_key(id_shortener, package), sha256.clone());
             }
         }
     }
 
-    if hashes != old_prefetched_hashes {
+    if !offline && stored_hashes != old_prefetched_hashes {
         std::fs::write(
             &config.crate_hashes_json,
-            serde_json::to_vec_pretty(&hashes)?,
+            serde_json::to_vec_pretty(&stored_hashes)?,
         )
         .map_err(|e| {
             format_err!(
@@ -172,7 +181,24 @@ pub fn prefetch(
     Ok(hashes)
 }
 
-fn get_command_output(cmd: &str, args: &[&str]) -> Result<String, Error> {
+/// The key of the hash of a package in `crate-hashes.json`.
+///
+/// This is the full package ID, with an ` archive` suffix for git sources that are fetched
+/// as archive tarballs. Their hash differs from the one of a checkout with `fetchgit`.
+fn crate_hashes_key(id_shortener: &PackageIdShortener, package: &CrateDerivation) -> PackageId {
+    let package_id = id_shortener.lengthen_ref(&package.package_id);
+    match &package.source {
+        ResolvedSource::Git(GitSource {
+            archive_url: Some(_),
+            ..
+        }) => PackageId {
+            repr: format!("{} archive", package_id.repr),
+        },
+        _ => package_id.clone(),
+    }
+}
+
+pub(crate) fn get_command_output(cmd: &str, args: &[&str]) -> Result<String, Error> {
     let output = Command::new(cmd)
         .args(args)
         .output()
@@ -206,6 +232,7 @@ impl ResolvedSource {
     fn inner_prefetchable(&self) -> Option<&dyn PrefetchableSource> {
         match self {
             ResolvedSource::CratesIo(source) => Some(source),
+            ResolvedSource::Registry(source) => Some(source),
             ResolvedSource::Git(source) => Some(source),
             _ => None,
         }
@@ -241,6 +268,25 @@ impl PrefetchableSource for CratesIoSource {
     }
 }
 
+impl PrefetchableSource for RegistrySource {
+    fn needs_prefetch(&self) -> bool {
+        self.sha256.is_none()
+    }
+
+    fn prefetch(&self) -> Result<String, Error> {
+        let url = self
+            .url
+            .as_ref()
+            .ok_or_else(|| format_err!("no download URL known for {}", self))?;
+        let args = &[
+            url.as_str(),
+            "--name",
+            &format!("{}-{}", self.name, self.version),
+        ];
+        get_command_output("nix-prefetch-url", args)
+    }
+}
+
 impl PrefetchableSource for GitSource {
     fn needs_prefetch(&self) -> bool {
         self.sha256.is_none()
@@ -257,6 +303,10 @@ impl PrefetchableSource for GitSource {
             sha256: String,
         }
 
+        if let Some(archive_url) = &self.archive_url {
+            return get_command_output("nix-prefetch-url", &["--unpack", archive_url]);
+        }
+
         let mut args = vec![
             "--url",
             self.url.as_str(),
@@ -277,3 +327,69 @@ impl PrefetchableSource for GitSource {
         Ok(prefetch_info.sha256)
     }
 }
+
+#[test]
+fn test_fetchgit_hash_is_not_used_for_archive() {
+    use crate::resolve::GitSource;
+
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("main")
+        .make_root()
+        .add_dependency("dep")
+        .version_and_package_id("0.1.0")
+        .update_package(|p| {
+            p.source = Some(cargo_metadata::Source {
+                repr: "git+https://github.com/owner/dep?rev=abc123#abc123".to_string(),
+            })
+        });
+    let mut build_info = env.build_info(crate::config::Config::default());
+    let project_dir = env.temp_dir();
+    let config = GenerateConfig {
+        crate_hashes_json: project_dir.join("crate-hashes.json"),
+        ..crate::test::generate_config()
+    };
+    let dep = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "dep")
+        .unwrap();
+    let fetchgit_hash = "0mgawc86jxvbmgk66xq644mizh59vgb1i0a2f28knram4lch108f";
+    std::fs::write(
+        &config.crate_hashes_json,
+        serde_json::to_string(&BTreeMap::from([(
+            build_info
+                .indexed_metadata
+                .id_shortener
+                .lengthen_ref(&dep.package_id),
+            fetchgit_hash,
+        )]))
+        .unwrap(),
+    )
+    .unwrap();
+
+    let prefetch_offline = |build_info: &crate::BuildInfo| {
+        prefetch(
+            &config,
+            &HashMap::new(),
+            &build_info.crates,
+            &build_info.indexed_metadata.id_shortener,
+            true,
+            &mut crate::stats::Stats::default(),
+        )
+    };
+    let hashes = prefetch_offline(&build_info).unwrap();
+    assert_eq!(hashes.values().collect::<Vec<_>>(), [fetchgit_hash]);
+
+    let dep = build_info
+        .crates
+        .iter_mut()
+        .find(|c| c.crate_name == "dep")
+        .unwrap();
+    if let ResolvedSource::Git(GitSource { archive_url, .. }) = &mut dep.source {
+        *archive_url = Some("https://github.com/owner/dep/archive/abc123.tar.gz".to_string());
+    }
+    let err = prefetch_offline(&build_info).unwrap_err().to_string();
+    assert!(err.contains("No hash known"), "{}", err);
+
+    env.close();
+}
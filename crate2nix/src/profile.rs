@@ -0,0 +1,124 @@
+//! Settings of the cargo profiles that are passed on to rustc.
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The rustc options for the `release` and the `dev` profile of the workspace.
+///
+/// Only `panic` and `strip` are supported. Since crates with different panic strategies
+/// cannot be linked together, the options are applied to all crates built for the host
+/// platform. Like cargo, build dependencies and proc macros are built without them.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProfileRustcOpts {
+    /// The options for release builds.
+    pub release: Vec<String>,
+    /// The options for debug builds.
+    pub dev: Vec<String>,
+}
+
+impl ProfileRustcOpts {
+    /// Reads the profiles of the first `Cargo.toml` of the given config.
+    pub fn for_config(config: &crate::GenerateConfig) -> Result<ProfileRustcOpts, Error> {
+        match config.cargo_toml.first() {
+            Some(cargo_toml) => ProfileRustcOpts::read_from(cargo_toml),
+            None => Ok(ProfileRustcOpts::default()),
+        }
+    }
+
+    /// Reads the `[profile.release]` and `[profile.dev]` sections of the given manifest.
+    pub fn read_from(cargo_toml: &Path) -> Result<ProfileRustcOpts, Error> {
+        let manifest: toml::Value = match std::fs::read_to_string(cargo_toml) {
+            Ok(content) => content.parse().map_err(|e| {
+                format_err!("while parsing {}: {}", cargo_toml.to_string_lossy(), e)
+            })?,
+            Err(_) => return Ok(ProfileRustcOpts::default()),
+        };
+        let rustc_opts = |name: &str| {
+            let profile = manifest.get("profile").and_then(|p| p.get(name));
+            rustc_opts(profile).map_err(|e| {
+                format_err!(
+                    "while reading profile.{} in {}: {}",
+                    name,
+                    cargo_toml.to_string_lossy(),
+                    e
+                )
+            })
+        };
+        Ok(ProfileRustcOpts {
+            release: rustc_opts("release")?,
+            dev: rustc_opts("dev")?,
+        })
+    }
+}
+
+/// The rustc options for the `panic` and `strip` settings of a profile.
+fn rustc_opts(profile: Option<&toml::Value>) -> Result<Vec<String>, Error> {
+    let mut opts = Vec::new();
+    let setting = |name: &str| profile.and_then(|p| p.get(name));
+
+    match setting("panic") {
+        None => {}
+        Some(toml::Value::String(panic)) if panic == "unwind" => {}
+        Some(toml::Value::String(panic)) if panic == "abort" => {
+            opts.push("-C panic=abort".to_string())
+        }
+        Some(other) => bail!("unsupported panic strategy: {}", other),
+    }
+
+    let strip = match setting("strip") {
+        None | Some(toml::Value::Boolean(false)) => None,
+        Some(toml::Value::Boolean(true)) => Some("symbols"),
+        Some(toml::Value::String(strip)) if strip == "none" => None,
+        Some(toml::Value::String(strip)) if strip == "debuginfo" || strip == "symbols" => {
+            Some(strip.as_str())
+        }
+        Some(other) => bail!("unsupported strip setting: {}", other),
+    };
+    if let Some(strip) = strip {
+        opts.push(format!("-C strip={}", strip));
+    }
+
+    Ok(opts)
+}
+
+#[test]
+fn test_profile_rustc_opts() {
+    let rustc_opts_of = |profile: &str| rustc_opts(Some(&profile.parse().unwrap()));
+    assert_eq!(rustc_opts(None).unwrap(), Vec::<String>::new());
+    assert_eq!(
+        rustc_opts_of("panic = \"abort\"\nstrip = true").unwrap(),
+        vec!["-C panic=abort", "-C strip=symbols"]
+    );
+    assert_eq!(
+        rustc_opts_of("panic = \"unwind\"\nstrip = \"debuginfo\"").unwrap(),
+        vec!["-C strip=debuginfo"]
+    );
+    assert_eq!(
+        rustc_opts_of("strip = \"none\"\nopt-level = 3").unwrap(),
+        Vec::<String>::new()
+    );
+    assert!(rustc_opts_of("panic = \"explode\"").is_err());
+    assert!(rustc_opts_of("strip = 1").is_err());
+}
+
+#[test]
+fn test_read_profiles_from_manifest() {
+    let project = tempdir::TempDir::new("crate2nix_profiles").unwrap();
+    let cargo_toml = project.path().join("Cargo.toml");
+    std::fs::write(
+        &cargo_toml,
+        "[package]\nname = \"aborting\"\n\n[profile.release]\npanic = \"abort\"\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        ProfileRustcOpts::read_from(&cargo_toml).unwrap(),
+        ProfileRustcOpts {
+            release: vec!["-C panic=abort".to_string()],
+            dev: vec![],
+        }
+    );
+
+    project.close().unwrap();
+}
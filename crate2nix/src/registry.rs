@@ -0,0 +1,612 @@
+//! Reading crate metadata from registry indexes.
+//!
+//! Both index protocols are supported:
+//!
+//! * git indexes (`registry+https://...`) are cloned once per run.
+//! * sparse indexes (`sparse+https://...`) are fetched file by file via HTTP.
+//!
+//! See https://doc.rust-lang.org/cargo/reference/registry-index.html
+
+use crate::warnings::{warn, WarningKind};
+use anyhow::{bail, format_err, Context, Error};
+use itertools::Itertools;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use url::Url;
+
+const GIT_INDEX_PREFIX: &str = "registry+";
+const SPARSE_INDEX_PREFIX: &str = "sparse+";
+const CRATES_IO_GIT_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+const CRATES_IO_SPARSE_INDEX: &str = "https://index.crates.io/";
+
+/// The protocol used to access a registry index.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub enum IndexProtocol {
+    /// The index is a git repository.
+    Git,
+    /// The index is served file by file via HTTP.
+    Sparse,
+}
+
+/// The index of a registry as referenced by a package source.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct RegistryIndex {
+    /// The protocol to access the index.
+    pub protocol: IndexProtocol,
+    /// The URL of the index without the protocol prefix.
+    #[serde(with = "url_serde")]
+    pub url: Url,
+}
+
+impl RegistryIndex {
+    /// The sparse index of crates.io.
+    pub fn crates_io() -> RegistryIndex {
+        RegistryIndex {
+            protocol: IndexProtocol::Sparse,
+            url: Url::parse(CRATES_IO_SPARSE_INDEX).expect("valid crates.io index URL"),
+        }
+    }
+
+    /// Returns the registry index for a package source such as
+    /// `registry+https://github.com/rust-lang/crates.io-index` or
+    /// `sparse+https://index.crates.io/`.
+    ///
+    /// Returns `None` for sources which are not registries.
+    pub fn from_source(source: &str) -> Option<Result<RegistryIndex, Error>> {
+        let (protocol, url) = if let Some(url) = source.strip_prefix(GIT_INDEX_PREFIX) {
+            (IndexProtocol::Git, url)
+        } else if let Some(url) = source.strip_prefix(SPARSE_INDEX_PREFIX) {
+            (IndexProtocol::Sparse, url)
+        } else {
+            return None;
+        };
+
+        Some(
+            Url::parse(url)
+                .map(|url| RegistryIndex { protocol, url })
+                .map_err(|e| format_err!("while parsing registry index URL {}: {}", url, e)),
+        )
+    }
+
+    /// Returns the registry index for an `index` of the `[registries]` in a cargo config.
+    ///
+    /// Unlike package sources, the `registry+` prefix of git indexes is optional.
+    pub fn from_index(index: &str) -> Result<RegistryIndex, Error> {
+        match RegistryIndex::from_source(index) {
+            Some(index) => index,
+            None => Url::parse(index)
+                .map(|url| RegistryIndex {
+                    protocol: IndexProtocol::Git,
+                    url,
+                })
+                .map_err(|e| format_err!("while parsing registry index URL {}: {}", index, e)),
+        }
+    }
+
+    /// Whether this is the index of crates.io.
+    pub fn is_crates_io(&self) -> bool {
+        match self.protocol {
+            IndexProtocol::Git => self.url.as_str().trim_end_matches('/') == CRATES_IO_GIT_INDEX,
+            IndexProtocol::Sparse => self.url.as_str() == CRATES_IO_SPARSE_INDEX,
+        }
+    }
+}
+
+impl Display for RegistryIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.protocol {
+            IndexProtocol::Git => write!(f, "{}{}", GIT_INDEX_PREFIX, self.url),
+            IndexProtocol::Sparse => write!(f, "{}{}", SPARSE_INDEX_PREFIX, self.url),
+        }
+    }
+}
+
+/// The `config.json` at the root of a registry index.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct IndexConfig {
+    /// The download URL, potentially with markers like `{crate}` or `{version}`.
+    pub dl: String,
+    /// The API URL of the registry.
+    pub api: Option<String>,
+}
+
+impl IndexConfig {
+    /// Returns the download URL for the given crate version.
+    ///
+    /// ```
+    /// use crate2nix::registry::IndexConfig;
+    /// let config = IndexConfig { dl: "https://example.com/api/v1/crates".to_string(), api: None };
+    /// assert_eq!(
+    ///     config.download_url("serde", &"1.0.0".parse().unwrap(), "abc"),
+    ///     "https://example.com/api/v1/crates/serde/1.0.0/download"
+    /// );
+    /// let config = IndexConfig { dl: "https://example.com/{prefix}/{crate}-{version}.crate".to_string(), api: None };
+    /// assert_eq!(
+    ///     config.download_url("Serde", &"1.0.0".parse().unwrap(), "abc"),
+    ///     "https://example.com/Se/rd/Serde-1.0.0.crate"
+    /// );
+    /// ```
+    pub fn download_url(&self, name: &str, version: &Version, checksum: &str) -> String {
+        const MARKERS: &[&str] = &[
+            "{crate}",
+            "{version}",
+            "{prefix}",
+            "{lowerprefix}",
+            "{sha256-checksum}",
+        ];
+        if !MARKERS.iter().any(|marker| self.dl.contains(marker)) {
+            return format!(
+                "{}/{}/{}/download",
+                self.dl.trim_end_matches('/'),
+                name,
+                version
+            );
+        }
+
+        self.dl
+            .replace("{crate}", name)
+            .replace("{version}", &version.to_string())
+            .replace("{prefix}", &index_prefix(name))
+            .replace("{lowerprefix}", &index_prefix(&name.to_lowercase()))
+            .replace("{sha256-checksum}", checksum)
+    }
+}
+
+/// One line in the index file of a crate, describing one version.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The crate name.
+    pub name: String,
+    /// The version of this entry.
+    pub vers: Version,
+    /// The hex-encoded sha256 of the `.crate` file.
+    pub cksum: String,
+    /// Whether this version was yanked.
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+/// Returns the directory prefix of a crate in the index.
+///
+/// ```
+/// use crate2nix::registry::index_prefix;
+/// assert_eq!(index_prefix("a"), "1");
+/// assert_eq!(index_prefix("ab"), "2");
+/// assert_eq!(index_prefix("abc"), "3/a");
+/// assert_eq!(index_prefix("serde"), "se/rd");
+/// ```
+pub fn index_prefix(name: &str) -> String {
+    match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+/// Reads registry indexes, caching git clones and fetched files for the lifetime of the reader.
+#[derive(Debug, Default)]
+pub struct IndexReader {
+    clones: HashMap<Url, IndexClone>,
+    configs: HashMap<RegistryIndex, IndexConfig>,
+    entries: HashMap<(RegistryIndex, String), Vec<IndexEntry>>,
+}
+
+impl IndexReader {
+    /// Returns the `config.json` of the index.
+    pub fn config(&mut self, index: &RegistryIndex) -> Result<IndexConfig, Error> {
+        if let Some(config) = self.configs.get(index) {
+            return Ok(config.clone());
+        }
+
+        let content = self.read_file(index, "config.json")?;
+        let config: IndexConfig = serde_json::from_str(&content)
+            .map_err(|e| format_err!("while parsing config.json of {}: {}", index, e))?;
+        self.configs.insert(index.clone(), config.clone());
+        Ok(config)
+    }
+
+    /// Returns all index entries, i.e. versions, of the given crate.
+    pub fn entries(&mut self, index: &RegistryIndex, name: &str) -> Result<Vec<IndexEntry>, Error> {
+        let key = (index.clone(), name.to_string());
+        if let Some(entries) = self.entries.get(&key) {
+            return Ok(entries.clone());
+        }
+
+        let lower_name = name.to_lowercase();
+        let path = format!("{}/{}", index_prefix(&lower_name), lower_name);
+        let content = self.read_file(index, &path)?;
+        let entries = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line).map_err(|e| {
+                    format_err!("while parsing index entry for {} in {}: {}", name, index, e)
+                })
+            })
+            .collect::<Result<Vec<IndexEntry>, Error>>()?;
+        self.entries.insert(key, entries.clone());
+        Ok(entries)
+    }
+
+    /// Returns the index entry for the given crate version.
+    pub fn entry(
+        &mut self,
+        index: &RegistryIndex,
+        name: &str,
+        version: &Version,
+    ) -> Result<IndexEntry, Error> {
+        self.entries(index, name)?
+            .into_iter()
+            .find(|entry| &entry.vers == version)
+            .ok_or_else(|| format_err!("{} {} not found in index {}", name, version, index))
+    }
+
+    fn read_file(&mut self, index: &RegistryIndex, path: &str) -> Result<String, Error> {
+        match index.protocol {
+            IndexProtocol::Sparse => {
+                let url = index
+                    .url
+                    .join(path)
+                    .map_err(|e| format_err!("while joining {} to {}: {}", path, index.url, e))?;
+                get_url(&url)
+            }
+            IndexProtocol::Git => {
+                if !self.clones.contains_key(&index.url) {
+                    let clone = IndexClone::new(&index.url)?;
+                    self.clones.insert(index.url.clone(), clone);
+                }
+                let file = self.clones[&index.url].path.join(path);
+                std::fs::read_to_string(&file)
+                    .map_err(|e| format_err!("while reading {} from {}: {}", path, index, e))
+            }
+        }
+    }
+}
+
+/// What to do if yanked crate versions are used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum YankedPolicy {
+    /// Warn about every yanked crate version.
+    #[default]
+    Warn,
+    /// Fail if any yanked crate version is used.
+    Fail,
+    /// Silently allow yanked crate versions without checking the index.
+    Include,
+}
+
+/// Checks the given crate versions for yanked versions according to the policy.
+pub fn check_yanked<'a>(
+    policy: YankedPolicy,
+    reader: &mut IndexReader,
+    crates: impl IntoIterator<Item = (&'a RegistryIndex, &'a str, &'a Version)>,
+) -> Result<(), Error> {
+    if policy == YankedPolicy::Include {
+        return Ok(());
+    }
+
+    let mut yanked = Vec::new();
+    let mut unchecked = Vec::new();
+    for (index, name, version) in crates {
+        match reader.entry(index, name, version) {
+            Ok(entry) if entry.yanked => yanked.push(format!("{} {}", name, version)),
+            Ok(_) => {}
+            Err(e) if policy == YankedPolicy::Fail => return Err(e),
+            Err(_) => unchecked.push(format!("{} {}", name, version)),
+        }
+    }
+
+    if policy == YankedPolicy::Fail && !yanked.is_empty() {
+        bail!(
+            "{} yanked crate version(s) are used: {}",
+            yanked.len(),
+            yanked.join(", ")
+        );
+    }
+
+    for crate_version in &yanked {
+        warn(
+            WarningKind::YankedCrate,
+            format!("{} is yanked.", crate_version),
+        );
+    }
+    if !unchecked.is_empty() {
+        warn(
+            WarningKind::YankedCheckFailed,
+            format!(
+                "Could not check whether {} crate version(s) are yanked, e.g. {}.",
+                unchecked.len(),
+                unchecked.iter().take(10).join(", ")
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// A shallow clone of a git index which is removed when dropped.
+#[derive(Debug)]
+struct IndexClone {
+    path: PathBuf,
+}
+
+impl IndexClone {
+    fn new(url: &Url) -> Result<IndexClone, Error> {
+        let path = unique_temp_dir("crate2nix-index");
+        eprintln!("Cloning registry index {}.", url);
+        let output = Command::new("git")
+            .args(["clone", "--quiet", "--depth", "1", url.as_str()])
+            .arg(&path)
+            .output()
+            .map_err(|e| format_err!("while spawning git clone {}: {}", url, e))?;
+        let clone = IndexClone { path };
+        if !output.status.success() {
+            bail!(
+                "git clone {}\n=> exited with: {}\n{}",
+                url,
+                output.status.code().unwrap_or(-1),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(clone)
+    }
+}
+
+impl Drop for IndexClone {
+    fn drop(&mut self) {
+        if self.path.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&self.path) {
+                eprintln!("Could not remove {}: {}", self.path.to_string_lossy(), e);
+            }
+        }
+    }
+}
+
+fn unique_temp_dir(prefix: &str) -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "{}-{}-{}",
+        prefix,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+fn get_url(url: &Url) -> Result<String, Error> {
+    let output = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            url.as_str(),
+        ])
+        .output()
+        .map_err(|e| format_err!("while spawning curl {}: {}", url, e))?;
+    if !output.status.success() {
+        bail!(
+            "curl {}\n=> exited with: {}\n{}",
+            url,
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    String::from_utf8(output.stdout).context(format!("output of curl {} is not UTF8", url))
+}
+
+/// Returns the registries of the `[registries]` tables in the cargo configs that apply to
+/// `dir`, by name.
+///
+/// As in cargo, the configs in `dir` and its parents take precedence over the config
+/// in `CARGO_HOME`, and the configs closer to `dir` over the ones further up.
+pub fn registry_aliases(dir: &Path) -> Result<BTreeMap<String, RegistryIndex>, Error> {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")));
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let mut cargo_dirs: Vec<PathBuf> = dir.ancestors().map(|d| d.join(".cargo")).collect();
+    cargo_dirs.extend(cargo_home);
+
+    let mut aliases = BTreeMap::new();
+    for cargo_dir in cargo_dirs.iter().rev() {
+        let config_file = match ["config.toml", "config"]
+            .iter()
+            .map(|name| cargo_dir.join(name))
+            .find(|path| path.is_file())
+        {
+            Some(config_file) => config_file,
+            None => continue,
+        };
+        let config: toml::Value = std::fs::read_to_string(&config_file)
+            .map_err(|e| format_err!("while reading {}: {}", config_file.to_string_lossy(), e))?
+            .parse()
+            .map_err(|e| format_err!("while parsing {}: {}", config_file.to_string_lossy(), e))?;
+        let registries = config.get("registries").and_then(|r| r.as_table());
+        for (name, registry) in registries.into_iter().flatten() {
+            if let Some(index) = registry.get("index").and_then(|i| i.as_str()) {
+                aliases.insert(name.clone(), RegistryIndex::from_index(index)?);
+            }
+        }
+    }
+    Ok(aliases)
+}
+
+#[test]
+fn test_registry_aliases() {
+    let project = tempdir::TempDir::new("crate2nix_registry_aliases").unwrap();
+    let crate_dir = project.path().join("crates/some_crate");
+    std::fs::create_dir_all(crate_dir.join(".cargo")).unwrap();
+    std::fs::create_dir_all(project.path().join(".cargo")).unwrap();
+    std::fs::write(
+        project.path().join(".cargo/config.toml"),
+        "[registries]\n\
+         my-registry = { index = \"sparse+https://outer.example.com/index/\" }\n\
+         other = { index = \"https://git.example.com/index\" }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        crate_dir.join(".cargo/config.toml"),
+        "[registries.my-registry]\nindex = \"sparse+https://inner.example.com/index/\"\n",
+    )
+    .unwrap();
+
+    let aliases = registry_aliases(&crate_dir).unwrap();
+    assert_eq!(
+        aliases["my-registry"].to_string(),
+        "sparse+https://inner.example.com/index/"
+    );
+    assert_eq!(aliases["other"].protocol, IndexProtocol::Git);
+    assert_eq!(
+        aliases["other"].to_string(),
+        "registry+https://git.example.com/index"
+    );
+
+    project.close().unwrap();
+}
+
+#[cfg(test)]
+fn write_index_fixture(dir: &std::path::Path) {
+    std::fs::write(
+        dir.join("config.json"),
+        r#"{ "dl": "https://dl.example.com/api/v1/crates", "api": "https://example.com" }"#,
+    )
+    .unwrap();
+    std::fs::create_dir_all(dir.join("so/me")).unwrap();
+    std::fs::write(
+        dir.join("so/me/some_crate"),
+        "{\"name\":\"some_crate\",\"vers\":\"1.0.0\",\"deps\":[],\"cksum\":\"aaaa\",\"features\":{},\"yanked\":false}\n\
+         {\"name\":\"some_crate\",\"vers\":\"1.1.0\",\"deps\":[],\"cksum\":\"bbbb\",\"features\":{},\"yanked\":true}\n",
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_registry_index_from_source() {
+    let git = RegistryIndex::from_source("registry+https://github.com/rust-lang/crates.io-index")
+        .unwrap()
+        .unwrap();
+    assert_eq!(git.protocol, IndexProtocol::Git);
+    assert!(git.is_crates_io());
+
+    let sparse = RegistryIndex::from_source("sparse+https://index.crates.io/")
+        .unwrap()
+        .unwrap();
+    assert_eq!(sparse.protocol, IndexProtocol::Sparse);
+    assert!(sparse.is_crates_io());
+    assert_eq!(sparse.to_string(), "sparse+https://index.crates.io/");
+
+    let other = RegistryIndex::from_source("sparse+https://my-registry.example.com/index/")
+        .unwrap()
+        .unwrap();
+    assert!(!other.is_crates_io());
+
+    assert!(RegistryIndex::from_source("git+https://github.com/kolloch/crate2nix").is_none());
+}
+
+#[test]
+fn test_git_and_sparse_index_resolve_to_same_download_url() {
+    let git_dir = tempdir::TempDir::new("crate2nix_git_index").unwrap();
+    write_index_fixture(git_dir.path());
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .current_dir(git_dir.path())
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    };
+    git(&["init", "--quiet"]);
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "-m", "index"]);
+
+    let sparse_dir = tempdir::TempDir::new("crate2nix_sparse_index").unwrap();
+    write_index_fixture(sparse_dir.path());
+
+    let git_index = RegistryIndex::from_source(&format!(
+        "registry+file://{}",
+        git_dir.path().to_str().unwrap()
+    ))
+    .unwrap()
+    .unwrap();
+    let sparse_index = RegistryIndex::from_source(&format!(
+        "sparse+file://{}/",
+        sparse_dir.path().to_str().unwrap()
+    ))
+    .unwrap()
+    .unwrap();
+
+    let version = Version::parse("1.1.0").unwrap();
+    let mut reader = IndexReader::default();
+    let mut download_url = |index: &RegistryIndex| {
+        let entry = reader.entry(index, "some_crate", &version).unwrap();
+        assert_eq!(entry.cksum, "bbbb");
+        assert!(entry.yanked);
+        reader
+            .config(index)
+            .unwrap()
+            .download_url("some_crate", &version, &entry.cksum)
+    };
+
+    let from_git = download_url(&git_index);
+    let from_sparse = download_url(&sparse_index);
+    assert_eq!(
+        from_git,
+        "https://dl.example.com/api/v1/crates/some_crate/1.1.0/download"
+    );
+    assert_eq!(from_git, from_sparse);
+
+    drop(reader);
+    git_dir.close().unwrap();
+    sparse_dir.close().unwrap();
+}
+
+#[test]
+fn test_check_yanked() {
+    let dir = tempdir::TempDir::new("crate2nix_sparse_index").unwrap();
+    write_index_fixture(dir.path());
+    let index =
+        RegistryIndex::from_source(&format!("sparse+file://{}/", dir.path().to_str().unwrap()))
+            .unwrap()
+            .unwrap();
+    let ok = Version::parse("1.0.0").unwrap();
+    let yanked = Version::parse("1.1.0").unwrap();
+    let crates = vec![(&index, "some_crate", &ok), (&index, "some_crate", &yanked)];
+
+    let mut reader = IndexReader::default();
+    let _lock = crate::warnings::TAKE_LOCK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    // Other tests that run in parallel may report warnings as well.
+    let yanked_warnings = || {
+        crate::warnings::take()
+            .into_iter()
+            .filter(|w| w.kind == WarningKind::YankedCrate)
+            .collect::<Vec<_>>()
+    };
+    yanked_warnings();
+    check_yanked(YankedPolicy::Warn, &mut reader, crates.clone()).unwrap();
+    let reported = yanked_warnings();
+    assert_eq!(reported.len(), 1, "{:?}", reported);
+    assert!(reported[0].message.contains("some_crate 1.1.0"));
+
+    let error = check_yanked(YankedPolicy::Fail, &mut reader, crates.clone())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("some_crate 1.1.0"), "{}", error);
+    assert!(!error.contains("1.0.0"), "{}", error);
+
+    check_yanked(YankedPolicy::Include, &mut reader, crates).unwrap();
+    assert!(yanked_warnings().is_empty());
+
+    assert_eq!(YankedPolicy::default(), YankedPolicy::Warn);
+
+    drop(reader);
+    dir.close().unwrap();
+}
@@ -4,7 +4,8 @@ use std::io::{BufRead, Write};
 use std::path::Path;
 use std::{fs::File, iter};
 
-use crate::{BuildInfo, GenerateInfo};
+use crate::rust_platform::RustPlatformBuild;
+use crate::{BuildInfo, FetchurlSources, GenerateInfo};
 use anyhow::format_err;
 use anyhow::{bail, Error};
 use cargo_platform::{Cfg, CfgExpr};
@@ -29,9 +30,15 @@ macro_rules! template {
 /// The template for generating Cargo.nix.
 pub const CARGO_NIX: Template<BuildInfo> = template!("Cargo.nix.tera");
 
+/// The template for generating Cargo.nix with the `rustplatform` backend.
+pub const RUST_PLATFORM_NIX: Template<RustPlatformBuild> = template!("Cargo.rustplatform.nix.tera");
+
 /// Included in build.nix.tera
 const DEFAULT_NIX: Template<()> = template!("nix/crate2nix/default.nix");
 
+/// The template for listing the `fetchurl` sources of all crates.
+pub const FETCHURL_SOURCES_NIX: Template<FetchurlSources> = template!("fetchurl-sources.nix.tera");
+
 /// The template for generating a directory with all out of tree sources.
 pub const SOURCES_NIX: Template<GenerateInfo> = template!("crate2nix-sources.nix.tera");
 
@@ -82,9 +89,13 @@ impl<C: Serialize + Debug> Template<C> {
     }
 
     /// Writes the rendered template to the given file path.
+    ///
+    /// The file is replaced atomically so that it is never left partially written.
     pub fn write_to_file(&self, path: impl AsRef<Path>, context: &C) -> Result<(), Error> {
-        let mut output_file = File::create(&path)?;
-        output_file.write_all(self.render(context)?.as_bytes())?;
+        let rendered = self.render(context)?;
+        crate::util::write_atomically(&path, |output_file| {
+            Ok(output_file.write_all(rendered.as_bytes())?)
+        })?;
         println!(
             "Generated {} successfully.",
             path.as_ref().to_string_lossy()
@@ -131,7 +142,13 @@ impl<C: Serialize + Debug> AbstractTemplate for Template<C> {
     }
 }
 
-const TEMPLATES: &[&'static dyn AbstractTemplate] = &[&CARGO_NIX, &DEFAULT_NIX, &SOURCES_NIX];
+const TEMPLATES: &[&'static dyn AbstractTemplate] = &[
+    &CARGO_NIX,
+    &RUST_PLATFORM_NIX,
+    &DEFAULT_NIX,
+    &SOURCES_NIX,
+    &FETCHURL_SOURCES_NIX,
+];
 
 fn create_tera() -> Tera {
     let mut tera = Tera::default();
@@ -160,6 +177,12 @@ fn create_tera() -> Tera {
     tera.autoescape_on(vec![".nix.tera", ".nix"]);
     tera.set_escape_fn(escape_nix_string);
     tera.register_filter("cfg_to_nix_expr", cfg_to_nix_expr_filter);
+    tera.register_filter("spdx_to_nix_licenses", spdx_to_nix_licenses_filter);
+    tera.register_filter("nix_path", nix_path_filter);
+    tera.register_filter(
+        "nix_string_with_interpolations",
+        nix_string_with_interpolations_filter,
+    );
     tera
 }
 
@@ -176,7 +199,7 @@ fn cfg_to_nix_expr_filter(
             if key.starts_with("cfg(") && key.ends_with(')') {
                 let cfg = &key[4..key.len() - 1];
 
-                let expr = CfgExpr::from_str(&cfg).map_err(|e| {
+                let expr = CfgExpr::from_str(cfg).map_err(|e| {
                     tera::Error::msg(format!(
                         "cfg_to_nix_expr_filter: Could not parse '{}': {}",
                         cfg, e
@@ -197,6 +220,54 @@ fn cfg_to_nix_expr_filter(
     }
 }
 
+/// Renders an SPDX license expression as a nix list of licenses.
+fn spdx_to_nix_licenses_filter(
+    value: &tera::Value,
+    _args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    match value {
+        tera::Value::String(spdx) => Ok(tera::Value::String(format!(
+            "[ {} ]",
+            crate::licenses::nix_licenses(spdx).join(" ")
+        ))),
+        _ => Err(tera::Error::msg(format!(
+            "spdx_to_nix_licenses_filter: Expected string, got {:?}",
+            value
+        ))),
+    }
+}
+
+/// Renders a path as nix path expression, see `nix_path`.
+fn nix_path_filter(
+    value: &tera::Value,
+    _args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    match value {
+        tera::Value::String(path) => Ok(tera::Value::String(nix_path(path))),
+        _ => Err(tera::Error::msg(format!(
+            "nix_path_filter: Expected string, got {:?}",
+            value
+        ))),
+    }
+}
+
+/// Renders a string as nix string but keeps the `${...}` interpolations.
+fn nix_string_with_interpolations_filter(
+    value: &tera::Value,
+    _args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    match value {
+        tera::Value::String(raw_string) => {
+            let escaped = raw_string.replace('\\', "\\\\").replace('"', "\\\"");
+            Ok(tera::Value::String(format!("\"{}\"", escaped)))
+        }
+        _ => Err(tera::Error::msg(format!(
+            "nix_string_with_interpolations_filter: Expected string, got {:?}",
+            value
+        ))),
+    }
+}
+
 /// Renders a config expression to nix code.
 fn cfg_to_nix_expr(cfg: &CfgExpr) -> String {
     fn target(target_name: &str) -> String {
@@ -288,6 +359,82 @@ fn test_render_cfg_to_nix_expr() {
     );
 }
 
+#[test]
+fn test_render_target_features_by_target() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("main").make_root();
+
+    let mut crate2nix_json = crate::config::Config::default();
+    crate2nix_json.target_features.insert(
+        "x86_64-unknown-linux-gnu".to_string(),
+        vec!["avx2".to_string(), "fma".to_string()],
+    );
+    crate2nix_json.target_features.insert(
+        "aarch64-unknown-linux-gnu".to_string(),
+        vec!["neon".to_string()],
+    );
+
+    let rendered = CARGO_NIX.render(&env.build_info(crate2nix_json)).unwrap();
+    let expected = r#"
+, targetFeatures ? {
+    "aarch64-unknown-linux-gnu" = [ "neon" ];
+    "x86_64-unknown-linux-gnu" = [ "avx2" "fma" ];
+  }.${stdenv.hostPlatform.config} or []
+"#;
+    assert!(
+        rendered.contains(expected),
+        "target features not selected by target:\n{}",
+        rendered
+    );
+
+    let unconfigured = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    assert!(unconfigured.contains("\n, targetFeatures ? []\n"));
+    assert!(!unconfigured.contains("neon"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_rust_platform() {
+    let build = RustPlatformBuild {
+        pname: "main".to_string(),
+        version: "0.1.0".to_string(),
+        src: "./.".into(),
+        cargo_hash: "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string(),
+        info: GenerateInfo::default(),
+        config: crate::test::generate_config(),
+    };
+
+    let rendered = RUST_PLATFORM_NIX.render(&build).unwrap();
+    let expected = r#"
+rustPlatform.buildRustPackage {
+  pname = "main";
+  version = "0.1.0";
+  src = lib.cleanSource ./.;
+  # Only needed for nixpkgs releases that still default to `fetchCargoTarball`.
+  useFetchCargoVendor = true;
+  cargoHash = "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+}
+"#;
+    assert!(rendered.contains(expected), "{}", rendered);
+    assert!(rendered.contains("@generated by crate2nix"));
+    assert_eq!(rendered.matches("sha256-").count(), 1, "{}", rendered);
+    assert!(!rendered.contains("buildRustCrate"), "{}", rendered);
+
+    let build = RustPlatformBuild {
+        src: "../my project".into(),
+        ..build
+    };
+    let rendered = RUST_PLATFORM_NIX.render(&build).unwrap();
+    assert!(
+        rendered.contains("\n  src = lib.cleanSource (./. + \"/../my project\");\n"),
+        "{}",
+        rendered
+    );
+}
+
 /// Escapes a string as a nix string.
 ///
 /// ```
@@ -311,3 +458,1081 @@ pub fn escape_nix_string(raw_string: &str) -> String {
     ret.push('"');
     ret
 }
+
+/// Renders a relative path like `./.` or `../sub dir` or an absolute path as nix path
+/// expression.
+///
+/// Paths with characters that nix path literals do not allow, e.g. spaces, are appended as
+/// string to `./.` or `/.` in parentheses.
+///
+/// ```
+/// use crate2nix::render::nix_path;
+/// assert_eq!("./.", nix_path("./."));
+/// assert_eq!("../crates/foo-bar_1.0", nix_path("../crates/foo-bar_1.0"));
+/// assert_eq!("/abs/path", nix_path("/abs/path"));
+/// assert_eq!("(./. + \"/../sub dir\")", nix_path("../sub dir"));
+/// assert_eq!("(./. + \"/a b\")", nix_path("./a b"));
+/// assert_eq!("(/. + \"/abs/a \\${b}\")", nix_path("/abs/a ${b}"));
+/// ```
+pub fn nix_path(path: &str) -> String {
+    let is_literal = (path.starts_with("./") || path.starts_with("../") || path.starts_with('/'))
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "./_-+".contains(c));
+    if is_literal {
+        return path.to_string();
+    }
+    match path.strip_prefix('/') {
+        Some(absolute) => format!("(/. + {})", escape_nix_string(&format!("/{}", absolute))),
+        None => format!(
+            "(./. + {})",
+            escape_nix_string(&format!("/{}", path.strip_prefix("./").unwrap_or(path)))
+        ),
+    }
+}
+
+/// The attributes of the crate with the given name in a rendered Cargo.nix.
+#[cfg(test)]
+fn crate_entry(rendered: &str, name: &str) -> String {
+    let start = rendered
+        .find(&format!("crateName = \"{}\";", name))
+        .unwrap();
+    let end = start + rendered[start..].find("\n      };").unwrap();
+    rendered[start..end].to_string()
+}
+
+#[test]
+fn test_render_target_dev_dependency() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("proptest")
+        .version_and_package_id("1.0.0")
+        .update_package_dep(|d| {
+            d.kind = cargo_metadata::DependencyKind::Development;
+            d.target = Some(cargo_platform::Platform::from_str("cfg(unix)").unwrap());
+        });
+
+    let build_info = env.build_info(crate::config::Config::default());
+    let main = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "main")
+        .unwrap();
+    assert!(main.dependencies.is_empty());
+    assert_eq!(main.dev_dependencies.len(), 1);
+
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    let expected = r#"
+        devDependencies = [
+          {
+            name = "proptest";
+            packageId = "proptest";
+            target = {target, features}: (target."unix" or false);
+          }
+        ];
+"#;
+    assert!(
+        rendered.contains(expected),
+        "dev dependency target not rendered:\n{}",
+        rendered
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_optional_build_dependency() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("bindgen")
+        .version_and_package_id("0.59.0")
+        .update_package_dep(|d| {
+            d.kind = cargo_metadata::DependencyKind::Build;
+            d.optional = true;
+        });
+
+    let build_info = env.build_info(crate::config::Config::default());
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    let expected = r#"
+        buildDependencies = [
+          {
+            name = "bindgen";
+            packageId = "bindgen";
+            optional = true;
+          }
+        ];
+"#;
+    assert!(
+        rendered.contains(expected),
+        "optional build dependency not rendered:\n{}",
+        rendered
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_post_patch() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("vendored")
+        .version_and_package_id("1.0.0");
+
+    let mut crate2nix_json = crate::config::Config::default();
+    crate2nix_json.post_patch.insert(
+        "vendored".to_string(),
+        "sed -i '/^\\[workspace\\]/d' Cargo.toml".to_string(),
+    );
+
+    let rendered = CARGO_NIX.render(&env.build_info(crate2nix_json)).unwrap();
+    assert!(
+        rendered.contains("        postPatch = \"sed -i '/^\\\\[workspace\\\\]/d' Cargo.toml\";\n"),
+        "postPatch not rendered:\n{}",
+        rendered
+    );
+    assert_eq!(rendered.matches("postPatch = ").count(), 1, "{}", rendered);
+
+    env.close();
+}
+
+#[test]
+fn test_render_pre_configure_and_pre_build() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("codegen")
+        .version_and_package_id("1.0.0");
+
+    let mut crate2nix_json = crate::config::Config::default();
+    crate2nix_json
+        .pre_configure
+        .insert("codegen".to_string(), "export PROTOC=protoc".to_string());
+    crate2nix_json
+        .pre_build
+        .insert("codegen".to_string(), "protoc --version".to_string());
+
+    let rendered = CARGO_NIX.render(&env.build_info(crate2nix_json)).unwrap();
+    let expected = r#"
+        preConfigure = "export PROTOC=protoc";
+        preBuild = "protoc --version";
+"#;
+    assert!(
+        rendered.contains(expected),
+        "hooks not rendered:\n{}",
+        rendered
+    );
+    assert_eq!(
+        rendered.matches("preConfigure = ").count(),
+        1,
+        "{}",
+        rendered
+    );
+    assert_eq!(rendered.matches("preBuild = ").count(), 1, "{}", rendered);
+
+    env.close();
+}
+
+#[test]
+fn test_render_nix_cflags_compile_and_ldflags() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("zstd-sys")
+        .version_and_package_id("2.0.0");
+
+    let mut crate2nix_json = crate::config::Config::default();
+    crate2nix_json.crate_nix_cflags_compile.insert(
+        "zstd-sys".to_string(),
+        "-I${pkgs.zstd.dev}/include -DNAME=\"zstd\"".to_string(),
+    );
+    crate2nix_json.nix_ldflags = Some("-L${pkgs.zstd.out}/lib".to_string());
+    crate2nix_json
+        .crate_nix_ldflags
+        .insert("zstd-sys".to_string(), "-lzstd".to_string());
+
+    let rendered = CARGO_NIX.render(&env.build_info(crate2nix_json)).unwrap();
+    let expected = r#"
+        NIX_CFLAGS_COMPILE = "-I${pkgs.zstd.dev}/include -DNAME=\"zstd\"";
+        NIX_LDFLAGS = "-L${pkgs.zstd.out}/lib -lzstd";
+"#;
+    assert!(
+        rendered.contains(expected),
+        "flags not rendered:\n{}",
+        rendered
+    );
+    assert_eq!(
+        rendered.matches("NIX_CFLAGS_COMPILE = ").count(),
+        1,
+        "{}",
+        rendered
+    );
+    assert!(rendered.contains("\n        NIX_LDFLAGS = \"-L${pkgs.zstd.out}/lib\";\n"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_platforms() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("linux-only")
+        .version_and_package_id("1.0.0");
+    main.add_dependency("no-darwin")
+        .version_and_package_id("1.0.0");
+
+    let mut crate2nix_json = crate::config::Config::default();
+    crate2nix_json.platforms.insert(
+        "linux-only".to_string(),
+        crate::config::Platforms {
+            platforms: vec!["x86_64-linux".to_string(), "aarch64-linux".to_string()],
+            bad_platforms: vec![],
+        },
+    );
+    crate2nix_json.platforms.insert(
+        "no-darwin".to_string(),
+        crate::config::Platforms {
+            platforms: vec![],
+            bad_platforms: vec!["x86_64-darwin".to_string()],
+        },
+    );
+
+    let rendered = CARGO_NIX.render(&env.build_info(crate2nix_json)).unwrap();
+    assert!(
+        rendered.contains("        meta.platforms = [ \"x86_64-linux\" \"aarch64-linux\" ];\n"),
+        "meta.platforms not rendered:\n{}",
+        rendered
+    );
+    assert!(rendered.contains(
+        "        meta.platforms = lib.platforms.all;\n        meta.badPlatforms = [ \"x86_64-darwin\" ];\n"
+    ));
+    assert_eq!(
+        rendered.matches("meta.platforms = ").count(),
+        2,
+        "{}",
+        rendered
+    );
+    assert_eq!(rendered.matches("meta.badPlatforms = ").count(), 1);
+
+    env.close();
+}
+
+#[test]
+fn test_render_git_archive() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("gitea-crate")
+        .version_and_package_id("1.0.0")
+        .update_package(|p| {
+            p.source = Some(cargo_metadata::Source {
+                repr: "git+https://gitea.example.org/team/gitea-crate?rev=abc123#abc123"
+                    .to_string(),
+            })
+        });
+    main.add_dependency("cloned-crate")
+        .version_and_package_id("1.0.0")
+        .update_package(|p| {
+            p.source = Some(cargo_metadata::Source {
+                repr: "git+https://git.unknown.org/team/cloned-crate#def456".to_string(),
+            })
+        });
+
+    let crate2nix_json = crate::config::Config {
+        git_archives: Some(crate::config::GitArchives {
+            patterns: vec![(
+                "gitea.example.org".to_string(),
+                "https://{host}/{owner}/{repo}/archive/{rev}.tar.gz".to_string(),
+            )]
+            .into_iter()
+            .collect(),
+        }),
+        ..Default::default()
+    };
+    let rendered = CARGO_NIX.render(&env.build_info(crate2nix_json)).unwrap();
+    assert!(
+        rendered.contains(
+            "        src = pkgs.fetchzip {\n          \
+             url = \"https://gitea.example.org/team/gitea-crate/archive/abc123.tar.gz\";\n"
+        ),
+        "archive not rendered:\n{}",
+        rendered
+    );
+    assert!(rendered.contains(
+        "        src = pkgs.fetchgit {\n          url = \"https://git.unknown.org/team/cloned-crate\";\n"
+    ));
+
+    let without_archives = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    assert!(!without_archives.contains("fetchzip"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_custom_unpack() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("packed")
+        .version_and_package_id("1.0.0");
+
+    let mut crate2nix_json = crate::config::Config::default();
+    crate2nix_json.unpack.insert(
+        "packed".to_string(),
+        crate::config::Unpack {
+            unpack_cmd: Some("tar --zstd -xf $curSrc".to_string()),
+            source_root: Some("packed-1.0.0".to_string()),
+        },
+    );
+
+    let rendered = CARGO_NIX.render(&env.build_info(crate2nix_json)).unwrap();
+    assert!(
+        rendered.contains("        unpackCmd = \"tar --zstd -xf $curSrc\";\n"),
+        "unpackCmd not rendered:\n{}",
+        rendered
+    );
+    assert!(rendered.contains("        sourceRoot = \"packed-1.0.0\";\n"));
+    assert_eq!(rendered.matches("unpackCmd = ").count(), 1, "{}", rendered);
+
+    let unconfigured = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    assert!(!unconfigured.contains("unpackCmd"));
+    assert!(!unconfigured.contains("sourceRoot"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_old_nixpkgs_compat() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    let crate_dir = main
+        .get_package()
+        .manifest_path
+        .parent()
+        .unwrap()
+        .to_owned();
+    std::fs::create_dir_all(crate_dir.join("src")).unwrap();
+    std::fs::write(crate_dir.join("src/main.rs"), "fn main() {}").unwrap();
+    let bin_target = |name: &str, src_path: std::path::PathBuf| {
+        serde_json::from_value(serde_json::json!({
+            "kind": ["bin"],
+            "crate_types": ["bin"],
+            "name": name,
+            "src_path": src_path,
+            "edition": "2018",
+            "doctest": false
+        }))
+        .unwrap()
+    };
+    main.update_package(|p| {
+        p.targets = vec![bin_target("main", crate_dir.join("src/main.rs").into())]
+    });
+    main.add_dependency("tool").update_package_and_node(|tool| {
+        let tool_dir = tool
+            .get_package()
+            .manifest_path
+            .parent()
+            .unwrap()
+            .to_owned();
+        std::fs::create_dir_all(tool_dir.join("src")).unwrap();
+        std::fs::write(tool_dir.join("src/main.rs"), "fn main() {}").unwrap();
+        tool.update_package(|p| {
+            p.targets = vec![bin_target("tool", tool_dir.join("src/main.rs").into())]
+        });
+    });
+
+    let current = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    assert!(current.contains("edition = \"2015\";"), "{}", current);
+    assert!(current.contains("crateBin = ["), "{}", current);
+    assert!(current.contains("crateBin = [];"), "{}", current);
+    assert!(!current.contains("postUnpack"), "{}", current);
+
+    let mut old = env.build_info(crate::config::Config::default());
+    old.build_rust_crate_attributes = crate::nixpkgs_compat::BuildRustCrateAttributes::for_release(
+        Some(&"18.09".parse().unwrap()),
+    );
+    let old = CARGO_NIX.render(&old).unwrap();
+    assert!(!old.contains("edition ="), "{}", old);
+    // The default.nix part refers to `crateBin` of the crate configs.
+    assert!(!old.contains("crateBin = "), "{}", old);
+    assert!(old.contains("crateName = \"main\";"), "{}", old);
+    // Only the binaries of the dependency are removed.
+    assert_eq!(
+        old.matches(
+            "\n        postUnpack = \"rm -rf $sourceRoot/src/main.rs $sourceRoot/src/bin\";\n"
+        )
+        .count(),
+        1,
+        "{}",
+        old
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_crate_bin_with_custom_names_and_paths() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    let crate_dir = main
+        .get_package()
+        .manifest_path
+        .parent()
+        .unwrap()
+        .to_owned();
+    let crate_dir = std::path::PathBuf::from(crate_dir.as_str())
+        .canonicalize()
+        .unwrap();
+    for src_path in ["bin/server.rs", "tools/cli/main.rs"] {
+        let src_path = crate_dir.join(src_path);
+        std::fs::create_dir_all(src_path.parent().unwrap()).unwrap();
+        std::fs::write(src_path, "fn main() {}").unwrap();
+    }
+    main.update_package(|p| {
+        p.targets = [("srv", "bin/server.rs"), ("cli-tool", "tools/cli/main.rs")]
+            .iter()
+            .map(|(name, src_path)| {
+                serde_json::from_value(serde_json::json!({
+                    "kind": ["bin"],
+                    "crate_types": ["bin"],
+                    "name": name,
+                    "src_path": crate_dir.join(src_path),
+                    "edition": "2018",
+                    "doctest": false
+                }))
+                .unwrap()
+            })
+            .collect();
+    });
+
+    let rendered = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    let expected = r#"
+        crateBin = [
+          { name = "srv"; path = "bin/server.rs"; }
+          { name = "cli-tool"; path = "tools/cli/main.rs"; }
+        ];
+"#;
+    assert!(
+        rendered.contains(expected),
+        "crateBin not rendered:\n{}",
+        rendered
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_build_rust_crate_path() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("main").make_root();
+
+    let default = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    assert!(default.contains("    else pkgs: pkgs.buildRustCrate\n"));
+    assert!(!default.contains("buildRustCrateSrc"));
+
+    let mut build_info = env.build_info(crate::config::Config::default());
+    build_info.config.build_rust_crate_path = Some("./nix/build-rust-crate".to_string());
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(
+        rendered.contains(", buildRustCrateSrc ? ./nix/build-rust-crate\n"),
+        "buildRustCrate path not rendered:\n{}",
+        rendered
+    );
+    assert!(rendered.contains("    else pkgs: pkgs.callPackage buildRustCrateSrc { }\n"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_default_run() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    let crate_dir = main
+        .get_package()
+        .manifest_path
+        .parent()
+        .unwrap()
+        .to_owned();
+    std::fs::write(
+        crate_dir.join("Cargo.toml"),
+        "[package]\nname = \"main\"\nversion = \"0.1.0\"\ndefault-run = \"cli\"\n",
+    )
+    .unwrap();
+    std::fs::create_dir_all(crate_dir.join("src/bin")).unwrap();
+    let bin = |name: &str| {
+        let src_path = crate_dir.join(format!("src/bin/{}.rs", name));
+        std::fs::write(&src_path, "fn main() {}").unwrap();
+        serde_json::from_value(serde_json::json!({
+            "kind": ["bin"],
+            "crate_types": ["bin"],
+            "name": name,
+            "src_path": src_path,
+            "edition": "2018",
+            "doctest": false
+        }))
+        .unwrap()
+    };
+    let targets = vec![bin("main"), bin("cli")];
+    main.update_package(|p| p.targets = targets);
+
+    let build_info = env.build_info(crate::config::Config::default());
+    let main = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "main")
+        .unwrap();
+    assert_eq!(main.default_run.as_deref(), Some("cli"));
+
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(
+        rendered.contains("        meta.mainProgram = \"cli\";\n"),
+        "mainProgram not rendered:\n{}",
+        rendered
+    );
+    assert_eq!(rendered.matches("mainProgram").count(), 1, "{}", rendered);
+    assert!(!rendered.contains("appsBySystem"), "{}", rendered);
+
+    let rendered = CARGO_NIX
+        .render(&env.build_info(crate::config::Config {
+            systems: vec!["x86_64-linux".to_string()],
+            ..Default::default()
+        }))
+        .unwrap();
+    assert!(
+        rendered.contains("      program = \"${packages.default}/bin/\" + \"cli\";\n"),
+        "default app not rendered:\n{}",
+        rendered
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_package_metadata() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("main")
+        .make_root()
+        .update_package(|p| {
+            p.description = Some("Does \"main\" things".to_string());
+            p.homepage = Some("https://example.com".to_string());
+            p.license = Some("MIT".to_string());
+        })
+        .add_dependency("dep")
+        .version_and_package_id("1.0.0")
+        .update_package(|p| p.repository = Some("https://example.com/dep.git".to_string()));
+
+    let mut build_info = env.build_info(crate::config::Config::default());
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    let main = crate_entry(&rendered, "main");
+    for attr in [
+        "\n        description = \"Does \\\"main\\\" things\";\n",
+        "\n        homepage = \"https://example.com\";\n",
+        "\n        license = \"MIT\";\n",
+    ] {
+        assert!(main.contains(attr), "{} missing:\n{}", attr, main);
+    }
+    assert!(!main.contains("repository = "), "{}", main);
+    let dep = crate_entry(&rendered, "dep");
+    assert!(
+        dep.contains("\n        repository = \"https://example.com/dep.git\";\n"),
+        "{}",
+        dep
+    );
+    assert!(!dep.contains("description = "), "{}", dep);
+
+    build_info.build_rust_crate_attributes =
+        crate::nixpkgs_compat::BuildRustCrateAttributes::for_release(Some(
+            &"22.11".parse().unwrap(),
+        ));
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    let main = crate_entry(&rendered, "main");
+    assert!(!main.contains("description = "), "{}", main);
+    assert!(!main.contains("homepage = "), "{}", main);
+
+    env.close();
+}
+
+#[test]
+fn test_render_check_inputs() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("main")
+        .make_root()
+        .add_dependency("dep")
+        .version_and_package_id("1.0.0");
+
+    let crate2nix_json = crate::config::Config {
+        check_inputs: vec!["cowsay".to_string()],
+        crate_check_inputs: vec![
+            ("main".to_string(), vec!["protobuf".to_string()]),
+            ("dep".to_string(), vec!["hello".to_string()]),
+        ]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    };
+    let rendered = CARGO_NIX.render(&env.build_info(crate2nix_json)).unwrap();
+    assert!(
+        rendered.contains("checkInputs = [ pkgs.cowsay pkgs.protobuf ];"),
+        "check inputs not rendered:\n{}",
+        rendered
+    );
+    assert!(!rendered.contains("pkgs.hello"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_packages_by_system() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("main").make_root();
+
+    let crate2nix_json = crate::config::Config {
+        systems: vec![
+            "x86_64-linux".to_string(),
+            "aarch64-linux".to_string(),
+            "x86_64-darwin".to_string(),
+        ],
+        ..Default::default()
+    };
+    let mut build_info = env.build_info(crate2nix_json);
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(
+        rendered.contains(
+            "  packagesBySystem = lib.genAttrs [ \"x86_64-linux\" \"aarch64-linux\" \"x86_64-darwin\" ] (system:\n"
+        ),
+        "systems not rendered:\n{}",
+        rendered
+    );
+    assert!(
+        rendered.contains("      cargoNix = import (./. + \"/Cargo.nix\") {\n"),
+        "{}",
+        rendered
+    );
+    assert!(rendered.contains("      default = cargoNix.rootCrate.build;\n"));
+    for expected in [
+        "        else (import pkgs.path {\n          inherit (pkgs) config overlays;\n          localSystem = system;\n        });\n",
+        "        inherit nixpkgs buildRustCrateForPkgs defaultCrateOverrides rootFeatures",
+    ] {
+        assert!(rendered.contains(expected), "{} missing:\n{}", expected, rendered);
+    }
+    assert!(!rendered.contains("crossSystem"));
+
+    build_info.config.libc = crate::Libc::Musl;
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(
+        rendered.contains("          localSystem = system;\n        }).pkgsStatic;\n"),
+        "{}",
+        rendered
+    );
+
+    let rendered = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    assert!(!rendered.contains("packagesBySystem"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_artifact_dependency() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("codegen-tool")
+        .version_and_package_id("0.1.0")
+        .update_package_dep(|d| d.kind = cargo_metadata::DependencyKind::Build);
+    let manifest_path = main.get_package().manifest_path.clone();
+    std::fs::write(
+        &manifest_path,
+        "[package]\nname = \"main\"\nversion = \"0.1.0\"\n\n\
+         [build-dependencies]\ncodegen-tool = { path = \"../tool\", artifact = \"bin\" }\n",
+    )
+    .unwrap();
+
+    let mut config = crate::test::generate_config();
+    config.artifact_dependencies = true;
+    let build_info = crate::BuildInfo::new(
+        &GenerateInfo::default(),
+        &config,
+        crate::config::Config::default(),
+        env.indexed_metadata(),
+    )
+    .unwrap();
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    let expected = r#"
+        buildDependencies = [
+          {
+            name = "codegen-tool";
+            packageId = "codegen-tool";
+            artifact = { kinds = [ "bin" ]; };
+          }
+        ];
+"#;
+    assert!(
+        rendered.contains(expected),
+        "artifact dependency not rendered:\n{}",
+        rendered
+    );
+
+    let without_flag = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    assert!(!without_flag.contains("artifact = {"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_all_attribute() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("cli").make_workspace_member();
+    env.add_package_and_node("server").make_workspace_member();
+    env.add_package_and_node("tool").make_workspace_member();
+
+    let mut build_info = env.build_info(crate::config::Config::default());
+    build_info.config.all_attribute = true;
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    let expected = r#"
+  all = pkgs.symlinkJoin {
+      name = "all";
+      paths = [
+        workspaceMembers."cli".build
+        workspaceMembers."server".build
+        workspaceMembers."tool".build
+      ];
+  };
+"#;
+    assert!(
+        rendered.contains(expected),
+        "all members not joined:\n{}",
+        rendered
+    );
+
+    build_info.config.all_attribute = false;
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(!rendered.contains("  all = "));
+
+    env.close();
+}
+
+#[test]
+fn test_render_all_attribute_with_default_members() {
+    let mut env = crate::test::MetadataEnv::default();
+    let member_dir = |package: &cargo_metadata::Package| {
+        std::path::PathBuf::from(package.manifest_path.parent().unwrap().as_str())
+    };
+    let cli_dir = member_dir(
+        env.add_package_and_node("cli")
+            .make_workspace_member()
+            .get_package(),
+    );
+    env.add_package_and_node("xtask").make_workspace_member();
+
+    let workspace_dir = env.temp_dir();
+    let cargo_toml = workspace_dir.join("Cargo.toml");
+    std::fs::write(
+        &cargo_toml,
+        format!(
+            "[workspace]\nmembers = []\ndefault-members = [{:?}]\n",
+            cli_dir.to_string_lossy()
+        ),
+    )
+    .unwrap();
+
+    let mut config = crate::test::generate_config();
+    config.cargo_toml = vec![cargo_toml];
+    config.all_attribute = true;
+    let build_info = crate::BuildInfo::new(
+        &GenerateInfo::default(),
+        &config,
+        crate::config::Config::default(),
+        env.indexed_metadata(),
+    )
+    .unwrap();
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(
+        rendered.contains("      paths = [\n        workspaceMembers.\"cli\".build\n      ];\n"),
+        "default members not used:\n{}",
+        rendered
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_musl() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("main").make_root();
+
+    let mut build_info = env.build_info(crate::config::Config::default());
+    build_info.config.libc = crate::Libc::Musl;
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(
+        rendered.contains("\n, pkgs ? (import nixpkgs { config = {}; }).pkgsStatic\n"),
+        "static package set not used:\n{}",
+        rendered
+    );
+
+    build_info.config.libc = crate::Libc::Glibc;
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(rendered.contains("\n, pkgs ? import nixpkgs { config = {}; }\n"));
+    assert!(!rendered.contains("pkgsStatic"));
+
+    env.close();
+}
+
+#[test]
+fn test_render_shared_dependencies() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("cli").make_workspace_member();
+    env.add_package_and_node("server").make_workspace_member();
+
+    let rendered = CARGO_NIX
+        .render(&env.build_info(crate::config::Config::default()))
+        .unwrap();
+    assert!(rendered.contains(", shareDependencies ? false\n"));
+    assert!(rendered.contains("unifyFeaturesWith = internal.sharedDependenciesPackageIds;"));
+    assert!(
+        rendered.contains("    sharedDependenciesPackageIds = lib.optionals shareDependencies [\n"),
+        "workspace members not listed:\n{}",
+        rendered
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_profile_rustc_opts() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("dep").version_and_package_id("1.0.0");
+    main.add_dependency("other_dep")
+        .version_and_package_id("2.0.0");
+
+    let mut build_info = env.build_info(crate::config::Config::default());
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(!rendered.contains("        extraRustcOpts = "));
+
+    assert!(rendered.contains("\n    profileRustcOpts = [ ];\n"));
+
+    build_info.profile_rustc_opts.release = vec!["-C panic=abort".to_string()];
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    let expected = r#"
+    profileRustcOpts = if release
+      then [ "-C panic=abort" ]
+      else [ ];
+"#;
+    assert!(
+        rendered.contains(expected),
+        "panic strategy not set:\n{}",
+        rendered
+    );
+    // The builder adds the options only to the crates built for the host.
+    assert!(!rendered.contains("        extraRustcOpts = "));
+
+    env.close();
+}
+
+#[test]
+fn test_render_fetchurl_sources() {
+    let crates_io = || cargo_metadata::Source {
+        repr: "registry+https://github.com/rust-lang/crates.io-index".to_string(),
+    };
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("zeta")
+        .version_and_package_id("0.2.0")
+        .update_package(|p| p.source = Some(crates_io()));
+    main.add_dependency("alpha")
+        .version_and_package_id("1.0.0")
+        .update_package(|p| p.source = Some(crates_io()));
+
+    let mut build_info = env.build_info(crate::config::Config::default());
+    for crate_derivation in build_info.crates.iter_mut() {
+        crate_derivation.source = crate_derivation
+            .source
+            .with_sha256(format!("sha256-of-{}", crate_derivation.crate_name));
+    }
+    let sources = crate::FetchurlSources {
+        info: GenerateInfo::default(),
+        sources: build_info.fetchurl_sources().unwrap(),
+    };
+    let rendered = FETCHURL_SOURCES_NIX.render(&sources).unwrap();
+    let expected = r#"
+[
+  {
+    name = "alpha";
+    version = "1.0.0";
+    url = "https://static.crates.io/crates/alpha/alpha-1.0.0.crate";
+    sha256 = "sha256-of-alpha";
+  }
+  {
+    name = "zeta";
+    version = "0.2.0";
+    url = "https://static.crates.io/crates/zeta/zeta-0.2.0.crate";
+    sha256 = "sha256-of-zeta";
+  }
+]
+"#;
+    assert!(
+        rendered.contains(expected),
+        "unexpected sources:\n{}",
+        rendered
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_meta_license() {
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("main")
+        .make_root()
+        .update_package(|p| p.license = Some("MIT OR Apache-2.0".to_string()));
+
+    let mut build_info = env.build_info(crate::config::Config::default());
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(rendered.contains("        license = \"MIT OR Apache-2.0\";\n"));
+    assert!(!rendered.contains("meta.license"));
+
+    build_info.config.meta_license = true;
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(
+        rendered.contains(
+            "        license = \"MIT OR Apache-2.0\";\n        \
+             meta.license = [ lib.licenses.mit lib.licenses.asl20 ];\n"
+        ),
+        "meta.license missing:\n{}",
+        rendered
+    );
+
+    env.close();
+}
+
+#[test]
+fn test_render_meta_source_provenance() {
+    let mut env = crate::test::MetadataEnv::default();
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.add_dependency("ring").version_and_package_id("0.17.0");
+
+    let build_info = |meta_source_provenance: bool, source_types: &[&str]| {
+        let mut crate2nix_json = crate::config::Config::default();
+        crate2nix_json.source_provenance.insert(
+            "ring".to_string(),
+            source_types.iter().map(|t| t.to_string()).collect(),
+        );
+        crate::BuildInfo::new(
+            &GenerateInfo::default(),
+            &crate::GenerateConfig {
+                meta_source_provenance,
+                ..crate::test::generate_config()
+            },
+            crate2nix_json,
+            env.indexed_metadata(),
+        )
+    };
+
+    let marked = "\n        meta.sourceProvenance = \
+                  [ lib.sourceTypes.fromSource lib.sourceTypes.binaryNativeCode ];\n";
+    let from_source = "\n        meta.sourceProvenance = [ lib.sourceTypes.fromSource ];\n";
+
+    let rendered = CARGO_NIX
+        .render(&build_info(false, &["fromSource", "binaryNativeCode"]).unwrap())
+        .unwrap();
+    assert!(rendered.contains(marked), "ring not marked:\n{}", rendered);
+    assert_eq!(rendered.matches("meta.sourceProvenance = ").count(), 1);
+
+    let rendered = CARGO_NIX
+        .render(&build_info(true, &["fromSource", "binaryNativeCode"]).unwrap())
+        .unwrap();
+    assert!(rendered.contains(marked), "ring not marked:\n{}", rendered);
+    assert_eq!(rendered.matches(from_source).count(), 1, "{}", rendered);
+
+    assert!(build_info(true, &["prebuilt"]).is_err());
+
+    let mut old = build_info(true, &["fromSource", "binaryNativeCode"]).unwrap();
+    old.build_rust_crate_attributes = crate::nixpkgs_compat::BuildRustCrateAttributes::for_release(
+        Some(&"21.11".parse().unwrap()),
+    );
+    let rendered = CARGO_NIX.render(&old).unwrap();
+    assert!(!rendered.contains("lib.sourceTypes"), "{}", rendered);
+
+    env.close();
+}
+
+#[test]
+fn test_render_crate_type_rustc_opts() {
+    let target = |kind: &str| -> cargo_metadata::Target {
+        serde_json::from_value(serde_json::json!({
+            "kind": [kind],
+            "crate_types": [kind],
+            "name": "main",
+            "src_path": "/src/main.rs",
+            "edition": "2018",
+        }))
+        .unwrap()
+    };
+    let mut env = crate::test::MetadataEnv::default();
+    env.add_package_and_node("cli")
+        .make_workspace_member()
+        .update_package(|p| p.targets = vec![target("bin")]);
+    env.add_package_and_node("ffi")
+        .make_workspace_member()
+        .update_package(|p| p.targets = vec![target("cdylib")]);
+    env.add_package_and_node("macros")
+        .make_workspace_member()
+        .update_package(|p| p.targets = vec![target("proc-macro")]);
+
+    let mut crate2nix_json = crate::config::Config::default();
+    crate2nix_json.crate_type_rustc_opts.insert(
+        "cdylib".to_string(),
+        vec!["-C".to_string(), "prefer-dynamic".to_string()],
+    );
+    crate2nix_json
+        .crate_type_rustc_opts
+        .insert("proc-macro".to_string(), vec!["-C opt-level=3".to_string()]);
+    crate2nix_json
+        .crate_crate_type_rustc_opts
+        .entry("ffi".to_string())
+        .or_default()
+        .insert("cdylib".to_string(), vec!["-C strip=symbols".to_string()]);
+    let build_info = env.build_info(crate2nix_json);
+
+    let rendered = CARGO_NIX.render(&build_info).unwrap();
+    assert!(
+        crate_entry(&rendered, "ffi").contains(
+            "\n        extraRustcOpts = [ \"-C\" \"prefer-dynamic\" \"-C strip=symbols\" ];\n"
+        ),
+        "cdylib options missing:\n{}",
+        rendered
+    );
+    assert!(
+        crate_entry(&rendered, "macros").contains(
+            "\n        procMacro = true;\n        extraRustcOpts = [ \"-C opt-level=3\" ];\n"
+        ),
+        "proc-macro options missing:\n{}",
+        rendered
+    );
+    assert!(!crate_entry(&rendered, "cli").contains("extraRustcOpts"));
+
+    env.close();
+}
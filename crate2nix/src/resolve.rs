@@ -1,7 +1,7 @@
 //! Resolve dependencies and other data for CrateDerivation.
 
 use anyhow::format_err;
-use anyhow::Error;
+use anyhow::{bail, Error};
 use cargo_metadata::Node;
 use cargo_metadata::Package;
 use cargo_metadata::PackageId;
@@ -18,8 +18,10 @@ use std::convert::Into;
 use std::path::{Path, PathBuf};
 
 use crate::metadata::IndexedMetadata;
+use crate::registry::RegistryIndex;
 #[cfg(test)]
 use crate::test;
+use crate::warnings::{warn, WarningKind};
 use crate::GenerateConfig;
 use itertools::Itertools;
 use std::{collections::btree_map::BTreeMap, fmt::Display};
@@ -51,6 +53,42 @@ pub struct CrateDerivation {
     pub proc_macro: bool,
     /// This derivation builds the root crate or a workspace member.
     pub is_root_or_workspace_member: bool,
+    /// Whether this crate may be published, i.e. it does not declare `publish = false`.
+    pub publish: bool,
+    /// Whether `meta.license` and `meta.sourceProvenance` are omitted since the crate is
+    /// not published or only available by path and `--meta-skip-unpublished` is given.
+    pub omit_meta: bool,
+    /// Custom unpacking of the source as configured in `crate2nix.json`.
+    pub unpack: Option<crate::config::Unpack>,
+    /// The `postPatch` hook as configured in `crate2nix.json`.
+    pub post_patch: Option<String>,
+    /// The `preConfigure` hook as configured in `crate2nix.json`.
+    pub pre_configure: Option<String>,
+    /// The `preBuild` hook as configured in `crate2nix.json`.
+    pub pre_build: Option<String>,
+    /// The `NIX_CFLAGS_COMPILE` as configured in `crate2nix.json`.
+    pub nix_cflags_compile: Option<String>,
+    /// The `NIX_LDFLAGS` as configured in `crate2nix.json`.
+    pub nix_ldflags: Option<String>,
+    /// The `extraRustcOpts` of the crate: the rustc options for the crate types that it
+    /// emits as configured in `crate2nix.json`.
+    pub extra_rustc_opts: Vec<String>,
+    /// The platforms the crate can be built on as configured in `crate2nix.json`.
+    pub platforms: Option<crate::config::Platforms>,
+    /// The `lib.sourceTypes` for `meta.sourceProvenance`, if emitted.
+    pub source_provenance: Option<Vec<String>>,
+    /// The binary selected by `default-run` in the manifest of a root crate or workspace member.
+    pub default_run: Option<String>,
+    /// The `description` of the package, e.g. for `CARGO_PKG_DESCRIPTION`.
+    pub description: Option<String>,
+    /// The `homepage` of the package.
+    pub homepage: Option<String>,
+    /// The `repository` of the package.
+    pub repository: Option<String>,
+    /// The SPDX `license` expression of the package.
+    pub license: Option<String>,
+    /// The attribute paths of the packages in `pkgs` that are available when running the tests.
+    pub check_inputs: Vec<String>,
 }
 
 impl CrateDerivation {
@@ -62,14 +100,14 @@ impl CrateDerivation {
     ) -> Result<CrateDerivation, Error> {
         let resolved_dependencies = ResolvedDependencies::new(metadata, package)?;
 
-        let build_dependencies =
+        let mut build_dependencies =
             resolved_dependencies.filtered_dependencies(|d| d.kind == DependencyKind::Build);
 
-        let dependencies = resolved_dependencies.filtered_dependencies(|d| {
+        let mut dependencies = resolved_dependencies.filtered_dependencies(|d| {
             d.kind == DependencyKind::Normal || d.kind == DependencyKind::Unknown
         });
 
-        let dev_dependencies =
+        let mut dev_dependencies =
             resolved_dependencies.filtered_dependencies(|d| d.kind == DependencyKind::Development);
 
         let is_root_or_workspace_member = metadata
@@ -78,6 +116,21 @@ impl CrateDerivation {
             .chain(metadata.workspace_members.iter())
             .any(|pkg_id| *pkg_id == package.id);
 
+        // Artifact dependencies are only supported for local crates.
+        if config.artifact_dependencies && is_root_or_workspace_member {
+            let artifacts = artifact_dependencies(package.manifest_path.as_ref())?;
+            for (kind, dependencies) in &mut [
+                (DependencyKind::Normal, &mut dependencies),
+                (DependencyKind::Build, &mut build_dependencies),
+                (DependencyKind::Development, &mut dev_dependencies),
+            ] {
+                for dependency in dependencies.iter_mut() {
+                    let name = dependency.rename.as_ref().unwrap_or(&dependency.name);
+                    dependency.artifact = artifacts.get(&(*kind, name.clone())).cloned();
+                }
+            }
+        }
+
         let package_path = package.manifest_path.parent().unwrap_or_else(|| {
             panic!(
                 "WUUT? No parent directory of manifest at {}?",
@@ -92,12 +145,15 @@ impl CrateDerivation {
             // name anymore. So we need to extract it from the path.
             let configured_source = package_path
                 .file_name()
-                .and_then(|file_name| crate2nix_json.sources.get(&*file_name).cloned());
+                .and_then(|file_name| crate2nix_json.sources.get(file_name).cloned());
 
             if !crate2nix_json.sources.is_empty() && configured_source.is_none() {
-                eprintln!(
-                    "warning: Could not find configured source for workspace member {:?}",
-                    package_path
+                warn(
+                    WarningKind::MissingConfiguredSource,
+                    format!(
+                        "Could not find configured source for workspace member {:?}",
+                        package_path
+                    ),
                 );
             }
 
@@ -109,7 +165,17 @@ impl CrateDerivation {
         let source = if let Some(configured) = configured_source {
             configured.into()
         } else {
-            ResolvedSource::new(&config, &package, &package_path)?
+            match ResolvedSource::new(config, package, package_path)? {
+                // Archives do not contain the submodules that cargo checks out.
+                ResolvedSource::Git(git) if !has_git_submodules(package_path.as_ref()) => {
+                    let archive_url = crate2nix_json
+                        .git_archives
+                        .as_ref()
+                        .and_then(|git_archives| git_archive_url(git_archives, &git.url, &git.rev));
+                    ResolvedSource::Git(GitSource { archive_url, ..git })
+                }
+                source => source,
+            }
         };
 
         let package_path = package_path.canonicalize().map_err(|e| {
@@ -128,13 +194,13 @@ impl CrateDerivation {
                     k == "lib" || k == "cdylib" || k == "dylib" || k == "rlib" || k == "proc-macro"
                 })
             })
-            .and_then(|target| BuildTarget::new(&target, &package_path).ok());
+            .and_then(|target| BuildTarget::new(target, &package_path).ok());
 
         let build = package
             .targets
             .iter()
             .find(|t| t.kind.iter().any(|k| k == "custom-build"))
-            .and_then(|target| BuildTarget::new(&target, &package_path).ok());
+            .and_then(|target| BuildTarget::new(target, &package_path).ok());
 
         let proc_macro = package
             .targets
@@ -146,13 +212,26 @@ impl CrateDerivation {
             .iter()
             .filter_map(|t| {
                 if t.kind.iter().any(|k| k == "bin") {
-                    BuildTarget::new(&t, &package_path).ok()
+                    BuildTarget::new(t, &package_path).ok()
                 } else {
                     None
                 }
             })
             .collect();
 
+        let extra_rustc_opts = crate_type_rustc_opts(crate2nix_json, package);
+
+        // `cargo metadata` reports `publish = false` as an empty list of registries.
+        let publish = package
+            .publish
+            .as_ref()
+            .map(|registries| !registries.is_empty())
+            .unwrap_or(true);
+        // Path dependencies outside of the workspace are only available by their path.
+        let path_only =
+            !is_root_or_workspace_member && matches!(source, ResolvedSource::LocalDirectory(_));
+        let omit_meta = config.meta_skip_unpublished && (!publish || path_only);
+
         Ok(CrateDerivation {
             crate_name: package.name.clone(),
             edition: package.edition.clone(),
@@ -169,7 +248,7 @@ impl CrateDerivation {
                 .nodes_by_id
                 .get(&package.id)
                 .map(|n| n.features.clone())
-                .unwrap_or_else(Vec::new),
+                .unwrap_or_default(),
             lib_crate_types: package
                 .targets
                 .iter()
@@ -186,8 +265,286 @@ impl CrateDerivation {
             proc_macro,
             binaries,
             is_root_or_workspace_member,
+            publish,
+            omit_meta,
+            unpack: crate2nix_json.unpack.get(&package.name).cloned(),
+            post_patch: crate2nix_json.post_patch.get(&package.name).cloned(),
+            pre_configure: crate2nix_json.pre_configure.get(&package.name).cloned(),
+            pre_build: crate2nix_json.pre_build.get(&package.name).cloned(),
+            nix_cflags_compile: join_flags(
+                &crate2nix_json.nix_cflags_compile,
+                crate2nix_json.crate_nix_cflags_compile.get(&package.name),
+            ),
+            nix_ldflags: join_flags(
+                &crate2nix_json.nix_ldflags,
+                crate2nix_json.crate_nix_ldflags.get(&package.name),
+            ),
+            extra_rustc_opts,
+            platforms: crate2nix_json.platforms.get(&package.name).cloned(),
+            source_provenance: if omit_meta {
+                None
+            } else {
+                source_provenance(config, crate2nix_json, &package.name)?
+            },
+            default_run: if is_root_or_workspace_member {
+                default_run(package.manifest_path.as_ref())
+            } else {
+                None
+            },
+            description: package.description.clone(),
+            homepage: package.homepage.clone(),
+            repository: package.repository.clone(),
+            license: package.license.clone(),
+            check_inputs: if is_root_or_workspace_member {
+                check_inputs(crate2nix_json, &package.name)?
+            } else {
+                Vec::new()
+            },
+        })
+    }
+}
+
+/// Returns `package.default-run` from the given manifest.
+///
+/// `cargo metadata` reports it as `default_run` but `cargo_metadata` 0.14 does not
+/// deserialize it, so it is read from the manifest until we can depend on a version
+/// with `Package::default_run`. `cargo metadata` has already validated the manifest,
+/// so it is not reported if it cannot be read.
+fn default_run(manifest_path: &std::path::Path) -> Option<String> {
+    let manifest: toml::Value = std::fs::read_to_string(manifest_path).ok()?.parse().ok()?;
+    manifest
+        .get("package")?
+        .get("default-run")?
+        .as_str()
+        .map(|s| s.to_string())
+}
+
+/// Returns the rustc options for all crate types that the library and binaries of the
+/// package emit, first the ones for all crates, then the ones for this crate.
+fn crate_type_rustc_opts(crate2nix_json: &crate::config::Config, package: &Package) -> Vec<String> {
+    let crate_types: Vec<&String> = package
+        .targets
+        .iter()
+        .filter(|target| {
+            target
+                .kind
+                .iter()
+                .any(|kind| kind.ends_with("lib") || kind == "bin" || kind == "proc-macro")
+        })
+        .flat_map(|target| target.crate_types.iter())
+        .unique()
+        .collect();
+    let for_crate_types = |opts_by_crate_type: &BTreeMap<String, Vec<String>>| {
+        opts_by_crate_type
+            .iter()
+            .filter(|(crate_type, _)| crate_types.contains(crate_type))
+            .flat_map(|(_, opts)| opts.iter().cloned())
+            .collect::<Vec<_>>()
+    };
+    let mut opts = for_crate_types(&crate2nix_json.crate_type_rustc_opts);
+    if let Some(opts_by_crate_type) = crate2nix_json
+        .crate_crate_type_rustc_opts
+        .get(&package.name)
+    {
+        opts.extend(for_crate_types(opts_by_crate_type));
+    }
+    opts
+}
+
+/// The `lib.sourceTypes` of nixpkgs.
+const SOURCE_TYPES: &[&str] = &[
+    "fromSource",
+    "binaryNativeCode",
+    "binaryFirmware",
+    "binaryBytecode",
+];
+
+/// Returns the source types for `meta.sourceProvenance` of the given crate.
+///
+/// Crates that are not configured in `crate2nix.json` are built `fromSource`, which is
+/// only emitted with `--meta-source-provenance`.
+fn source_provenance(
+    config: &GenerateConfig,
+    crate2nix_json: &crate::config::Config,
+    crate_name: &str,
+) -> Result<Option<Vec<String>>, Error> {
+    let source_types = match crate2nix_json.source_provenance.get(crate_name) {
+        Some(source_types) => source_types.clone(),
+        None if config.meta_source_provenance => vec!["fromSource".to_string()],
+        None => return Ok(None),
+    };
+    if let Some(invalid) = source_types
+        .iter()
+        .find(|source_type| !SOURCE_TYPES.contains(&source_type.as_str()))
+    {
+        bail!(
+            "Invalid source type '{}' in sourceProvenance of crate '{}', expected one of: {}",
+            invalid,
+            crate_name,
+            SOURCE_TYPES.join(", ")
+        );
+    }
+    Ok(Some(source_types))
+}
+
+/// Joins the flags for all crates with the ones for a specific crate.
+fn join_flags(all_crates: &Option<String>, this_crate: Option<&String>) -> Option<String> {
+    let flags = all_crates.iter().chain(this_crate).join(" ");
+    if flags.is_empty() {
+        None
+    } else {
+        Some(flags)
+    }
+}
+
+/// Returns the configured check inputs for the crate with the given name.
+fn check_inputs(
+    crate2nix_json: &crate::config::Config,
+    crate_name: &str,
+) -> Result<Vec<String>, Error> {
+    let check_inputs: Vec<String> = crate2nix_json
+        .check_inputs
+        .iter()
+        .chain(
+            crate2nix_json
+                .crate_check_inputs
+                .get(crate_name)
+                .into_iter()
+                .flatten(),
+        )
+        .unique()
+        .cloned()
+        .collect();
+    let is_attr_path = |input: &str| {
+        input.split('.').all(|name| {
+            name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '\'')
         })
+    };
+    if let Some(invalid) = check_inputs.iter().find(|input| !is_attr_path(input)) {
+        bail!(
+            "check input '{}' of {} is not an attribute path in pkgs, e.g. 'protobuf'",
+            invalid,
+            crate_name
+        );
+    }
+    Ok(check_inputs)
+}
+
+/// Writes a workspace whose root package depends on `foo` from crates.io, patched to the
+/// workspace member `crates/foo` with the given `version` line.
+#[cfg(test)]
+fn write_path_patch_workspace(dir: &Path, foo_version: &str) {
+    let write = |path: &str, content: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write(
+        "Cargo.toml",
+        "[package]\nname = \"main\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+         [dependencies]\nfoo = \"0.1\"\n\n\
+         [workspace]\nmembers = [\"crates/foo\"]\n\n\
+         [workspace.package]\nversion = \"0.1.3\"\n\n\
+         [patch.crates-io]\nfoo = { path = \"crates/foo\" }\n",
+    );
+    write("src/lib.rs", "");
+    write(
+        "crates/foo/Cargo.toml",
+        &format!(
+            "[package]\nname = \"foo\"\n{}\nedition = \"2018\"\n",
+            foo_version
+        ),
+    );
+    write("crates/foo/src/lib.rs", "");
+    write(
+        "Cargo.lock",
+        "version = 3\n\n[[package]]\nname = \"foo\"\nversion = \"0.1.3\"\n\n\
+         [[package]]\nname = \"main\"\nversion = \"0.1.0\"\ndependencies = [\n \"foo\",\n]\n",
+    );
+}
+
+#[test]
+pub fn path_patch_uses_local_source() {
+    let workspace = tempdir::TempDir::new("crate2nix_path_patch").unwrap();
+    write_path_patch_workspace(workspace.path(), "version.workspace = true");
+
+    let config = GenerateConfig {
+        cargo_toml: vec![workspace.path().join("Cargo.toml")],
+        output: workspace.path().join("Cargo.nix"),
+        crate_hashes_json: workspace.path().join("crate-hashes.json"),
+        ..test::generate_config()
+    };
+    let build_info =
+        crate::BuildInfo::for_config(&crate::GenerateInfo::default(), &config).unwrap();
+
+    let foo = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "foo")
+        .unwrap();
+    assert_eq!(foo.version, Version::new(0, 1, 3));
+    assert_eq!(
+        foo.source,
+        ResolvedSource::LocalDirectory(LocalDirectorySource {
+            path: "./crates/foo".into()
+        })
+    );
+
+    workspace.close().unwrap();
+}
+
+#[test]
+pub fn registry_name_is_resolved_from_cargo_config() {
+    // A git registry in a local directory with `private_crate 1.0.0` and a project that
+    // depends on it by the name of the registry.
+    let dir = tempdir::TempDir::new("crate2nix_registry_name").unwrap();
+    let index_url = test::write_local_git_registry(dir.path(), &[("private_crate", "1.0.0")]);
+    let project_dir = dir.path().join("project");
+    let metadata = test::local_registry_project(
+        dir.path(),
+        &project_dir,
+        &index_url,
+        &[("private_crate", "1")],
+    );
+    let source = metadata
+        .packages
+        .iter()
+        .find(|p| p.name == "private_crate")
+        .and_then(|p| p.source.as_ref())
+        .unwrap();
+    assert_eq!(source.repr, format!("registry+{}", index_url));
+
+    let config = GenerateConfig {
+        cargo_toml: vec![project_dir.join("Cargo.toml")],
+        output: project_dir.join("Cargo.nix"),
+        ..test::generate_config()
+    };
+    let build_info = crate::BuildInfo::new(
+        &crate::GenerateInfo::default(),
+        &config,
+        crate::config::Config::default(),
+        IndexedMetadata::new_from(metadata).unwrap(),
+    )
+    .unwrap();
+
+    let private_crate = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "private_crate")
+        .unwrap();
+    match &private_crate.source {
+        ResolvedSource::Registry(source) => {
+            assert_eq!(source.index.to_string(), format!("registry+{}", index_url));
+            assert_eq!(source.registry.as_deref(), Some("my-registry"));
+            assert_eq!(source.to_string(), "private_crate 1.0.0 from my-registry");
+        }
+        source => panic!("unexpected source {:?}", source),
     }
+
+    dir.close().unwrap();
 }
 
 #[test]
@@ -228,7 +585,7 @@ pub fn minimal_resolve() {
         crate_derivation.version,
         semver::Version::parse("1.2.3").unwrap()
     );
-    assert_eq!(crate_derivation.is_root_or_workspace_member, true);
+    assert!(crate_derivation.is_root_or_workspace_member);
     let empty: Vec<String> = vec![];
     assert_eq!(crate_derivation.lib_crate_types, empty);
 
@@ -249,7 +606,7 @@ pub fn configured_source_is_used_instead_of_local_directory() {
     // By simulating this layout, we ensure that we do not canonicalize paths at the "wrong"
     // moment.
     let simulated_store_path = env.temp_dir();
-    std::fs::File::create(&simulated_store_path.join("Cargo.toml")).expect("File creation failed");
+    std::fs::File::create(simulated_store_path.join("Cargo.toml")).expect("File creation failed");
     let workspace_with_symlink = env.temp_dir();
     std::os::unix::fs::symlink(
         &simulated_store_path,
@@ -291,6 +648,22 @@ pub fn configured_source_is_used_instead_of_local_directory() {
     env.close();
 }
 
+#[test]
+pub fn invalid_check_inputs_are_rejected() {
+    let crate2nix_json = crate::config::Config {
+        check_inputs: vec!["python3Packages.pytest".to_string()],
+        crate_check_inputs: vec![("main".to_string(), vec!["${evil}".to_string()])]
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    };
+    assert_eq!(
+        check_inputs(&crate2nix_json, "other").unwrap(),
+        vec!["python3Packages.pytest".to_string()]
+    );
+    assert!(check_inputs(&crate2nix_json, "main").is_err());
+}
+
 #[test]
 pub fn double_crate_with_rename() {
     let mut env = test::MetadataEnv::default();
@@ -328,6 +701,101 @@ pub fn double_crate_with_rename() {
     env.close();
 }
 
+#[test]
+pub fn unpublished_workspace_member_is_flagged() {
+    let mut env = test::MetadataEnv::default();
+
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.update_package(|p| p.publish = Some(vec![]));
+    main.add_dependency("internal")
+        .update_package(|p| p.publish = Some(vec!["my-registry".to_string()]));
+    main.add_dependency("published");
+
+    let build_info = env.build_info(crate::config::Config::default());
+    let dump = serde_json::to_value(&build_info).unwrap();
+    let publish_by_name: BTreeMap<&str, bool> = dump["crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            (
+                c["crate_name"].as_str().unwrap(),
+                c["publish"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        publish_by_name,
+        vec![("internal", true), ("main", false), ("published", true)]
+            .into_iter()
+            .collect()
+    );
+
+    env.close();
+}
+
+#[test]
+pub fn meta_is_omitted_for_unpublished_and_path_only_crates() {
+    let mut env = test::MetadataEnv::default();
+
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    main.update_package(|p| p.publish = Some(vec![]));
+    main.add_dependency("path-only");
+    main.add_dependency("published").update_package(|p| {
+        p.source = Some(cargo_metadata::Source {
+            repr: "registry+https://github.com/rust-lang/crates.io-index".to_string(),
+        })
+    });
+    let mut member = env.add_package_and_node("member");
+    member.make_workspace_member();
+
+    let omitted = |meta_skip_unpublished: bool| -> BTreeMap<String, bool> {
+        let config = GenerateConfig {
+            meta_source_provenance: true,
+            meta_skip_unpublished,
+            ..test::generate_config()
+        };
+        crate::BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &config,
+            crate::config::Config::default(),
+            env.indexed_metadata(),
+        )
+        .unwrap()
+        .crates
+        .into_iter()
+        .map(|c| {
+            assert_eq!(
+                c.omit_meta,
+                c.source_provenance.is_none(),
+                "{}",
+                c.crate_name
+            );
+            (c.crate_name, c.omit_meta)
+        })
+        .collect()
+    };
+
+    assert!(omitted(false).values().all(|omit| !omit));
+    // Only the published dependency and the workspace member keep their meta attributes.
+    assert_eq!(
+        omitted(true),
+        vec![
+            ("main".to_string(), true),
+            ("member".to_string(), false),
+            ("path-only".to_string(), true),
+            ("published".to_string(), false),
+        ]
+        .into_iter()
+        .collect()
+    );
+
+    env.close();
+}
+
 /// A build target of a crate.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct BuildTarget {
@@ -354,6 +822,7 @@ impl BuildTarget {
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub enum ResolvedSource {
     CratesIo(CratesIoSource),
+    Registry(RegistrySource),
     Git(GitSource),
     LocalDirectory(LocalDirectorySource),
     Nix(NixSource),
@@ -367,6 +836,7 @@ impl From<crate::config::Source> for ResolvedSource {
                 rev,
                 r#ref: None,
                 sha256: Some(sha256),
+                archive_url: None,
             }),
             crate::config::Source::CratesIo {
                 name,
@@ -391,6 +861,19 @@ pub struct CratesIoSource {
     pub sha256: Option<String>,
 }
 
+/// A crate from a registry other than crates.io, e.g. a mirror.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct RegistrySource {
+    pub index: RegistryIndex,
+    /// The name of the registry in the `[registries]` of the cargo configs, if it has one.
+    pub registry: Option<String>,
+    pub name: String,
+    pub version: Version,
+    /// The download URL as configured in the index, filled in by `fill_registry_sources`.
+    pub url: Option<String>,
+    pub sha256: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub struct GitSource {
     #[serde(with = "url_serde")]
@@ -398,6 +881,78 @@ pub struct GitSource {
     pub rev: String,
     pub r#ref: Option<String>,
     pub sha256: Option<String>,
+    /// The URL of an archive tarball of the revision if the source is fetched from a known forge.
+    pub archive_url: Option<String>,
+}
+
+/// The archive URL patterns of well-known forges by host.
+const GIT_ARCHIVE_PATTERNS: &[(&str, &str)] = &[
+    (
+        "github.com",
+        "https://github.com/{owner}/{repo}/archive/{rev}.tar.gz",
+    ),
+    (
+        "gitlab.com",
+        "https://gitlab.com/{owner}/{repo}/-/archive/{rev}/{repo}-{rev}.tar.gz",
+    ),
+    (
+        "codeberg.org",
+        "https://codeberg.org/{owner}/{repo}/archive/{rev}.tar.gz",
+    ),
+    (
+        "git.sr.ht",
+        "https://git.sr.ht/{owner}/{repo}/archive/{rev}.tar.gz",
+    ),
+];
+
+/// Returns the archive tarball URL of the git revision if tarballs are configured for its host.
+///
+/// The patterns configured in `crate2nix.json` take precedence over the built-in ones.
+fn git_archive_url(
+    git_archives: &crate::config::GitArchives,
+    url: &Url,
+    rev: &str,
+) -> Option<String> {
+    if !matches!(url.scheme(), "https" | "http") {
+        return None;
+    }
+    let host = url.host_str()?;
+    let pattern = git_archives
+        .patterns
+        .get(host)
+        .map(String::as_str)
+        .or_else(|| {
+            GIT_ARCHIVE_PATTERNS
+                .iter()
+                .find(|(known_host, _)| *known_host == host)
+                .map(|(_, pattern)| *pattern)
+        })
+        .filter(|pattern| !pattern.is_empty())?;
+
+    let path = url.path().trim_matches('/');
+    let (owner, repo) = path.rsplit_once('/')?;
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+    if owner.is_empty() || repo.is_empty() {
+        return None;
+    }
+    Some(
+        pattern
+            .replace("{host}", host)
+            .replace("{owner}", owner)
+            .replace("{repo}", repo)
+            .replace("{rev}", rev),
+    )
+}
+
+/// Whether the git checkout containing the package directory has a `.gitmodules` file.
+///
+/// The checkout root is the closest directory with a `.git` or cargo's `.cargo-ok` marker.
+fn has_git_submodules(package_path: &Path) -> bool {
+    package_path
+        .ancestors()
+        .find(|dir| dir.join(".git").exists() || dir.join(".cargo-ok").exists())
+        .map(|checkout| checkout.join(".gitmodules").exists())
+        .unwrap_or(false)
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
@@ -413,6 +968,24 @@ pub struct NixSource {
 
 const GIT_SOURCE_PREFIX: &str = "git+";
 
+/// Returns the name of the registry with the given index in the `[registries]` of the
+/// cargo configs, e.g. `my-registry` for a dependency with `registry = "my-registry"`.
+///
+/// `cargo metadata` reports the sources of such dependencies by their index URL.
+fn registry_name(config: &GenerateConfig, index: &RegistryIndex) -> Result<Option<String>, Error> {
+    for cargo_toml in &config.cargo_toml {
+        let dir = match cargo_toml.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let registries = crate::registry::registry_aliases(dir)?;
+        if let Some((name, _)) = registries.into_iter().find(|(_, i)| i == index) {
+            return Ok(Some(name));
+        }
+    }
+    Ok(None)
+}
+
 impl ResolvedSource {
     pub fn new(
         config: &GenerateConfig,
@@ -420,23 +993,77 @@ impl ResolvedSource {
         package_path: impl AsRef<Path>,
     ) -> Result<ResolvedSource, Error> {
         match package.source.as_ref() {
-            Some(source) if source.is_crates_io() => {
-                // Will sha256 will be filled later by prefetch_and_fill_crates_sha256.
-                Ok(ResolvedSource::CratesIo(CratesIoSource {
-                    name: package.name.clone(),
-                    version: package.version.clone(),
-                    sha256: None,
+            Some(source) => match RegistryIndex::from_source(&source.repr) {
+                Some(index) => {
+                    let index = index?;
+                    if index.is_crates_io() {
+                        // Will sha256 will be filled later by prefetch_and_fill_crates_sha256.
+                        Ok(ResolvedSource::CratesIo(CratesIoSource {
+                            name: package.name.clone(),
+                            version: package.version.clone(),
+                            sha256: None,
+                        }))
+                    } else {
+                        // The url and sha256 will be filled later by fill_registry_sources.
+                        Ok(ResolvedSource::Registry(RegistrySource {
+                            registry: registry_name(config, &index)?,
+                            index,
+                            name: package.name.clone(),
+                            version: package.version.clone(),
+                            url: None,
+                            sha256: None,
+                        }))
+                    }
+                }
+                None => {
+                    ResolvedSource::git_or_local_directory(config, package, &package_path, source)
+                }
+            },
+            None => {
+                ResolvedSource::warn_if_outside_repository(config, package, &package_path)?;
+                Ok(ResolvedSource::LocalDirectory(LocalDirectorySource {
+                    path: ResolvedSource::relative_directory(config, package_path)?,
                 }))
             }
-            Some(source) => {
-                ResolvedSource::git_or_local_directory(config, package, &package_path, source)
-            }
-            None => Ok(ResolvedSource::LocalDirectory(LocalDirectorySource {
-                path: ResolvedSource::relative_directory(config, package_path)?,
-            })),
         }
     }
 
+    /// Warns if the package directory is not part of the git repository that contains
+    /// the output file, e.g. because a path dependency points to a sibling checkout.
+    ///
+    /// Such sources are missing if the repository is used on its own, e.g. as a flake.
+    fn warn_if_outside_repository(
+        config: &GenerateConfig,
+        package: &Package,
+        package_path: impl AsRef<Path>,
+    ) -> Result<(), Error> {
+        let output_directory = ResolvedSource::output_directory(config)?;
+        let repository = match output_directory
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+        {
+            Some(repository) => repository,
+            None => return Ok(()),
+        };
+        let package_path = package_path.as_ref();
+        let package_path = package_path
+            .canonicalize()
+            .unwrap_or_else(|_| package_path.to_path_buf());
+        if !package_path.starts_with(repository) {
+            warn(
+                WarningKind::PathDependencyOutsideRepository,
+                format!(
+                    "The path dependency {} {} in {} is outside of the git repository {}.",
+                    package.name,
+                    package.version,
+                    package_path.to_string_lossy(),
+                    repository.to_string_lossy()
+                ),
+            );
+        }
+        Ok(())
+    }
+
     fn git_or_local_directory(
         config: &GenerateConfig,
         package: &Package,
@@ -477,6 +1104,7 @@ impl ResolvedSource {
             rev,
             r#ref: branch,
             sha256: None,
+            archive_url: None,
         }))
     }
 
@@ -487,28 +1115,27 @@ impl ResolvedSource {
         warning: &str,
     ) -> Result<ResolvedSource, Error> {
         let path = Self::relative_directory(config, package_path)?;
-        eprintln!(
-            "WARNING: {} Falling back to local directory for crate {} with source {}: {}",
-            warning,
-            package.id,
-            package
-                .source
-                .as_ref()
-                .map(std::string::ToString::to_string)
-                .unwrap_or_else(|| "N/A".to_string()),
-            &path.to_string_lossy()
+        warn(
+            WarningKind::LocalDirectoryFallback,
+            format!(
+                "{} Falling back to local directory for crate {} with source {}: {}",
+                warning,
+                package.id,
+                package
+                    .source
+                    .as_ref()
+                    .map(std::string::ToString::to_string)
+                    .unwrap_or_else(|| "N/A".to_string()),
+                &path.to_string_lossy()
+            ),
         );
         Ok(ResolvedSource::LocalDirectory(LocalDirectorySource {
             path,
         }))
     }
 
-    fn relative_directory(
-        config: &GenerateConfig,
-        package_path: impl AsRef<Path>,
-    ) -> Result<PathBuf, Error> {
-        // Use local directory. This is the local cargo crate directory in the worst case.
-
+    /// The canonical directory of the output file.
+    fn output_directory(config: &GenerateConfig) -> Result<PathBuf, Error> {
         let mut output_build_file_directory = config
             .output
             .parent()
@@ -532,13 +1159,22 @@ impl ResolvedSource {
                 e
             )
         })?;
+        Ok(output_build_file_directory)
+    }
+
+    fn relative_directory(
+        config: &GenerateConfig,
+        package_path: impl AsRef<Path>,
+    ) -> Result<PathBuf, Error> {
+        // Use local directory. This is the local cargo crate directory in the worst case.
+        let output_build_file_directory = ResolvedSource::output_directory(config)?;
 
         Ok(if package_path.as_ref() == output_build_file_directory {
             "./.".into()
         } else {
             let path = diff_paths(package_path.as_ref(), &output_build_file_directory)
                 .unwrap_or_else(|| package_path.as_ref().to_path_buf());
-            if path == PathBuf::from("../") {
+            if path == Path::new("../") {
                 path.join(PathBuf::from("."))
             } else if path.starts_with("../") {
                 path
@@ -550,9 +1186,9 @@ impl ResolvedSource {
 
     pub fn sha256(&self) -> Option<&String> {
         match self {
-            Self::CratesIo(CratesIoSource { sha256, .. }) | Self::Git(GitSource { sha256, .. }) => {
-                sha256.as_ref()
-            }
+            Self::CratesIo(CratesIoSource { sha256, .. })
+            | Self::Registry(RegistrySource { sha256, .. })
+            | Self::Git(GitSource { sha256, .. }) => sha256.as_ref(),
             _ => None,
         }
     }
@@ -563,6 +1199,10 @@ impl ResolvedSource {
                 sha256: Some(sha256),
                 ..source.clone()
             }),
+            Self::Registry(source) => Self::Registry(RegistrySource {
+                sha256: Some(sha256),
+                ..source.clone()
+            }),
             Self::Git(source) => Self::Git(GitSource {
                 sha256: Some(sha256),
                 ..source.clone()
@@ -577,6 +1217,10 @@ impl ResolvedSource {
                 sha256: None,
                 ..source.clone()
             }),
+            Self::Registry(source) => Self::Registry(RegistrySource {
+                sha256: None,
+                ..source.clone()
+            }),
             Self::Git(source) => Self::Git(GitSource {
                 sha256: None,
                 ..source.clone()
@@ -586,13 +1230,14 @@ impl ResolvedSource {
     }
 }
 
-impl ToString for ResolvedSource {
-    fn to_string(&self) -> String {
+impl Display for ResolvedSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::CratesIo(source) => source.to_string(),
-            Self::Git(source) => source.to_string(),
-            Self::LocalDirectory(source) => source.to_string(),
-            Self::Nix(source) => source.to_string(),
+            Self::CratesIo(source) => source.fmt(f),
+            Self::Registry(source) => source.fmt(f),
+            Self::Git(source) => source.fmt(f),
+            Self::LocalDirectory(source) => source.fmt(f),
+            Self::Nix(source) => source.fmt(f),
         }
     }
 }
@@ -615,6 +1260,18 @@ impl Display for CratesIoSource {
     }
 }
 
+impl Display for RegistrySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.url.as_ref() {
+            Some(url) => write!(f, "{}", url),
+            None => match self.registry.as_ref() {
+                Some(registry) => write!(f, "{} {} from {}", self.name, self.version, registry),
+                None => write!(f, "{} {} from {}", self.name, self.version, self.index),
+            },
+        }
+    }
+}
+
 impl Display for GitSource {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let base = format!("{}#{}", self.url, self.rev);
@@ -713,6 +1370,17 @@ impl<'a> ResolvedDependencies<'a> {
                 let resolved = resolved_packages_by_crate_name
                     .get(&name)
                     .and_then(|packages| {
+                        // Path dependencies are local packages which often do not
+                        // specify a version, so match them by their directory.
+                        let path_match = package_dep.path.as_ref().and_then(|path| {
+                            packages.iter().find(|p| {
+                                p.source.is_none() && p.manifest_path.parent() == Some(path)
+                            })
+                        });
+                        if path_match.is_some() {
+                            return path_match;
+                        }
+
                         let exact_match = packages
                             .iter()
                             .find(|p| package_dep.req.matches(&p.version));
@@ -746,6 +1414,7 @@ impl<'a> ResolvedDependencies<'a> {
                     optional: package_dep.optional,
                     uses_default_features: package_dep.uses_default_features,
                     features: package_dep.features.clone(),
+                    artifact: None,
                 })
             })
             .collect::<Vec<ResolvedDependency>>();
@@ -854,6 +1523,121 @@ pub fn resolved_dependencies_filtered_dependencies_with_double_crate() {
     env.close();
 }
 
+#[test]
+pub fn resolved_dependencies_path_dependency_without_version() {
+    let mut env = test::MetadataEnv::default();
+
+    let mut main = env.add_package_and_node("main");
+    main.make_root();
+    let main_id = main.get_package().id.clone();
+    main.add_dependency("shared")
+        .version_and_package_id("0.3.0")
+        .update_package(|p| {
+            p.source = Some(cargo_metadata::Source {
+                repr: "registry+https://github.com/rust-lang/crates.io-index".to_string(),
+            })
+        })
+        .update_package_dep(|d| d.rename = Some("shared_registry".to_string()))
+        .update_node_dep(|n| n.name = "shared_registry".to_string());
+    let mut shared = main.add_dependency("shared");
+    shared
+        .version_and_package_id("0.0.0")
+        .update_package_and_node(|pn| {
+            pn.make_workspace_member();
+        });
+    let member_dir = shared
+        .get_package()
+        .manifest_path
+        .parent()
+        .unwrap()
+        .to_owned();
+    shared.update_package_dep(|d| {
+        d.req = semver::VersionReq::STAR;
+        d.path = Some(member_dir);
+    });
+    let member_id = shared.get_package().id.clone();
+
+    let indexed = env.indexed_metadata();
+    let main_id = indexed.id_shortener.shorten(&main_id);
+    let member_id = indexed.id_shortener.shorten(&member_id);
+
+    let main_package = &indexed.pkgs_by_id[&main_id];
+    let resolved_deps = ResolvedDependencies::new(&indexed, main_package).unwrap();
+    let filtered_deps = resolved_deps.filtered_dependencies(|_| true);
+
+    let path_dep = filtered_deps
+        .iter()
+        .find(|d| d.rename.is_none())
+        .expect("path dependency");
+    assert_eq!(path_dep.package_id, member_id);
+
+    let build_info = env.build_info(crate::config::Config::default());
+    let member = build_info
+        .crates
+        .iter()
+        .find(|c| c.package_id == member_id)
+        .expect("workspace member");
+    assert_eq!(member.version, semver::Version::new(0, 0, 0));
+    assert!(matches!(member.source, ResolvedSource::LocalDirectory(_)));
+
+    env.close();
+}
+
+#[test]
+pub fn workspace_member_as_build_dependency() {
+    let mut env = test::MetadataEnv::default();
+
+    let mut app = env.add_package_and_node("app");
+    app.make_workspace_member();
+    let app_id = app.get_package().id.clone();
+    let mut codegen = app.add_dependency("codegen");
+    codegen.update_package_and_node(|pn| {
+        pn.make_workspace_member();
+    });
+    let codegen_dir = codegen
+        .get_package()
+        .manifest_path
+        .parent()
+        .unwrap()
+        .to_owned();
+    codegen.update_package_dep(|d| {
+        d.kind = DependencyKind::Build;
+        d.req = semver::VersionReq::STAR;
+        d.path = Some(codegen_dir);
+    });
+    let codegen_id = codegen.get_package().id.clone();
+
+    let build_info = env.build_info(crate::config::Config::default());
+    let app_id = build_info.indexed_metadata.id_shortener.shorten(&app_id);
+    let codegen_id = build_info
+        .indexed_metadata
+        .id_shortener
+        .shorten(&codegen_id);
+    let crate_by_id = |id: &PackageId| {
+        build_info
+            .crates
+            .iter()
+            .find(|c| c.package_id == *id)
+            .unwrap()
+    };
+
+    let app = crate_by_id(&app_id);
+    assert!(app.dependencies.is_empty());
+    assert_eq!(
+        app.build_dependencies
+            .iter()
+            .map(|d| &d.package_id)
+            .collect::<Vec<_>>(),
+        vec![&codegen_id]
+    );
+    assert!(matches!(
+        crate_by_id(&codegen_id).source,
+        ResolvedSource::LocalDirectory(_)
+    ));
+
+    env.close();
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ResolvedDependency {
     pub name: String,
@@ -869,4 +1653,119 @@ pub struct ResolvedDependency {
     pub uses_default_features: bool,
     /// Extra-enabled features.
     pub features: Vec<String>,
+    /// Set if this is an artifact dependency, e.g. `artifact = "bin"`.
+    pub artifact: Option<ArtifactDependency>,
+}
+
+/// The artifacts that are requested by an artifact dependency.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ArtifactDependency {
+    /// The artifact kinds, e.g. `bin` or `bin:some-binary`.
+    pub kinds: Vec<String>,
+    /// Whether the library of the dependency is used as well.
+    pub lib: bool,
+}
+
+/// Returns the artifact dependencies declared in the given manifest by
+/// dependency kind and dependency name (or rename).
+///
+/// `cargo metadata` does not report them in its stable output format.
+fn artifact_dependencies(
+    manifest_path: &Path,
+) -> Result<HashMap<(DependencyKind, String), ArtifactDependency>, Error> {
+    let manifest: toml::Value = std::fs::read_to_string(manifest_path)
+        .map_err(|e| format_err!("while reading {}: {}", manifest_path.to_string_lossy(), e))?
+        .parse()
+        .map_err(|e| format_err!("while parsing {}: {}", manifest_path.to_string_lossy(), e))?;
+
+    let sections = std::iter::once(&manifest).chain(
+        manifest
+            .get("target")
+            .and_then(|t| t.as_table())
+            .into_iter()
+            .flat_map(|targets| targets.values()),
+    );
+    let mut artifacts = HashMap::new();
+    for section in sections {
+        for (table, kind) in &[
+            ("dependencies", DependencyKind::Normal),
+            ("build-dependencies", DependencyKind::Build),
+            ("dev-dependencies", DependencyKind::Development),
+        ] {
+            let dependencies = section.get(table).and_then(|d| d.as_table());
+            for (name, dependency) in dependencies.into_iter().flatten() {
+                let kinds: Vec<String> = match dependency.get("artifact") {
+                    Some(toml::Value::String(kind)) => vec![kind.clone()],
+                    Some(toml::Value::Array(kinds)) => kinds
+                        .iter()
+                        .filter_map(|k| k.as_str().map(|k| k.to_string()))
+                        .collect(),
+                    _ => continue,
+                };
+                let lib = dependency
+                    .get("lib")
+                    .and_then(|l| l.as_bool())
+                    .unwrap_or(false);
+                artifacts.insert((*kind, name.clone()), ArtifactDependency { kinds, lib });
+            }
+        }
+    }
+    Ok(artifacts)
+}
+
+#[test]
+fn git_archive_urls() {
+    let git_archives = crate::config::GitArchives {
+        patterns: vec![
+            (
+                "git.example.org".to_string(),
+                "https://{host}/{owner}/{repo}/archive/{rev}.tar.gz".to_string(),
+            ),
+            ("gitlab.com".to_string(), "".to_string()),
+        ]
+        .into_iter()
+        .collect(),
+    };
+    let archive_url =
+        |url: &str| git_archive_url(&git_archives, &Url::parse(url).unwrap(), "abc123");
+
+    assert_eq!(
+        archive_url("https://codeberg.org/forgejo/some-crate.git"),
+        Some("https://codeberg.org/forgejo/some-crate/archive/abc123.tar.gz".to_string())
+    );
+    assert_eq!(
+        archive_url("https://git.sr.ht/~someone/some-crate"),
+        Some("https://git.sr.ht/~someone/some-crate/archive/abc123.tar.gz".to_string())
+    );
+    assert_eq!(
+        archive_url("https://git.example.org/team/sub/some-crate"),
+        Some("https://git.example.org/team/sub/some-crate/archive/abc123.tar.gz".to_string())
+    );
+    assert_eq!(archive_url("https://gitlab.com/disabled/some-crate"), None);
+    assert_eq!(
+        archive_url("https://git.unknown.org/someone/some-crate"),
+        None
+    );
+    assert_eq!(
+        archive_url("ssh://git@codeberg.org/forgejo/some-crate"),
+        None
+    );
+}
+
+#[test]
+fn git_submodules_are_detected_in_checkout() {
+    let checkout = tempdir::TempDir::new("crate2nix_git_submodules").unwrap();
+    let package_dir = checkout.path().join("crates").join("dep");
+    std::fs::create_dir_all(&package_dir).unwrap();
+    std::fs::write(checkout.path().join(".cargo-ok"), "").unwrap();
+    assert!(!has_git_submodules(&package_dir));
+
+    std::fs::write(
+        checkout.path().join(".gitmodules"),
+        "[submodule \"vendor\"]\n\tpath = vendor\n",
+    )
+    .unwrap();
+    assert!(has_git_submodules(&package_dir));
+
+    checkout.close().unwrap();
 }
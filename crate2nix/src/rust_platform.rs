@@ -0,0 +1,180 @@
+//! Generating a build that delegates vendoring to nixpkgs' `rustPlatform`.
+//!
+//! Instead of one `buildRustCrate` derivation per crate, the whole build is done by
+//! `rustPlatform.buildRustPackage` which vendors all dependencies with
+//! `fetchCargoVendor` and therefore only needs a single `cargoHash`.
+
+use anyhow::{bail, format_err, Error};
+use pathdiff::diff_paths;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::nix_build::Nix;
+use crate::{GenerateConfig, GenerateInfo};
+
+/// The kind of build that is generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend {
+    /// One `buildRustCrate` derivation per crate.
+    BuildRustCrate,
+    /// A single `rustPlatform.buildRustPackage` derivation.
+    RustPlatform,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "buildrustcrate" => Ok(Backend::BuildRustCrate),
+            "rustplatform" => Ok(Backend::RustPlatform),
+            _ => bail!(
+                "unknown backend '{}', expected 'buildrustcrate' or 'rustplatform'",
+                s
+            ),
+        }
+    }
+}
+
+/// The input for rendering the `rustPlatform` based build.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RustPlatformBuild {
+    /// The name of the root package.
+    pub pname: String,
+    /// The version of the root package.
+    pub version: String,
+    /// The source directory relative to the output file.
+    pub src: PathBuf,
+    /// The hash of the vendored dependencies.
+    pub cargo_hash: String,
+    /// The generation info.
+    pub info: GenerateInfo,
+    /// The generation configuration.
+    pub config: GenerateConfig,
+}
+
+impl RustPlatformBuild {
+    /// Returns the build for the root package of the given config.
+    ///
+    /// If `cargo_hash` is not given, it is prefetched by building the vendored dependencies
+    /// with `nix`.
+    pub fn for_config(
+        info: &GenerateInfo,
+        config: &GenerateConfig,
+        nix: &Nix,
+        cargo_hash: Option<String>,
+    ) -> Result<RustPlatformBuild, Error> {
+        let cargo_toml = match config.cargo_toml.as_slice() {
+            [cargo_toml] => cargo_toml,
+            _ => bail!("the rustplatform backend supports exactly one Cargo.toml"),
+        };
+        let metadata = crate::cargo_metadata(config, cargo_toml)?;
+        let root = metadata.root_package().ok_or_else(|| {
+            format_err!(
+                "the rustplatform backend needs a root package in {}",
+                cargo_toml.to_string_lossy()
+            )
+        })?;
+
+        let src = relative_source_dir(&config.output, cargo_toml)?;
+        let cargo_hash = match cargo_hash {
+            Some(cargo_hash) => cargo_hash,
+            None => prefetch_cargo_hash(config, nix, &src, &root.name, &root.version.to_string())?,
+        };
+
+        Ok(RustPlatformBuild {
+            pname: root.name.clone(),
+            version: root.version.to_string(),
+            src,
+            cargo_hash,
+            info: info.clone(),
+            config: config.clone(),
+        })
+    }
+}
+
+/// Returns the directory of `cargo_toml` relative to the directory of `output` as a nix path.
+fn relative_source_dir(output: &Path, cargo_toml: &Path) -> Result<PathBuf, Error> {
+    let canonicalize = |path: &Path| {
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path
+        };
+        path.canonicalize()
+            .map_err(|e| format_err!("while canonicalizing {}: {}", path.to_string_lossy(), e))
+    };
+    let output_dir = canonicalize(output.parent().unwrap_or_else(|| Path::new(".")))?;
+    let source_dir = canonicalize(cargo_toml.parent().unwrap_or_else(|| Path::new(".")))?;
+    let relative = diff_paths(&source_dir, &output_dir).unwrap_or(source_dir);
+    Ok(if relative.as_os_str().is_empty() {
+        PathBuf::from("./.")
+    } else if relative.starts_with("..") {
+        relative
+    } else {
+        Path::new("./").join(relative)
+    })
+}
+
+/// Builds the vendored dependencies with a fake hash and returns the actual hash.
+fn prefetch_cargo_hash(
+    config: &GenerateConfig,
+    nix: &Nix,
+    src: &Path,
+    pname: &str,
+    version: &str,
+) -> Result<String, Error> {
+    let output_dir = config.output.parent().unwrap_or_else(|| Path::new("."));
+    let expr = format!(
+        "let pkgs = import ({nixpkgs}) {{ config = {{}}; }}; \
+         in pkgs.rustPlatform.fetchCargoVendor {{ \
+           name = {name}; src = pkgs.lib.cleanSource {src}; hash = pkgs.lib.fakeHash; \
+         }}",
+        nixpkgs = config.nixpkgs_path,
+        name = crate::render::escape_nix_string(&format!("{}-{}", pname, version)),
+        src = crate::render::nix_path(&src.to_string_lossy()),
+    );
+    eprintln!(
+        "Prefetching vendored dependencies of {} {}.",
+        pname, version
+    );
+    let output = nix
+        .build_expr(&expr)
+        .current_dir(if output_dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            output_dir
+        })
+        .output()
+        .map_err(|e| format_err!("while spawning nix: {}", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    parse_hash_mismatch(&stderr).ok_or_else(|| {
+        format_err!(
+            "could not determine the cargo hash, nix exited with: {}\n{}",
+            output.status.code().unwrap_or(-1),
+            stderr
+        )
+    })
+}
+
+/// Extracts the actual hash from the output of a build with a wrong hash.
+///
+/// ```
+/// use crate2nix::rust_platform::parse_hash_mismatch;
+/// let output = "error: hash mismatch in fixed-output derivation '/nix/store/...':\n\
+///               specified: sha256-AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=\n\
+///                  got:    sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=\n";
+/// assert_eq!(
+///     parse_hash_mismatch(output).unwrap(),
+///     "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+/// );
+/// assert_eq!(parse_hash_mismatch("error: something else"), None);
+/// ```
+pub fn parse_hash_mismatch(output: &str) -> Option<String> {
+    output
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix("got:"))
+        .map(|hash| hash.trim().to_string())
+}
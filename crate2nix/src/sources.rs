@@ -4,6 +4,7 @@ use crate::{
     config,
     prefetch::PrefetchableSource,
     resolve::{CratesIoSource, GitSource},
+    warnings::{warn, WarningKind},
 };
 use anyhow::{bail, format_err, Context, Error};
 use semver::Version;
@@ -11,7 +12,7 @@ use std::{
     borrow::Cow,
     path::{Path, PathBuf},
 };
-use std::{fs::File, io::BufRead, process::Command, time::SystemTime};
+use std::{fs::File, io::BufRead, time::SystemTime};
 use url::Url;
 
 /// Returns the completed Source::CratesIo definition by prefetching the hash.
@@ -22,7 +23,7 @@ pub fn crates_io_source(name: String, version: Version) -> Result<config::Source
         sha256: None,
     };
 
-    eprint!("Prefetching {}: ", prefetchable.to_string());
+    eprint!("Prefetching {}: ", prefetchable);
     let sha256 = prefetchable.prefetch()?;
     eprintln!("done.");
 
@@ -40,18 +41,56 @@ pub fn git_io_source(url: Url, rev: String) -> Result<config::Source, Error> {
         rev: rev.clone(),
         r#ref: None,
         sha256: None,
+        archive_url: None,
     };
 
-    eprint!("Prefetching {}: ", prefetchable.to_string());
+    eprint!("Prefetching {}: ", prefetchable);
     let sha256 = prefetchable.prefetch()?;
     eprintln!("done.");
 
     Ok(config::Source::Git { url, rev, sha256 })
 }
 
+/// Returns the completed Source::Git definition for the revision that `branch`
+/// currently points to, as reported by `git ls-remote`.
+pub fn pinned_git_source(url: Url, branch: &str) -> Result<config::Source, Error> {
+    let branch_ref = format!("refs/heads/{}", branch);
+    let output =
+        crate::prefetch::get_command_output("git", &["ls-remote", url.as_str(), &branch_ref])?;
+    let rev = ls_remote_rev(&output, &branch_ref)
+        .ok_or_else(|| format_err!("Did not find branch '{}' in {}.", branch, url))?;
+    eprintln!("Branch {} of {} is at {}.", branch, url, rev);
+    git_io_source(url, rev)
+}
+
+/// Returns the revision of the given ref in the output of `git ls-remote`.
+fn ls_remote_rev(output: &str, git_ref: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (rev, name) = line.split_once('\t')?;
+        if name.trim() == git_ref {
+            Some(rev.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+#[test]
+fn test_ls_remote_rev() {
+    let output = "0123456789abcdef0123456789abcdef01234567\trefs/heads/main\n\
+                  fedcba9876543210fedcba9876543210fedcba98\trefs/heads/main-old\n";
+    assert_eq!(
+        ls_remote_rev(output, "refs/heads/main").as_deref(),
+        Some("0123456789abcdef0123456789abcdef01234567")
+    );
+    assert_eq!(ls_remote_rev(output, "refs/heads/dev"), None);
+}
+
 /// Operations on assmebling out-of-tree sources via nix.
 pub struct FetchedSources<'a> {
     crate2nix_json_path: Cow<'a, Path>,
+    copy: bool,
+    nix: Option<crate::nix_build::Nix>,
 }
 
 const FETCHED_SOURCES: &str = "crate2nix-sources";
@@ -61,9 +100,24 @@ impl<'a> FetchedSources<'a> {
     pub fn new<P: Into<Cow<'a, Path>>>(path: P) -> FetchedSources<'a> {
         FetchedSources {
             crate2nix_json_path: path.into(),
+            copy: false,
+            nix: None,
         }
     }
 
+    /// Fetch with `nix` command lines for the given nix instead of the detected one.
+    pub fn nix(mut self, nix: crate::nix_build::Nix) -> FetchedSources<'a> {
+        self.nix = Some(nix);
+        self
+    }
+
+    /// Copy the fetched sources into a real directory instead of symlinking
+    /// to them in the nix store.
+    pub fn copied(mut self, copy: bool) -> FetchedSources<'a> {
+        self.copy = copy;
+        self
+    }
+
     fn project_dir(&self) -> PathBuf {
         self.crate2nix_json_path
             .parent()
@@ -75,7 +129,9 @@ impl<'a> FetchedSources<'a> {
         self.project_dir().join("crate2nix-sources.nix")
     }
 
-    /// Create a config-nix if it doesn't exist yet.
+    /// Writes crate2nix-sources.nix from the current template.
+    ///
+    /// Fails if the existing file does not contain the `@generated by crate2nix` marker.
     pub fn regenerate_sources_nix(&self) -> Result<(), Error> {
         let info = crate::GenerateInfo::default();
 
@@ -87,7 +143,7 @@ impl<'a> FetchedSources<'a> {
         }
 
         if self.sources_nix().exists() {
-            let reader = std::io::BufReader::new(File::open(&self.sources_nix())?);
+            let reader = std::io::BufReader::new(File::open(self.sources_nix())?);
             let generated = reader.lines().any(|l| {
                 l.map(|l| l.contains("@generated by crate2nix"))
                     .unwrap_or(false)
@@ -97,7 +153,7 @@ impl<'a> FetchedSources<'a> {
             }
         }
 
-        crate::render::SOURCES_NIX.write_to_file(&self.sources_nix(), &info)?;
+        crate::render::SOURCES_NIX.write_to_file(self.sources_nix(), &info)?;
 
         Ok(())
     }
@@ -107,26 +163,48 @@ impl<'a> FetchedSources<'a> {
         self.regenerate_sources_nix()
             .context("while regenerating crate2nix-sources.nix")?;
 
-        let fetched_sources_symlink = self.project_dir().join(FETCHED_SOURCES);
+        let nix = self
+            .nix
+            .clone()
+            .unwrap_or_else(crate::nix_build::Nix::detect);
+        let fetched_sources = self.project_dir().join(FETCHED_SOURCES);
+        if !self.copy {
+            download_and_link_out_of_tree_sources(
+                &nix,
+                self.project_dir(),
+                self.sources_nix(),
+                &fetched_sources,
+                "fetchedSources",
+            )
+            .context("while building crate2nix-sources directory")?;
+            return Ok(fetched_sources);
+        }
+
+        let temp_symlink =
+            self.project_dir()
+                .join(format!(".{}.tmp-{}", FETCHED_SOURCES, std::process::id()));
         download_and_link_out_of_tree_sources(
+            &nix,
             self.project_dir(),
-            &self.sources_nix(),
-            &fetched_sources_symlink,
+            self.sources_nix(),
+            &temp_symlink,
             "fetchedSources",
         )
         .context("while building crate2nix-sources directory")?;
+        let copied = remove_fetched_sources(&fetched_sources)
+            .and_then(|_| crate::util::copy_dir_dereferencing(&temp_symlink, &fetched_sources));
+        std::fs::remove_file(&temp_symlink)?;
+        copied.context("while copying crate2nix-sources directory")?;
 
-        Ok(fetched_sources_symlink)
+        Ok(fetched_sources)
     }
 
     /// Fetches the sources via nix and returns the paths to their Cargo.tomls.
+    ///
+    /// Every source is resolved on its own with the `Cargo.lock` that it ships, so the
+    /// transitive dependencies of a fetched source are the ones pinned by its lock file.
     pub fn get_cargo_tomls(&self) -> Result<Vec<PathBuf>, Error> {
         let fetched_sources_symlink = self.project_dir().join(FETCHED_SOURCES);
-        let last_modified: fn(&std::path::Path) -> Option<SystemTime> = |f: &std::path::Path| {
-            std::fs::symlink_metadata(f)
-                .ok()
-                .and_then(|m| m.modified().ok())
-        };
 
         let has_nix_sources = {
             let config = crate::config::Config::read_from_or_default(&self.crate2nix_json_path)?;
@@ -135,19 +213,50 @@ impl<'a> FetchedSources<'a> {
                 .values()
                 .any(|s| matches!(s, config::Source::Nix { .. }))
         };
-        let outdated = || {
-            let symlink_generated =
-                last_modified(&fetched_sources_symlink).unwrap_or(SystemTime::UNIX_EPOCH);
-            let sources_modified =
-                last_modified(&self.crate2nix_json_path).unwrap_or_else(SystemTime::now);
-            symlink_generated < sources_modified
+        // Refetch if the layout does not match, e.g. after switching to copying.
+        let layout_mismatch = || {
+            std::fs::symlink_metadata(&fetched_sources_symlink)
+                .map(|m| m.file_type().is_symlink() == self.copy)
+                .unwrap_or(false)
         };
-        if has_nix_sources || outdated() {
+        if has_nix_sources || self.outdated() || layout_mismatch() {
             eprintln!("Fetching sources.");
             self.fetch()?;
         }
 
-        let workspace_member_dir = fetched_sources_symlink;
+        self.fetched_cargo_tomls()
+    }
+
+    /// Returns the paths to the Cargo.tomls of the already fetched sources.
+    ///
+    /// Unlike [FetchedSources::get_cargo_tomls], this never fetches anything and fails
+    /// if the sources were not fetched since `crate2nix.json` changed.
+    pub fn get_fetched_cargo_tomls(&self) -> Result<Vec<PathBuf>, Error> {
+        if self.outdated() {
+            bail!(
+                "The sources in {} are missing or outdated, please run crate2nix source fetch.",
+                self.project_dir().join(FETCHED_SOURCES).to_string_lossy()
+            );
+        }
+        self.fetched_cargo_tomls()
+    }
+
+    /// Whether the sources were not fetched since `crate2nix.json` changed.
+    fn outdated(&self) -> bool {
+        let last_modified = |f: &Path| {
+            std::fs::symlink_metadata(f)
+                .ok()
+                .and_then(|m| m.modified().ok())
+        };
+        let symlink_generated = last_modified(&self.project_dir().join(FETCHED_SOURCES))
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let sources_modified =
+            last_modified(&self.crate2nix_json_path).unwrap_or_else(SystemTime::now);
+        symlink_generated < sources_modified
+    }
+
+    fn fetched_cargo_tomls(&self) -> Result<Vec<PathBuf>, Error> {
+        let workspace_member_dir = self.project_dir().join(FETCHED_SOURCES);
         let mut cargo_tomls: Vec<PathBuf> = Vec::new();
         for entry in std::fs::read_dir(&workspace_member_dir).map_err(|e| {
             format_err!(
@@ -167,18 +276,25 @@ impl<'a> FetchedSources<'a> {
             if path.is_dir() {
                 let cargo_toml = path.join("Cargo.toml");
                 if !cargo_toml.exists() {
-                    eprintln!(
-                        "WARNING: No Cargo.toml found in {}.\n\
-                               This will lead to later failures.",
-                        path.to_string_lossy()
+                    warn(
+                        WarningKind::MissingCargoToml,
+                        format!(
+                            "No Cargo.toml found in {}.\n\
+                             This will lead to later failures.",
+                            path.to_string_lossy()
+                        ),
                     );
                 }
                 let cargo_lock = path.join("Cargo.lock");
                 if !cargo_lock.exists() {
-                    eprintln!(
-                        "WARNING: No Cargo.lock found in {}.\n\
-                               This will lead to later failures.",
-                        path.to_string_lossy()
+                    warn(
+                        WarningKind::MissingCargoLock,
+                        format!(
+                            "No Cargo.lock found in {}.\n\
+                             Fetched sources are resolved with the Cargo.lock they ship, \
+                             so generating will fail without one.",
+                            path.to_string_lossy()
+                        ),
                     );
                 }
                 cargo_tomls.push(cargo_toml);
@@ -189,7 +305,18 @@ impl<'a> FetchedSources<'a> {
     }
 }
 
+/// Removes the fetched sources, no matter whether they are a symlink or a copy.
+fn remove_fetched_sources(fetched_sources: &Path) -> Result<(), Error> {
+    match std::fs::symlink_metadata(fetched_sources) {
+        Ok(m) if m.is_dir() => std::fs::remove_dir_all(fetched_sources)?,
+        Ok(_) => std::fs::remove_file(fetched_sources)?,
+        Err(_) => {}
+    }
+    Ok(())
+}
+
 fn download_and_link_out_of_tree_sources(
+    nix: &crate::nix_build::Nix,
     project_dir: impl AsRef<Path>,
     sources_nix: impl AsRef<Path>,
     generated_sources_symlink: impl AsRef<Path>,
@@ -200,19 +327,229 @@ fn download_and_link_out_of_tree_sources(
     let caption = format!("Fetching sources via {} {}", sources_nix, nix_attr);
     crate::command::run(
         &caption,
-        Command::new("nix").current_dir(&project_dir).args(&[
-            "--show-trace",
-            "build",
-            "-f",
-            &sources_nix,
-            nix_attr,
-            "-o",
-            generated_sources_symlink
-                .as_ref()
-                .to_string_lossy()
-                .as_ref(),
-        ]),
+        nix.build(&sources_nix, nix_attr)
+            .current_dir(&project_dir)
+            .arg("-o")
+            .arg(generated_sources_symlink.as_ref()),
     )?;
 
     Ok(())
 }
+
+#[test]
+fn test_regenerate_sources_nix() {
+    let dir = tempdir::TempDir::new("crate2nix_regenerate_sources_nix").unwrap();
+    let crate2nix_json = dir.path().join("crate2nix.json");
+    config::Config::default().write_to(&crate2nix_json).unwrap();
+    let sources_nix = dir.path().join("crate2nix-sources.nix");
+    let sources = FetchedSources::new(crate2nix_json.as_path());
+
+    std::fs::write(&sources_nix, "# @generated by crate2nix 0.1.0\n{ }\n").unwrap();
+    sources.regenerate_sources_nix().unwrap();
+    let regenerated = std::fs::read_to_string(&sources_nix).unwrap();
+    assert!(regenerated.contains("@generated by crate2nix"));
+    assert!(regenerated.contains("fetchedSources"));
+
+    std::fs::write(&sources_nix, "{ }\n").unwrap();
+    assert!(sources.regenerate_sources_nix().is_err());
+    assert_eq!(std::fs::read_to_string(&sources_nix).unwrap(), "{ }\n");
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_get_cargo_tomls_from_copied_sources() {
+    let dir = tempdir::TempDir::new("crate2nix_copied_sources").unwrap();
+    let crate2nix_json = dir.path().join("crate2nix.json");
+    let mut config = config::Config::default();
+    config.upsert_source(
+        None,
+        config::Source::CratesIo {
+            name: "some_crate".to_string(),
+            version: "1.0.0".parse().unwrap(),
+            sha256: "0000000000000000000000000000000000000000000000000000".to_string(),
+        },
+    );
+    config.write_to(&crate2nix_json).unwrap();
+
+    // A copied layout as left behind by a previous fetch.
+    let member = dir.path().join(FETCHED_SOURCES).join("some_crate");
+    std::fs::create_dir_all(&member).unwrap();
+    std::fs::write(member.join("Cargo.toml"), "[package]").unwrap();
+    std::fs::write(member.join("Cargo.lock"), "").unwrap();
+
+    let cargo_tomls = FetchedSources::new(crate2nix_json.as_path())
+        .copied(true)
+        .get_cargo_tomls()
+        .unwrap();
+    assert_eq!(cargo_tomls, vec![member.join("Cargo.toml")]);
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_get_fetched_cargo_tomls_does_not_fetch() {
+    let dir = tempdir::TempDir::new("crate2nix_fetched_cargo_tomls").unwrap();
+    let crate2nix_json = dir.path().join("crate2nix.json");
+    let mut config = config::Config::default();
+    config.upsert_source(
+        None,
+        config::Source::CratesIo {
+            name: "some_crate".to_string(),
+            version: "1.0.0".parse().unwrap(),
+            sha256: "0000000000000000000000000000000000000000000000000000".to_string(),
+        },
+    );
+    config.write_to(&crate2nix_json).unwrap();
+    let sources = FetchedSources::new(crate2nix_json.as_path());
+
+    let err = sources.get_fetched_cargo_tomls().unwrap_err().to_string();
+    assert!(err.contains("missing or outdated"), "{}", err);
+    assert!(!dir.path().join("crate2nix-sources.nix").exists());
+
+    let member = dir.path().join(FETCHED_SOURCES).join("some_crate");
+    std::fs::create_dir_all(&member).unwrap();
+    std::fs::write(member.join("Cargo.toml"), "[package]").unwrap();
+    std::fs::write(member.join("Cargo.lock"), "").unwrap();
+    assert_eq!(
+        sources.get_fetched_cargo_tomls().unwrap(),
+        vec![member.join("Cargo.toml")]
+    );
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_fetched_source_is_resolved_with_its_own_lock_file() {
+    let dir = tempdir::TempDir::new("crate2nix_fetched_source_lock").unwrap();
+    let write = |path: &Path, content: &str| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+
+    let project = dir.path().join("project");
+    let cargo_toml = crate::test::write_lib_project(&project, "main");
+
+    // A fetched git source with its own lock file and a transitive dependency that the
+    // main project does not know about.
+    let fetched = project.join(FETCHED_SOURCES).join("gitdep");
+    write(
+        &fetched.join("Cargo.toml"),
+        "[package]\nname = \"gitdep\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+         [dependencies]\ninner = { path = \"inner\" }\n\n[workspace]\n",
+    );
+    write(&fetched.join("src").join("lib.rs"), "");
+    write(
+        &fetched.join("inner").join("Cargo.toml"),
+        "[package]\nname = \"inner\"\nversion = \"0.2.0\"\nedition = \"2018\"\n",
+    );
+    write(&fetched.join("inner").join("src").join("lib.rs"), "");
+    write(
+        &fetched.join("Cargo.lock"),
+        "version = 3\n\n[[package]]\nname = \"gitdep\"\nversion = \"0.1.0\"\n\
+         dependencies = [\n \"inner\",\n]\n\n[[package]]\nname = \"inner\"\nversion = \"0.2.0\"\n",
+    );
+
+    let config = crate::GenerateConfig {
+        cargo_toml: vec![cargo_toml.clone(), fetched.join("Cargo.toml")],
+        ..crate::test::generate_config_for(&cargo_toml)
+    };
+    let build_info =
+        crate::BuildInfo::for_config(&crate::GenerateInfo::default(), &config).unwrap();
+    let gitdep = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "gitdep")
+        .expect("gitdep");
+    let inner = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "inner")
+        .expect("inner");
+    assert_eq!(inner.version, Version::new(0, 2, 0));
+    assert_eq!(
+        gitdep
+            .dependencies
+            .iter()
+            .map(|d| &d.package_id)
+            .collect::<Vec<_>>(),
+        vec![&inner.package_id]
+    );
+
+    // Without its own lock file, the fetched source cannot be resolved.
+    std::fs::remove_file(fetched.join("Cargo.lock")).unwrap();
+    let err = crate::BuildInfo::for_config(&crate::GenerateInfo::default(), &config)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("gitdep"), "{}", err);
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_fetched_source_keeps_the_registry_versions_of_its_lock_file() {
+    let dir = tempdir::TempDir::new("crate2nix_fetched_source_registry_lock").unwrap();
+    let index_url =
+        crate::test::write_local_git_registry(dir.path(), &[("private_crate", "1.0.0")]);
+    let fetched = dir
+        .path()
+        .join("project")
+        .join(FETCHED_SOURCES)
+        .join("gitdep");
+    let dependencies = [("private_crate", "1")];
+    crate::test::local_registry_project(dir.path(), &fetched, &index_url, &dependencies);
+
+    // A newer version is published after the lock file of the source pinned 1.0.0.
+    crate::test::write_local_git_registry(
+        dir.path(),
+        &[("private_crate", "1.0.0"), ("private_crate", "1.1.0")],
+    );
+    let metadata =
+        crate::test::local_registry_project(dir.path(), &fetched, &index_url, &dependencies);
+
+    let config = crate::GenerateConfig {
+        cargo_toml: vec![fetched.join("Cargo.toml")],
+        output: fetched.join("Cargo.nix"),
+        ..crate::test::generate_config()
+    };
+    let mut build_info = crate::BuildInfo::new(
+        &crate::GenerateInfo::default(),
+        &config,
+        crate::config::Config::default(),
+        crate::IndexedMetadata::new_from(metadata).unwrap(),
+    )
+    .unwrap();
+    let lock_file_checksums =
+        crate::extract_hashes_from_lockfile(&config, &mut build_info).unwrap();
+    crate::fill_registry_sources(
+        &mut build_info,
+        &mut crate::registry::IndexReader::default(),
+        &lock_file_checksums,
+        false,
+    )
+    .unwrap();
+
+    let private_crate = build_info
+        .crates
+        .iter()
+        .find(|c| c.crate_name == "private_crate")
+        .expect("private_crate");
+    assert_eq!(private_crate.version, Version::new(1, 0, 0));
+    let crate_file = dir.path().join("dl").join("private_crate-1.0.0.crate");
+    let sha256 = crate::util::sha256_hex(&std::fs::read(&crate_file).unwrap());
+    match &private_crate.source {
+        crate::resolve::ResolvedSource::Registry(source) => {
+            assert_eq!(
+                source.url.as_deref(),
+                Some(url::Url::from_file_path(&crate_file).unwrap().as_str())
+            );
+            assert_eq!(
+                source.sha256.as_deref(),
+                Some(nix_base32::to_nix_base32(&hex::decode(sha256).unwrap()).as_str())
+            );
+        }
+        source => panic!("unexpected source {:?}", source),
+    }
+
+    dir.close().unwrap();
+}
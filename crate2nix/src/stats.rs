@@ -0,0 +1,79 @@
+//! Machine-readable statistics about a generation run, e.g. for tracking performance in CI.
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Instant};
+
+/// Timings and prefetch counts of a generation run, written with `--stats-file`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    /// The wall clock time of the phases.
+    pub timings: Timings,
+    /// The number of sources whose hash was prefetched in this run.
+    pub sources_prefetched: usize,
+    /// The number of sources whose hash was known from `Cargo.lock`, `crate-hashes.json`
+    /// or `crate2nix.json`.
+    pub sources_cached: usize,
+    /// The total size of the fetched sources in bytes.
+    ///
+    /// Always `null` for now since the prefetch commands do not report it.
+    pub bytes_fetched: Option<u64>,
+}
+
+/// The wall clock time of the phases of a generation run in milliseconds.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timings {
+    /// Running `cargo metadata` and merging its output.
+    pub metadata_ms: u64,
+    /// Resolving the crate derivations, including registry lookups.
+    pub resolve_ms: u64,
+    /// Looking up and prefetching the hashes of the sources.
+    pub prefetch_ms: u64,
+    /// Rendering and writing the build file, `null` if nothing was rendered.
+    pub render_ms: Option<u64>,
+}
+
+impl Stats {
+    /// Writes the stats as pretty printed JSON to the given path.
+    pub fn write_to(&self, path: &Path) -> Result<(), Error> {
+        crate::util::write_atomically(path, |file| Ok(serde_json::to_writer_pretty(file, self)?))
+    }
+}
+
+/// Returns the milliseconds since `started`.
+pub fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
+
+#[test]
+fn test_stats_of_generation() {
+    let project = tempdir::TempDir::new("crate2nix_stats").unwrap();
+    let cargo_toml = crate::test::write_lib_project(project.path(), "measured");
+    let config = crate::test::generate_config_for(&cargo_toml);
+    let build_info =
+        crate::BuildInfo::for_config(&crate::GenerateInfo::default(), &config).unwrap();
+    assert_eq!(build_info.stats.sources_prefetched, 0);
+    assert_eq!(build_info.stats.sources_cached, 0);
+    assert_eq!(build_info.stats.timings.render_ms, None);
+
+    let stats_file = project.path().join("stats.json");
+    build_info.stats.write_to(&stats_file).unwrap();
+    let written: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&stats_file).unwrap()).unwrap();
+    for timing in ["metadataMs", "resolveMs", "prefetchMs"] {
+        assert!(
+            written["timings"][timing].is_u64(),
+            "{}: {}",
+            timing,
+            written
+        );
+    }
+    assert!(written["timings"]["renderMs"].is_null(), "{}", written);
+    assert_eq!(written["sourcesPrefetched"], 0);
+    assert_eq!(written["sourcesCached"], 0);
+    assert!(written["bytesFetched"].is_null(), "{}", written);
+
+    project.close().unwrap();
+}
@@ -1,6 +1,7 @@
-///! Constructor functions for test data.
+//! Constructor functions for test data.
 use cargo_metadata::{Dependency, Metadata, Node, NodeDep, Package, PackageId, Resolve};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use tempdir::TempDir;
 
 /// Returns bogus crate::GenerateConfig.
@@ -13,9 +14,201 @@ pub fn generate_config() -> crate::GenerateConfig {
         output: "Cargo.nix".into(),
         use_cargo_lock_checksums: true,
         read_crate_hashes: true,
+        nixpkgs_compat: None,
+        yanked_policy: Default::default(),
+        libc: Default::default(),
+        artifact_dependencies: false,
+        all_attribute: false,
+        meta_license: false,
+        meta_source_provenance: false,
+        meta_skip_unpublished: false,
+        metadata_retries: 0,
+        metadata_cache: None,
+        lockfile: None,
+        isolated_metadata: true,
+        build_rust_crate_path: None,
+        offline: false,
     }
 }
 
+/// Returns bogus crate::GenerateConfig for the project of `cargo_toml` that writes its
+/// output next to it.
+pub fn generate_config_for(cargo_toml: &Path) -> crate::GenerateConfig {
+    let dir = cargo_toml.parent().unwrap();
+    crate::GenerateConfig {
+        cargo_toml: vec![cargo_toml.to_path_buf()],
+        output: dir.join("Cargo.nix"),
+        crate_hashes_json: dir.join("crate-hashes.json"),
+        ..generate_config()
+    }
+}
+
+/// Writes a library package `name` 0.1.0 without dependencies and its `Cargo.lock` to
+/// `dir` and returns the path of its `Cargo.toml`.
+pub fn write_lib_project(dir: &Path, name: &str) -> PathBuf {
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    let cargo_toml = dir.join("Cargo.toml");
+    std::fs::write(
+        &cargo_toml,
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+            name
+        ),
+    )
+    .unwrap();
+    std::fs::write(dir.join("src").join("lib.rs"), "").unwrap();
+    std::fs::write(
+        dir.join("Cargo.lock"),
+        format!(
+            "version = 3\n\n[[package]]\nname = \"{}\"\nversion = \"0.1.0\"\n",
+            name
+        ),
+    )
+    .unwrap();
+    cargo_toml
+}
+
+/// Runs the program in `cwd` and returns its output, panicking if it fails.
+pub fn run_successfully(cwd: &Path, program: &str, args: &[&str]) -> Vec<u8> {
+    let output = std::process::Command::new(program)
+        .current_dir(cwd)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{} {:?}: {}",
+        program,
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+/// Writes a git registry index with the given crate names and versions to `dir/index`
+/// and their `.crate` files to `dir/dl`.
+///
+/// Calling it again with more versions publishes them in a new commit of the index;
+/// existing `.crate` files are kept so that their checksums stay the same.
+///
+/// Returns the URL of the index.
+pub fn write_local_git_registry(dir: &Path, crates: &[(&str, &str)]) -> url::Url {
+    let write = |path: PathBuf, content: &[u8]| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    let index_dir = dir.join("index");
+    let dl_dir = dir.join("dl");
+    std::fs::create_dir_all(&dl_dir).unwrap();
+    let dl_url = url::Url::from_directory_path(&dl_dir).unwrap();
+    write(
+        index_dir.join("config.json"),
+        format!("{{\"dl\":\"{}{{crate}}-{{version}}.crate\"}}", dl_url).as_bytes(),
+    );
+
+    let mut entries: BTreeMap<&str, String> = BTreeMap::new();
+    for (name, version) in crates {
+        let crate_dir = dir.join("crate").join(format!("{}-{}", name, version));
+        write(
+            crate_dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2018\"\n",
+                name, version
+            )
+            .as_bytes(),
+        );
+        write(crate_dir.join("src/lib.rs"), b"");
+        let crate_file = dl_dir.join(format!("{}-{}.crate", name, version));
+        if !crate_file.exists() {
+            run_successfully(
+                &dir.join("crate"),
+                "tar",
+                &[
+                    "czf",
+                    &crate_file.to_string_lossy(),
+                    &format!("{}-{}", name, version),
+                ],
+            );
+        }
+        let cksum = crate::util::sha256_hex(&std::fs::read(&crate_file).unwrap());
+        entries.entry(name).or_default().push_str(&format!(
+            "{{\"name\":\"{}\",\"vers\":\"{}\",\"deps\":[],\"cksum\":\"{}\",\
+             \"features\":{{}},\"yanked\":false}}\n",
+            name, version, cksum
+        ));
+    }
+    for (name, lines) in entries {
+        let path = format!("{}/{}", crate::registry::index_prefix(name), name);
+        write(index_dir.join(path), lines.as_bytes());
+    }
+
+    run_successfully(&index_dir, "git", &["init", "--quiet"]);
+    run_successfully(&index_dir, "git", &["add", "--all"]);
+    run_successfully(
+        &index_dir,
+        "git",
+        &[
+            "-c",
+            "user.name=crate2nix",
+            "-c",
+            "user.email=crate2nix@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            "index",
+        ],
+    );
+    url::Url::from_directory_path(&index_dir).unwrap()
+}
+
+/// Writes a project to `project_dir` that depends on crates of the local registry at
+/// `index_url`, configured as `my-registry`, and returns its `cargo metadata`.
+///
+/// The dependencies are given by name and version requirement. Cargo runs with
+/// `dir/cargo_home` as its home, which leaves the one of the user untouched. An existing
+/// `Cargo.lock` of the project is kept.
+pub fn local_registry_project(
+    dir: &Path,
+    project_dir: &Path,
+    index_url: &url::Url,
+    dependencies: &[(&str, &str)],
+) -> Metadata {
+    std::fs::create_dir_all(project_dir.join("src")).unwrap();
+    std::fs::create_dir_all(project_dir.join(".cargo")).unwrap();
+    let mut cargo_toml = "[package]\nname = \"main\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+                          [dependencies]\n"
+        .to_string();
+    for (name, version) in dependencies {
+        cargo_toml.push_str(&format!(
+            "{} = {{ version = \"{}\", registry = \"my-registry\" }}\n",
+            name, version
+        ));
+    }
+    std::fs::write(project_dir.join("Cargo.toml"), cargo_toml).unwrap();
+    std::fs::write(project_dir.join("src/lib.rs"), "").unwrap();
+    std::fs::write(
+        project_dir.join(".cargo/config.toml"),
+        format!(
+            "[registries]\nmy-registry = {{ index = \"{}\" }}\n",
+            index_url
+        ),
+    )
+    .unwrap();
+
+    let output = std::process::Command::new("cargo")
+        .current_dir(project_dir)
+        .env("CARGO_HOME", dir.join("cargo_home"))
+        .args(["metadata", "--format-version", "1"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "cargo metadata: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
 #[derive(Debug)]
 pub struct MetadataEnv {
     /// Keep track of temporary directories.
@@ -56,10 +249,10 @@ impl MetadataEnv {
     }
 
     fn mut_resolve(&mut self) -> &mut Resolve {
-        self.metadata.resolve.get_or_insert_with(|| empty_resolve())
+        self.metadata.resolve.get_or_insert_with(empty_resolve)
     }
 
-    pub fn add_package_and_node(&mut self, name: &str) -> PackageAndNode {
+    pub fn add_package_and_node(&mut self, name: &str) -> PackageAndNode<'_> {
         let package = package(name, "0.1.0");
         let package_idx = self.metadata.packages.len();
         self.metadata.packages.push(package.clone());
@@ -82,6 +275,17 @@ impl MetadataEnv {
         crate::IndexedMetadata::new_from(self.metadata()).unwrap()
     }
 
+    /// Returns the build info for rendering, resolved with the given `crate2nix.json` config.
+    pub fn build_info(&self, crate2nix_json: crate::config::Config) -> crate::BuildInfo {
+        crate::BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &generate_config(),
+            crate2nix_json,
+            self.indexed_metadata(),
+        )
+        .unwrap()
+    }
+
     pub fn close(&mut self) {
         for temp_dir in self.temp_dirs.drain(..) {
             temp_dir.close().expect("while closing temp");
@@ -107,6 +311,12 @@ impl<'a> PackageAndNode<'a> {
         self
     }
 
+    pub fn make_workspace_member(&mut self) -> &mut Self {
+        let package_id = self.get_mut_package().id.clone();
+        self.env.mut_metadata().workspace_members.push(package_id);
+        self
+    }
+
     pub fn get_mut_package(&mut self) -> &mut Package {
         &mut self.env.metadata.packages[self.package_idx]
     }
@@ -264,11 +474,13 @@ where
     T: serde::de::DeserializeOwned,
 {
     use serde_json::{from_value, to_string_pretty};
-    from_value(json()).expect(&format!(
-        "invalid {}: {}",
-        std::any::type_name::<T>(),
-        to_string_pretty(&json()).unwrap()
-    ))
+    from_value(json()).unwrap_or_else(|_| {
+        panic!(
+            "invalid {}: {}",
+            std::any::type_name::<T>(),
+            to_string_pretty(&json()).unwrap()
+        )
+    })
 }
 
 /// Return value from given JSON.
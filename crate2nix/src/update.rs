@@ -0,0 +1,174 @@
+//! Bumping a single dependency and regenerating the build file with the recorded arguments.
+
+use anyhow::{bail, format_err, Error};
+use std::path::Path;
+
+/// Returns the arguments of the `crate2nix` command that generated the given build file,
+/// as recorded in its header.
+pub fn generate_arguments(cargo_nix: &Path) -> Result<Vec<String>, Error> {
+    let content = std::fs::read_to_string(cargo_nix)
+        .map_err(|e| format_err!("while reading {}: {}", cargo_nix.to_string_lossy(), e))?;
+    let mut lines = content.lines();
+    lines
+        .find(|l| l.contains("@generated by crate2nix") && l.ends_with("with the command:"))
+        .and_then(|_| lines.next())
+        .and_then(|l| l.strip_prefix('#'))
+        .ok_or_else(|| {
+            format_err!(
+                "No generate command recorded in {}, please run crate2nix generate.",
+                cargo_nix.to_string_lossy()
+            )
+        })
+        .and_then(parse_nix_strings)
+}
+
+/// Parses a whitespace separated list of nix strings as written by `escape_nix_string`.
+fn parse_nix_strings(line: &str) -> Result<Vec<String>, Error> {
+    let mut strings = Vec::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.extend(chars.next()),
+                        Some(c) => string.push(c),
+                        None => bail!("unterminated string in '{}'", line),
+                    }
+                }
+                strings.push(string);
+            }
+            c => bail!("unexpected '{}' in '{}'", c, line),
+        }
+    }
+    Ok(strings)
+}
+
+/// Updates only the given crate in the `Cargo.lock` of the given `Cargo.toml` with
+/// `cargo update -p`, optionally to the `precise` version.
+pub fn cargo_update(
+    cargo_toml: &Path,
+    crate_name: &str,
+    precise: Option<&str>,
+) -> Result<(), Error> {
+    let mut command = std::process::Command::new("cargo");
+    command
+        .arg("update")
+        .arg("--manifest-path")
+        .arg(cargo_toml)
+        .args(["-p", crate_name]);
+    if let Some(precise) = precise {
+        command.args(["--precise", precise]);
+    }
+    crate::command::run(&format!("Updating {}", crate_name), &mut command)
+}
+
+#[test]
+fn test_generate_arguments() {
+    let dir = tempdir::TempDir::new("crate2nix_generate_arguments").unwrap();
+    let cargo_nix = dir.path().join("Cargo.nix");
+    let args = vec![
+        "generate",
+        "-o",
+        "my \"Cargo\".nix",
+        "--features",
+        "a\\b ${x}",
+    ];
+    std::fs::write(
+        &cargo_nix,
+        format!(
+            "\n# This file was @generated by crate2nix 0.10.0 with the command:\n#  {}\n{{ }}\n",
+            args.iter()
+                .map(|arg| format!(" {}", crate::render::escape_nix_string(arg)))
+                .collect::<String>()
+        ),
+    )
+    .unwrap();
+    assert_eq!(generate_arguments(&cargo_nix).unwrap(), args);
+
+    std::fs::write(&cargo_nix, "{ }\n").unwrap();
+    assert!(generate_arguments(&cargo_nix).is_err());
+    assert!(parse_nix_strings(" \"generate").is_err());
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_update_changes_only_the_updated_crate() {
+    let dir = tempdir::TempDir::new("crate2nix_update").unwrap();
+    let project = dir.path();
+    let write = |path: &Path, content: &str| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    let manifest = |name: &str, version: &str| {
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2018\"\n",
+            name, version
+        )
+    };
+    write(
+        &project.join("Cargo.toml"),
+        &format!(
+            "{}\n[dependencies]\nbumped = {{ path = \"bumped\" }}\nkept = {{ path = \"kept\" }}\n",
+            manifest("main", "0.1.0")
+        ),
+    );
+    write(&project.join("src").join("lib.rs"), "");
+    for name in ["bumped", "kept"] {
+        write(
+            &project.join(name).join("Cargo.toml"),
+            &manifest(name, "0.1.0"),
+        );
+        write(&project.join(name).join("src").join("lib.rs"), "");
+    }
+    write(
+        &project.join("Cargo.lock"),
+        "version = 3\n\n[[package]]\nname = \"bumped\"\nversion = \"0.1.0\"\n\n\
+         [[package]]\nname = \"kept\"\nversion = \"0.1.0\"\n\n\
+         [[package]]\nname = \"main\"\nversion = \"0.1.0\"\n\
+         dependencies = [\n \"bumped\",\n \"kept\",\n]\n",
+    );
+
+    let config = crate::GenerateConfig {
+        cargo_toml: vec![project.join("Cargo.toml")],
+        output: project.join("Cargo.nix"),
+        crate_hashes_json: project.join("crate-hashes.json"),
+        ..crate::test::generate_config()
+    };
+    let render = || {
+        crate::BuildInfo::for_config(&crate::GenerateInfo::default(), &config)
+            .and_then(|build_info| crate::render::CARGO_NIX.render(&build_info))
+    };
+    let before = render().unwrap();
+
+    write(
+        &project.join("bumped").join("Cargo.toml"),
+        &manifest("bumped", "0.2.0"),
+    );
+    assert!(render().is_err(), "Cargo.lock is outdated");
+    cargo_update(&project.join("Cargo.toml"), "bumped", None).unwrap();
+    let after = render().unwrap();
+
+    let changed: Vec<(&str, &str)> = before
+        .lines()
+        .zip(after.lines())
+        .filter(|(before, after)| before != after)
+        .collect();
+    assert_eq!(before.lines().count(), after.lines().count());
+    assert!(!changed.is_empty());
+    for (before, after) in changed {
+        assert_eq!(before.replace("0.1.0", "0.2.0"), after);
+    }
+    assert_eq!(
+        after.matches("0.2.0").count(),
+        before.matches("0.2.0").count() + 1,
+        "only the version of the bumped crate changed:\n{}",
+        after
+    );
+
+    dir.close().unwrap();
+}
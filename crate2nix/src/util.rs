@@ -1,6 +1,9 @@
 //! Homeless code. Usually abstract and algorithmic.
 
+use anyhow::{format_err, Error};
 use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::Path;
 
 /// Return all occurrences of each item after the first.
 /// ```
@@ -14,3 +17,212 @@ pub fn find_duplicates<'a, T: Ord>(source: impl Iterator<Item = &'a T>) -> Vec<&
     let mut seen = BTreeSet::new();
     source.filter(|v| !seen.insert(*v)).collect()
 }
+
+/// Writes a file via `write`, so that the file at `path` is either fully written or untouched.
+///
+/// The content is written to a temporary file in the same directory which is then renamed
+/// to `path`. If `write` fails, the temporary file is removed.
+pub fn write_atomically(
+    path: impl AsRef<Path>,
+    write: impl FnOnce(&mut File) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format_err!("no file name in {}", path.to_string_lossy()))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.tmp-{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = File::create(&temp_path)
+        .map_err(|e| format_err!("while creating {}: {}", temp_path.to_string_lossy(), e))
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all()?;
+            Ok(())
+        })
+        .and_then(|_| {
+            std::fs::rename(&temp_path, path).map_err(|e| {
+                format_err!(
+                    "while renaming {} to {}: {}",
+                    temp_path.to_string_lossy(),
+                    path.to_string_lossy(),
+                    e
+                )
+            })
+        });
+
+    if result.is_err() && temp_path.exists() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Returns the hex-encoded SHA-256 of `data`.
+///
+/// Unlike the hashers of the standard library, the result is stable across Rust
+/// releases and platforms, e.g. for cache keys that are committed.
+///
+/// ```
+/// use crate2nix::util::sha256_hex;
+/// assert_eq!(
+///     sha256_hex(b""),
+///     "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+/// );
+/// assert_eq!(
+///     sha256_hex(b"abc"),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// assert_eq!(
+///     sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+///     "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+/// );
+/// ```
+pub fn sha256_hex(data: &[u8]) -> String {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    state
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Recursively copies `source` to `target`, copying the targets of symlinks
+/// instead of the symlinks themselves.
+pub fn copy_dir_dereferencing(source: &Path, target: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(target)
+        .map_err(|e| format_err!("while creating {}: {}", target.to_string_lossy(), e))?;
+    for entry in std::fs::read_dir(source)
+        .map_err(|e| format_err!("while reading {}: {}", source.to_string_lossy(), e))?
+    {
+        let path = entry?.path();
+        let target = target.join(path.file_name().expect("directory entry has a name"));
+        if path.is_dir() {
+            copy_dir_dereferencing(&path, &target)?;
+        } else {
+            std::fs::copy(&path, &target)
+                .map_err(|e| format_err!("while copying {}: {}", path.to_string_lossy(), e))?;
+            // Files from the nix store are read-only and would make later updates fail.
+            use std::os::unix::fs::PermissionsExt;
+            let mut permissions = std::fs::metadata(&target)?.permissions();
+            permissions.set_mode(permissions.mode() | 0o200);
+            std::fs::set_permissions(&target, permissions)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_atomically_preserves_original_on_failure() {
+    use std::io::Write;
+
+    let dir = tempdir::TempDir::new("crate2nix_write_atomically").unwrap();
+    let path = dir.path().join("Cargo.nix");
+    std::fs::write(&path, "original").unwrap();
+
+    let result = write_atomically(&path, |file| {
+        file.write_all(b"trunc")?;
+        anyhow::bail!("simulated write failure")
+    });
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "original");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    write_atomically(&path, |file| Ok(file.write_all(b"updated")?)).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "updated");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_copy_dir_dereferencing() {
+    let dir = tempdir::TempDir::new("crate2nix_copy_dir").unwrap();
+    let store = dir.path().join("store");
+    std::fs::create_dir_all(store.join("member/src")).unwrap();
+    std::fs::write(store.join("member/Cargo.toml"), "[package]").unwrap();
+    std::fs::write(store.join("member/src/lib.rs"), "").unwrap();
+    let link_farm = dir.path().join("link_farm");
+    std::fs::create_dir(&link_farm).unwrap();
+    std::os::unix::fs::symlink(store.join("member"), link_farm.join("member")).unwrap();
+
+    let copy = dir.path().join("copy");
+    copy_dir_dereferencing(&link_farm, &copy).unwrap();
+    let member = copy.join("member");
+    assert!(!std::fs::symlink_metadata(&member)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(
+        std::fs::read_to_string(member.join("Cargo.toml")).unwrap(),
+        "[package]"
+    );
+    assert!(member.join("src/lib.rs").is_file());
+
+    dir.close().unwrap();
+}
@@ -0,0 +1,214 @@
+//! Structured warnings which are reported while generating build files.
+//!
+//! Warnings are printed to stderr immediately and recorded so that callers
+//! can decide afterwards whether any of them should fail the run. They are recorded
+//! process wide, so warnings reported on worker threads are not lost.
+
+use anyhow::{bail, Error};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt::Display, str::FromStr, sync::Mutex};
+
+/// The kind of a warning, used to select warnings that should be treated as errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// A fetched out-of-tree source does not contain a `Cargo.toml`.
+    MissingCargoToml,
+    /// A fetched out-of-tree source does not contain a `Cargo.lock`.
+    MissingCargoLock,
+    /// No source in `crate2nix.json` matches a workspace member.
+    MissingConfiguredSource,
+    /// A source could not be interpreted and the local directory is used instead.
+    LocalDirectoryFallback,
+    /// `Cargo.lock` does not contain the checksums of all crates.io crates.
+    MissingLockChecksums,
+    /// The same crate version is used from different sources.
+    AmbiguousPackageVersion,
+    /// The root package is not part of the workspace members.
+    RootNotWorkspaceMember,
+    /// A yanked crate version is used.
+    YankedCrate,
+    /// The registry index could not be read to check whether crate versions are yanked.
+    YankedCheckFailed,
+    /// A crate does not contain a license file.
+    MissingLicenseFile,
+    /// A path dependency lies outside of the git repository of the output file.
+    PathDependencyOutsideRepository,
+}
+
+impl WarningKind {
+    /// All warning kinds.
+    pub const ALL: &'static [WarningKind] = &[
+        WarningKind::MissingCargoToml,
+        WarningKind::MissingCargoLock,
+        WarningKind::MissingConfiguredSource,
+        WarningKind::LocalDirectoryFallback,
+        WarningKind::MissingLockChecksums,
+        WarningKind::AmbiguousPackageVersion,
+        WarningKind::RootNotWorkspaceMember,
+        WarningKind::YankedCrate,
+        WarningKind::YankedCheckFailed,
+        WarningKind::MissingLicenseFile,
+        WarningKind::PathDependencyOutsideRepository,
+    ];
+
+    /// The name of the warning kind as used on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::MissingCargoToml => "missing-cargo-toml",
+            WarningKind::MissingCargoLock => "missing-cargo-lock",
+            WarningKind::MissingConfiguredSource => "missing-configured-source",
+            WarningKind::LocalDirectoryFallback => "local-directory-fallback",
+            WarningKind::MissingLockChecksums => "missing-lock-checksums",
+            WarningKind::AmbiguousPackageVersion => "ambiguous-package-version",
+            WarningKind::RootNotWorkspaceMember => "root-not-workspace-member",
+            WarningKind::YankedCrate => "yanked-crate",
+            WarningKind::YankedCheckFailed => "yanked-check-failed",
+            WarningKind::MissingLicenseFile => "missing-license-file",
+            WarningKind::PathDependencyOutsideRepository => "path-dependency-outside-repository",
+        }
+    }
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for WarningKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match WarningKind::ALL.iter().find(|kind| kind.name() == s) {
+            Some(kind) => Ok(*kind),
+            None => bail!(
+                "unknown warning kind '{}', expected one of: {}",
+                s,
+                WarningKind::ALL.iter().join(", ")
+            ),
+        }
+    }
+}
+
+/// A warning that was reported.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Warning {
+    /// The kind of the warning.
+    pub kind: WarningKind,
+    /// The human readable message.
+    pub message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WARNING [{}]: {}", self.kind, self.message)
+    }
+}
+
+static REPORTED: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+/// Held by tests that `take` the recorded warnings so that they do not take each other's.
+#[cfg(test)]
+pub(crate) static TAKE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Prints the warning to stderr and records it.
+pub fn warn(kind: WarningKind, message: impl Into<String>) {
+    let warning = Warning {
+        kind,
+        message: message.into(),
+    };
+    eprintln!("{}", warning);
+    REPORTED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(warning);
+}
+
+/// Returns all warnings recorded so far, on any thread, and clears them.
+pub fn take() -> Vec<Warning> {
+    std::mem::take(&mut *REPORTED.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Selects the warnings that should be treated as errors.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DenyWarnings {
+    /// Deny all warnings.
+    pub all: bool,
+    /// Deny warnings of these kinds.
+    pub kinds: BTreeSet<WarningKind>,
+}
+
+impl DenyWarnings {
+    /// Returns whether warnings of the given kind are denied.
+    pub fn denies(&self, kind: WarningKind) -> bool {
+        self.all || self.kinds.contains(&kind)
+    }
+
+    /// Returns an error listing all denied warnings, if there are any.
+    pub fn check(&self, warnings: &[Warning]) -> Result<(), Error> {
+        let denied: Vec<&Warning> = warnings.iter().filter(|w| self.denies(w.kind)).collect();
+        if denied.is_empty() {
+            return Ok(());
+        }
+
+        bail!(
+            "{} denied warning(s) were reported:\n{}",
+            denied.len(),
+            denied.iter().map(|w| format!("  {}", w)).join("\n")
+        )
+    }
+}
+
+#[test]
+fn test_warning_kind_names_round_trip() {
+    for kind in WarningKind::ALL {
+        assert_eq!(WarningKind::from_str(kind.name()).unwrap(), *kind);
+        assert_eq!(
+            serde_json::to_string(kind).unwrap(),
+            format!("\"{}\"", kind.name())
+        );
+    }
+    assert!(WarningKind::from_str("no-such-warning").is_err());
+}
+
+#[test]
+fn test_deny_warnings() {
+    // Other tests that run in parallel may report warnings as well.
+    let _lock = TAKE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let message = "no Cargo.lock in test_deny_warnings";
+    let ours = |warnings: Vec<Warning>| -> Vec<Warning> {
+        warnings
+            .into_iter()
+            .filter(|w| w.message == message)
+            .collect()
+    };
+    std::thread::spawn(move || warn(WarningKind::MissingCargoLock, message))
+        .join()
+        .unwrap();
+    let reported = ours(take());
+    assert_eq!(reported.len(), 1, "warning from other thread is recorded");
+    assert!(ours(take()).is_empty());
+
+    assert!(DenyWarnings::default().check(&reported).is_ok());
+
+    let deny_all = DenyWarnings {
+        all: true,
+        ..DenyWarnings::default()
+    };
+    let error = deny_all.check(&reported).unwrap_err().to_string();
+    assert!(error.contains("missing-cargo-lock"), "{}", error);
+
+    let deny_other = DenyWarnings {
+        kinds: vec![WarningKind::MissingCargoToml].into_iter().collect(),
+        ..DenyWarnings::default()
+    };
+    assert!(deny_other.check(&reported).is_ok());
+
+    let deny_kind = DenyWarnings {
+        kinds: vec![WarningKind::MissingCargoLock].into_iter().collect(),
+        ..DenyWarnings::default()
+    };
+    assert!(deny_kind.check(&reported).is_err());
+}
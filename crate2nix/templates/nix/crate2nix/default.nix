@@ -14,6 +14,8 @@
 , rootFeatures ? [ ]
 , targetFeatures ? [ ]
 , release ? true
+  # The rustc options of the cargo profile for the crates built for the host platform.
+, profileRustcOpts ? [ ]
 }:
 rec {
   # #}
@@ -21,7 +23,10 @@ rec {
   /* Target (platform) data for conditional dependencies.
     This corresponds roughly to what buildRustCrate is setting.
   */
-  defaultTarget = {
+  defaultTarget = targetForPlatform stdenv.hostPlatform;
+
+  /* The target data of the given nixpkgs platform, see `defaultTarget`. */
+  targetForPlatform = platform: {
     unix = true;
     windows = false;
     fuchsia = true;
@@ -30,20 +35,42 @@ rec {
     # This doesn't appear to be officially documented anywhere yet.
     # See https://github.com/rust-lang-nursery/rust-forge/issues/101.
     os =
-      if stdenv.hostPlatform.isDarwin
+      if platform.isDarwin
       then "macos"
-      else stdenv.hostPlatform.parsed.kernel.name;
-    arch = stdenv.hostPlatform.parsed.cpu.name;
+      else platform.parsed.kernel.name;
+    arch = platform.parsed.cpu.name;
     family = "unix";
-    env = "gnu";
+    env = if platform.isMusl then "musl" else "gnu";
     endian =
-      if stdenv.hostPlatform.parsed.cpu.significantByte.name == "littleEndian"
+      if platform.parsed.cpu.significantByte.name == "littleEndian"
       then "little" else "big";
-    pointer_width = toString stdenv.hostPlatform.parsed.cpu.bits;
-    vendor = stdenv.hostPlatform.parsed.vendor.name;
+    pointer_width = toString platform.parsed.cpu.bits;
+    vendor = platform.parsed.vendor.name;
     debug_assertions = false;
   };
 
+  /* Returns the `CARGO_CFG_*` environment variables that cargo sets for build
+    scripts when building for the given target.
+  */
+  cargoCfgEnv = { target, targetFeatures ? [ ] }:
+    assert (builtins.isAttrs target);
+    assert (builtins.isList targetFeatures);
+    {
+      CARGO_CFG_TARGET_OS = target.os;
+      CARGO_CFG_TARGET_ARCH = target.arch;
+      CARGO_CFG_TARGET_FAMILY = target.family;
+      CARGO_CFG_TARGET_ENV = target.env;
+      CARGO_CFG_TARGET_ENDIAN = target.endian;
+      CARGO_CFG_TARGET_POINTER_WIDTH = target.pointer_width;
+      CARGO_CFG_TARGET_VENDOR = target.vendor;
+    }
+    // lib.optionalAttrs (target.unix or false) { CARGO_CFG_UNIX = ""; }
+    // lib.optionalAttrs (target.windows or false) { CARGO_CFG_WINDOWS = ""; }
+    // lib.optionalAttrs (target.debug_assertions or false) { CARGO_CFG_DEBUG_ASSERTIONS = ""; }
+    // lib.optionalAttrs (targetFeatures != [ ]) {
+      CARGO_CFG_TARGET_FEATURE = lib.concatStringsSep "," targetFeatures;
+    };
+
   /* Filters common temp files and build files. */
   # TODO(pkolloch): Substitute with gitignore filter
   sourceFilter = name: type:
@@ -171,12 +198,52 @@ rec {
       ${lib.concatMapStringsSep "\n" (output: "ln -s ${crate.${output}} ${"$"}${output}") crate.outputs}
     '';
 
+  /* Returns the crate overrides to use when building for the given target triple.
+
+    Next to the usual override functions, an override can be an attribute set
+    with an optional `base` override and overrides by target triple in `perTarget`:
+
+      crateOverrides = defaultCrateOverrides // {
+        openssl-sys = {
+          base = attrs: { buildInputs = [ pkgs.openssl ]; };
+          perTarget."x86_64-apple-darwin" = attrs: {
+            buildInputs = attrs.buildInputs ++ [ pkgs.darwin.apple_sdk.frameworks.Security ];
+          };
+        };
+      };
+
+    The override for the target is applied on top of the base override.
+    Overrides can be functions or attribute sets.
+  */
+  crateOverridesForTarget = targetTriple: crateOverrides:
+    let
+      toFunction = override: if builtins.isFunction override then override else (_: override);
+      forTarget = override:
+        let
+          base = toFunction (override.base or { });
+          perTarget = toFunction (override.perTarget.${targetTriple} or { });
+        in
+        attrs:
+          let
+            baseAttrs = base attrs;
+          in
+          baseAttrs // perTarget (attrs // baseAttrs);
+    in
+    lib.mapAttrs
+      (name: override:
+        if builtins.isAttrs override && (override ? base || override ? perTarget)
+        then forTarget override
+        else override)
+      crateOverrides;
+
   /* A restricted overridable version of builtRustCratesWithFeatures. */
   buildRustCrateWithFeatures =
     { packageId
     , features ? rootFeatures
     , crateOverrides ? defaultCrateOverrides
     , buildRustCrateForPkgsFunc ? null
+      # Other packages to resolve features together with, see builtRustCratesWithFeatures.
+    , unifyFeaturesWith ? [ ]
     , runTests ? false
     , testCrateFlags ? [ ]
     , testInputs ? [ ]
@@ -205,16 +272,17 @@ rec {
                 then buildRustCrateForPkgs
                 else
                   pkgs: (buildRustCrateForPkgs pkgs).override {
-                    defaultCrateOverrides = crateOverrides;
+                    defaultCrateOverrides =
+                      crateOverridesForTarget pkgs.stdenv.hostPlatform.config crateOverrides;
                   }
               );
           builtRustCrates = builtRustCratesWithFeatures {
-            inherit packageId features;
+            inherit packageId features unifyFeaturesWith;
             buildRustCrateForPkgsFunc = buildRustCrateForPkgsFuncOverriden;
             runTests = false;
           };
           builtTestRustCrates = builtRustCratesWithFeatures {
-            inherit packageId features;
+            inherit packageId features unifyFeaturesWith;
             buildRustCrateForPkgsFunc = buildRustCrateForPkgsFuncOverriden;
             runTests = true;
           };
@@ -226,7 +294,9 @@ rec {
                 {
                   crate = drv;
                   testCrate = testDrv;
-                  inherit testCrateFlags testInputs testPreRun testPostRun;
+                  # The configured check inputs are only needed for running the tests.
+                  testInputs = testInputs ++ (crates.${packageId}.checkInputs or [ ]);
+                  inherit testCrateFlags testPreRun testPostRun;
                 }
             else drv;
         in
@@ -236,6 +306,10 @@ rec {
 
   /* Returns an attr set with packageId mapped to the result of buildRustCrateForPkgsFunc
     for the corresponding crate.
+
+    The features of the packages in unifyFeaturesWith are resolved together with the
+    ones of packageId, like `cargo build --workspace` does. Building several packages
+    with the same unifyFeaturesWith therefore builds every common dependency only once.
   */
   builtRustCratesWithFeatures =
     { packageId
@@ -244,41 +318,52 @@ rec {
     , buildRustCrateForPkgsFunc
     , runTests
     , target ? defaultTarget
+    , unifyFeaturesWith ? [ ]
     } @ args:
       assert (builtins.isAttrs crateConfigs);
       assert (builtins.isString packageId);
       assert (builtins.isList features);
       assert (builtins.isAttrs target);
       assert (builtins.isBool runTests);
+      assert (builtins.isList unifyFeaturesWith);
       let
         rootPackageId = packageId;
-        mergedFeatures = mergePackageFeatures
-          (
-            args // {
-              inherit rootPackageId;
-              target = target // { test = runTests; };
-            }
-          );
+        mergedFeatures = lib.foldl
+          (featuresByPackageId: packageId: mergePackageFeatures
+            (
+              args // {
+                inherit packageId rootPackageId featuresByPackageId;
+                target = target // { test = runTests; };
+              }
+            ))
+          { }
+          ([ rootPackageId ] ++ lib.remove rootPackageId unifyFeaturesWith);
         # Memoize built packages so that reappearing packages are only built once.
-        builtByPackageIdByPkgs = mkBuiltByPackageIdByPkgs pkgs;
-        mkBuiltByPackageIdByPkgs = pkgs:
+        builtByPackageIdByPkgs = mkBuiltByPackageIdByPkgs pkgs profileRustcOpts
+          (cargoCfgEnv { inherit target targetFeatures; });
+        # Like cargo, build scripts, their dependencies and proc macros ignore the profile options.
+        # They run on the build platform, so their CARGO_CFG_* variables describe it instead of `target`.
+        mkBuiltByPackageIdByPkgs = pkgs: profileRustcOpts: cfgEnv:
           let
             self = {
-              crates = lib.mapAttrs (packageId: value: buildByPackageIdForPkgsImpl self pkgs packageId) crateConfigs;
-              build = mkBuiltByPackageIdByPkgs pkgs.buildPackages;
+              crates = lib.mapAttrs (packageId: value: buildByPackageIdForPkgsImpl self pkgs profileRustcOpts cfgEnv packageId) crateConfigs;
+              build = mkBuiltByPackageIdByPkgs pkgs.buildPackages [ ]
+                (cargoCfgEnv { target = targetForPlatform pkgs.buildPackages.stdenv.hostPlatform; });
             };
           in
           self;
-        buildByPackageIdForPkgsImpl = self: pkgs: packageId:
+        buildByPackageIdForPkgsImpl = self: pkgs: profileRustcOpts: cfgEnv: packageId:
           let
             features = mergedFeatures."${packageId}" or [ ];
             crateConfig' = crateConfigs."${packageId}";
             crateConfig =
-              builtins.removeAttrs crateConfig' [ "resolvedDefaultFeatures" "devDependencies" ];
+              builtins.removeAttrs crateConfig' [ "resolvedDefaultFeatures" "devDependencies" "checkInputs" ];
             devDependencies =
               lib.optionals
                 (runTests && packageId == rootPackageId)
                 (crateConfig'.devDependencies or [ ]);
+            # Artifact dependencies without `lib = true` are not linked.
+            libraryDependencies = lib.filter (d: !(d ? artifact) || (d.artifact.lib or false));
             dependencies =
               dependencyDerivations {
                 inherit features target;
@@ -287,16 +372,27 @@ rec {
                   if crateConfigs.${depPackageId}.procMacro or false
                   then self.build.crates.${depPackageId}
                   else self.crates.${depPackageId};
-                dependencies =
-                  (crateConfig.dependencies or [ ])
-                  ++ devDependencies;
+                dependencies = libraryDependencies
+                  ((crateConfig.dependencies or [ ])
+                  ++ devDependencies);
               };
             buildDependencies =
               dependencyDerivations {
                 inherit features target;
                 buildByPackageId = depPackageId:
                   self.build.crates.${depPackageId};
-                dependencies = crateConfig.buildDependencies or [ ];
+                dependencies = libraryDependencies (crateConfig.buildDependencies or [ ]);
+              };
+            artifactEnv =
+              artifactDependencyEnv {
+                inherit crateConfigs;
+                buildByPackageId = depPackageId: self.crates.${depPackageId};
+                dependencies = filterEnabledDependenciesForThis (crateConfig.dependencies or [ ]);
+              }
+              // artifactDependencyEnv {
+                inherit crateConfigs;
+                buildByPackageId = depPackageId: self.build.crates.${depPackageId};
+                dependencies = filterEnabledDependenciesForThis (crateConfig.buildDependencies or [ ]);
               };
             filterEnabledDependenciesForThis = dependencies: filterEnabledDependencies {
               inherit dependencies features target;
@@ -311,31 +407,11 @@ rec {
                       ++ devDependencies
                     )
                 );
-            # Crate renames have the form:
-            #
-            # {
-            #    crate_name = [
-            #       { version = "1.2.3"; rename = "crate_name01"; }
-            #    ];
-            #    # ...
-            # }
-            crateRenames =
-              let
-                grouped =
-                  lib.groupBy
-                    (dependency: dependency.name)
-                    dependenciesWithRenames;
-                versionAndRename = dep:
-                  let
-                    package = crateConfigs."${dep.packageId}";
-                  in
-                  { inherit (dep) rename; version = package.version; };
-              in
-              lib.mapAttrs (name: choices: builtins.map versionAndRename choices) grouped;
+            crateRenames = crateRenamesFor crateConfigs dependenciesWithRenames;
           in
           buildRustCrateForPkgsFunc pkgs
             (
-              crateConfig // {
+              cfgEnv // artifactEnv // crateConfig // {
                 src = crateConfig.src or (
                   pkgs.fetchurl rec {
                     name = "${crateConfig.crateName}-${crateConfig.version}.tar.gz";
@@ -347,7 +423,12 @@ rec {
                       crateConfig.sha256;
                   }
                 );
-                extraRustcOpts = lib.lists.optional (targetFeatures != [ ]) "-C target-feature=${lib.concatMapStringsSep "," (x: "+${x}") targetFeatures}";
+                extraRustcOpts =
+                  (crateConfig.extraRustcOpts or [ ])
+                  # Like cargo, build tests with the default panic strategy.
+                  ++ lib.filter (opt: !(runTests && opt == "-C panic=abort"))
+                    (lib.optionals (!(crateConfig.procMacro or false)) profileRustcOpts)
+                  ++ lib.lists.optional (targetFeatures != [ ]) "-C target-feature=${lib.concatMapStringsSep "," (x: "+${x}") targetFeatures}";
                 inherit features dependencies buildDependencies crateRenames release;
               }
             );
@@ -372,6 +453,69 @@ rec {
       in
       map depDerivation enabledDependencies;
 
+  /* Returns the `CARGO_BIN_FILE_*` environment variables for the binaries of the
+    given artifact dependencies, i.e. dependencies with `artifact = "bin"`.
+
+    The binaries are the `crateBin` of the dependency. Other artifact kinds
+    are not supported.
+  */
+  artifactDependencyEnv = { crateConfigs, buildByPackageId, dependencies }:
+    assert (builtins.isAttrs crateConfigs);
+    assert (builtins.isList dependencies);
+    let
+      envName = name: lib.toUpper (lib.replaceStrings [ "-" ] [ "_" ] name);
+      binEnv = dependency:
+        let
+          name = dependency.rename or dependency.name;
+          crateConfig = crateConfigs.${dependency.packageId};
+          kinds = dependency.artifact.kinds or [ ];
+          allBins = map (bin: bin.name) (crateConfig.crateBin or [ ]);
+          selectedBins =
+            if builtins.elem "bin" kinds
+            then allBins
+            else
+              builtins.filter
+                (bin: builtins.elem "bin:${bin}" kinds)
+                allBins;
+          binPath = bin: "${buildByPackageId dependency.packageId}/bin/${bin}";
+        in
+        lib.listToAttrs
+          (map (bin: lib.nameValuePair "CARGO_BIN_FILE_${envName name}_${bin}" (binPath bin)) selectedBins)
+        // lib.optionalAttrs (builtins.elem dependency.name selectedBins) {
+          "CARGO_BIN_FILE_${envName name}" = binPath dependency.name;
+        };
+    in
+    lib.foldl (env: dependency: env // binEnv dependency) { }
+      (builtins.filter (d: d ? artifact) dependencies);
+
+  /* Returns the crate renames of the given renamed dependencies in the form
+    expected by buildRustCrate:
+
+    {
+       crate_name = [
+          { version = "1.2.3"; rename = "crate_name01"; }
+       ];
+       # ...
+    }
+
+    A crate can be present in several versions with different renames. Each
+    version/rename pair is only listed once, even if the dependency is e.g. both
+    a build and a normal dependency.
+  */
+  crateRenamesFor = crateConfigs: dependenciesWithRenames:
+    let
+      grouped =
+        lib.groupBy
+          (dependency: dependency.name)
+          dependenciesWithRenames;
+      versionAndRename = dep:
+        let
+          package = crateConfigs."${dep.packageId}";
+        in
+        { inherit (dep) rename; version = package.version; };
+    in
+    lib.mapAttrs (name: choices: lib.unique (builtins.map versionAndRename choices)) grouped;
+
   /* Returns a sanitized version of val with all values substituted that cannot
     be serialized as JSON.
   */
@@ -483,7 +627,10 @@ rec {
       let
         crateConfig = crateConfigs."${packageId}" or (builtins.throw "Package not found: ${packageId}");
         expandedFeatures = expandFeatures (crateConfig.features or { }) features;
-        enabledFeatures = enableFeatures (crateConfig.dependencies or [ ]) expandedFeatures;
+        # Optional build dependencies are enabled by features just like normal ones.
+        enabledFeatures = enableFeatures
+          ((crateConfig.dependencies or [ ]) ++ (crateConfig.buildDependencies or [ ]))
+          expandedFeatures;
         depWithResolvedFeatures = dependency:
           let
             packageId = dependency.packageId;
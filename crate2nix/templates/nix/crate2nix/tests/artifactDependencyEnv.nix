@@ -0,0 +1,48 @@
+{ lib, crate2nix }:
+let
+  crateConfigs = {
+    "pkg_tool" = {
+      crateName = "codegen-tool";
+      crateBin = [
+        { name = "codegen-tool"; path = "src/main.rs"; }
+        { name = "helper"; path = "src/bin/helper.rs"; }
+      ];
+    };
+  };
+  artifactEnv = dependencies:
+    crate2nix.artifactDependencyEnv {
+      buildByPackageId = packageId: "/nix/store/${packageId}";
+      inherit crateConfigs dependencies;
+    };
+in
+{
+  testNoArtifactDependencies = {
+    expr = artifactEnv [{ name = "codegen-tool"; packageId = "pkg_tool"; }];
+    expected = { };
+  };
+
+  testBinArtifactDependency = {
+    expr = artifactEnv [{
+      name = "codegen-tool";
+      packageId = "pkg_tool";
+      artifact = { kinds = [ "bin" ]; };
+    }];
+    expected = {
+      CARGO_BIN_FILE_CODEGEN_TOOL = "/nix/store/pkg_tool/bin/codegen-tool";
+      CARGO_BIN_FILE_CODEGEN_TOOL_codegen-tool = "/nix/store/pkg_tool/bin/codegen-tool";
+      CARGO_BIN_FILE_CODEGEN_TOOL_helper = "/nix/store/pkg_tool/bin/helper";
+    };
+  };
+
+  testSelectedBinArtifactDependency = {
+    expr = artifactEnv [{
+      name = "codegen-tool";
+      rename = "tool";
+      packageId = "pkg_tool";
+      artifact = { kinds = [ "bin:helper" ]; };
+    }];
+    expected = {
+      CARGO_BIN_FILE_TOOL_helper = "/nix/store/pkg_tool/bin/helper";
+    };
+  };
+}
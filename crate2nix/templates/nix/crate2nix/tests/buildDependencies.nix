@@ -0,0 +1,48 @@
+{ lib, pkgs, crate2nix }:
+let
+  crates = {
+    "pkg_app" = {
+      crateName = "app";
+      version = "0.1.0";
+      src = "workspace/app";
+      buildDependencies = [
+        {
+          name = "codegen";
+          packageId = "pkg_codegen";
+        }
+      ];
+    };
+    "pkg_codegen" = {
+      crateName = "codegen";
+      version = "0.1.0";
+      src = "workspace/codegen";
+    };
+  };
+  crossPkgs = pkgs.pkgsCross.aarch64-multiplatform;
+  crossCrate2nix = crossPkgs.callPackage ../default.nix { inherit crates; };
+  fakeBuildRustCrate = pkgs: crate: {
+    inherit (crate) crateName src buildDependencies;
+    hostPlatform = pkgs.stdenv.hostPlatform.config;
+  };
+  built = crossCrate2nix.buildRustCrateWithFeatures {
+    packageId = "pkg_app";
+    buildRustCrateForPkgsFunc = fakeBuildRustCrate;
+  };
+in
+{
+  testWorkspaceMemberIsBuiltForTheHost = {
+    expr = map (dependency: { inherit (dependency) crateName src hostPlatform; }) built.buildDependencies;
+    expected = [
+      {
+        crateName = "codegen";
+        src = "workspace/codegen";
+        hostPlatform = pkgs.stdenv.hostPlatform.config;
+      }
+    ];
+  };
+
+  testDependentMemberIsCrossCompiled = {
+    expr = built.hostPlatform;
+    expected = crossPkgs.stdenv.hostPlatform.config;
+  };
+}
@@ -0,0 +1,91 @@
+{ lib, pkgs, crate2nix }:
+let
+  muslTarget = crate2nix.targetForPlatform pkgs.pkgsCross.musl64.stdenv.hostPlatform;
+  gnuTarget = crate2nix.targetForPlatform pkgs.pkgsCross.gnu64.stdenv.hostPlatform;
+  windowsTarget = crate2nix.defaultTarget // {
+    unix = false;
+    windows = true;
+    os = "windows";
+    arch = "x86_64";
+    family = "windows";
+    env = "msvc";
+    endian = "little";
+    pointer_width = "64";
+    vendor = "pc";
+  };
+  crateConfigs = {
+    "pkg_sys" = {
+      crateName = "some-sys";
+      version = "0.1.0";
+      src = "fake-source";
+      buildDependencies = [
+        {
+          name = "cc";
+          packageId = "pkg_cc";
+        }
+      ];
+    };
+    "pkg_cc" = {
+      crateName = "cc";
+      version = "1.0.0";
+      src = "fake-cc-source";
+    };
+  };
+  builtFor = target:
+    (crate2nix.builtRustCratesWithFeatures {
+      packageId = "pkg_sys";
+      features = [ ];
+      buildRustCrateForPkgsFunc = _: crate: crate;
+      runTests = false;
+      inherit crateConfigs target;
+    }).crates.pkg_sys;
+  cfgOf = crate: { inherit (crate) CARGO_CFG_TARGET_OS CARGO_CFG_TARGET_ENV; };
+in
+{
+  testMuslTarget = {
+    expr = cfgOf (builtFor muslTarget);
+    expected = {
+      CARGO_CFG_TARGET_OS = "linux";
+      CARGO_CFG_TARGET_ENV = "musl";
+    };
+  };
+
+  testGnuTarget = {
+    expr = cfgOf (builtFor gnuTarget);
+    expected = {
+      CARGO_CFG_TARGET_OS = "linux";
+      CARGO_CFG_TARGET_ENV = "gnu";
+    };
+  };
+
+  testBuildDependencyDescribesTheBuildPlatform = {
+    expr = map cfgOf (builtFor muslTarget).buildDependencies;
+    expected = [
+      (cfgOf (crate2nix.cargoCfgEnv {
+        target = crate2nix.targetForPlatform pkgs.buildPackages.stdenv.hostPlatform;
+      }))
+    ];
+  };
+
+  testWindowsTarget = {
+    expr = crate2nix.cargoCfgEnv { target = windowsTarget; };
+    expected = {
+      CARGO_CFG_TARGET_OS = "windows";
+      CARGO_CFG_TARGET_ARCH = "x86_64";
+      CARGO_CFG_TARGET_FAMILY = "windows";
+      CARGO_CFG_TARGET_ENV = "msvc";
+      CARGO_CFG_TARGET_ENDIAN = "little";
+      CARGO_CFG_TARGET_POINTER_WIDTH = "64";
+      CARGO_CFG_TARGET_VENDOR = "pc";
+      CARGO_CFG_WINDOWS = "";
+    };
+  };
+
+  testTargetFeatures = {
+    expr = (crate2nix.cargoCfgEnv {
+      target = muslTarget;
+      targetFeatures = [ "neon" "aes" ];
+    }).CARGO_CFG_TARGET_FEATURE;
+    expected = "neon,aes";
+  };
+}
@@ -0,0 +1,36 @@
+{ lib, pkgs, crate2nix }:
+let
+  crates = {
+    "pkg_cli" = {
+      crateName = "cli";
+      version = "0.1.0";
+      src = "fake-source";
+      checkInputs = [ pkgs.hello ];
+    };
+  };
+  crate2nixWithCrates = pkgs.callPackage ../default.nix { inherit crates; };
+  fakeBuildRustCrate = _: crate: lib.makeOverridable
+    (attrs: pkgs.runCommand attrs.crateName
+      {
+        inherit (attrs) crateName src;
+        passthru.crateAttrs = attrs;
+      } "mkdir $out")
+    crate;
+  built = crate2nixWithCrates.buildRustCrateWithFeatures {
+    packageId = "pkg_cli";
+    buildRustCrateForPkgsFunc = fakeBuildRustCrate;
+    runTests = true;
+    testInputs = [ pkgs.cowsay ];
+  };
+in
+{
+  testCheckInputsOnTestDerivation = {
+    expr = map (input: input.name) built.test.buildInputs;
+    expected = [ pkgs.cowsay.name pkgs.hello.name ];
+  };
+
+  testNoCheckInputsOnBuild = {
+    expr = built.crateAttrs ? checkInputs;
+    expected = false;
+  };
+}
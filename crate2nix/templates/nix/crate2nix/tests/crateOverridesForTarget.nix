@@ -0,0 +1,43 @@
+{ lib, crate2nix }:
+let
+  crateOverrides = {
+    plain = attrs: { buildInputs = [ "plain" ]; };
+    openssl-sys = {
+      base = attrs: { buildInputs = [ "openssl" ]; };
+      perTarget."x86_64-apple-darwin" = attrs: {
+        buildInputs = attrs.buildInputs ++ [ "Security" ];
+      };
+    };
+    onlyDarwin = {
+      perTarget."x86_64-apple-darwin" = { CARGO_FEATURE_DARWIN = "1"; };
+    };
+  };
+  overridesFor = target: crate2nix.crateOverridesForTarget target crateOverrides;
+  crate = { crateName = "openssl-sys"; buildInputs = [ ]; };
+in
+{
+  testPlainOverrideUnchanged = {
+    expr = (overridesFor "x86_64-apple-darwin").plain crate;
+    expected = { buildInputs = [ "plain" ]; };
+  };
+
+  testDarwinOverrideMergedWithBase = {
+    expr = (overridesFor "x86_64-apple-darwin").openssl-sys crate;
+    expected = { buildInputs = [ "openssl" "Security" ]; };
+  };
+
+  testLinuxUsesBaseOverride = {
+    expr = (overridesFor "x86_64-unknown-linux-gnu").openssl-sys crate;
+    expected = { buildInputs = [ "openssl" ]; };
+  };
+
+  testTargetOnlyOverride = {
+    expr = (overridesFor "x86_64-apple-darwin").onlyDarwin crate;
+    expected = { CARGO_FEATURE_DARWIN = "1"; };
+  };
+
+  testTargetOnlyOverrideForOtherTarget = {
+    expr = (overridesFor "x86_64-unknown-linux-gnu").onlyDarwin crate;
+    expected = { };
+  };
+}
@@ -0,0 +1,46 @@
+{ lib, crate2nix }:
+let
+  crateConfigs = {
+    "futures 0.1.29" = { crateName = "futures"; version = "0.1.29"; };
+    "futures 0.3.5" = { crateName = "futures"; version = "0.3.5"; };
+    "log 0.4.8" = { crateName = "log"; version = "0.4.8"; };
+  };
+  futures01 = { name = "futures"; packageId = "futures 0.1.29"; rename = "futures01"; };
+  futures03 = { name = "futures"; packageId = "futures 0.3.5"; rename = "futures03"; };
+  logger = { name = "log"; packageId = "log 0.4.8"; rename = "logger"; };
+  crateRenames = crate2nix.crateRenamesFor crateConfigs;
+in
+{
+  testNoRenames = {
+    expr = crateRenames [ ];
+    expected = { };
+  };
+
+  testSingleRename = {
+    expr = crateRenames [ logger ];
+    expected = {
+      log = [{ version = "0.4.8"; rename = "logger"; }];
+    };
+  };
+
+  testRenamedInTwoVersions = {
+    expr = crateRenames [ futures01 futures03 logger ];
+    expected = {
+      futures = [
+        { version = "0.1.29"; rename = "futures01"; }
+        { version = "0.3.5"; rename = "futures03"; }
+      ];
+      log = [{ version = "0.4.8"; rename = "logger"; }];
+    };
+  };
+
+  testBuildAndNormalDependencyListedOnce = {
+    expr = crateRenames [ futures01 futures03 futures01 ];
+    expected = {
+      futures = [
+        { version = "0.1.29"; rename = "futures01"; }
+        { version = "0.3.5"; rename = "futures03"; }
+      ];
+    };
+  };
+}
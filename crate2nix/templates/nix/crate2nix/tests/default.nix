@@ -3,11 +3,19 @@ let
   lib = pkgs.lib;
   crate2nix = pkgs.callPackage ../default.nix { };
   testFiles = [
+    "artifactDependencyEnv"
+    "buildDependencies"
+    "cargoCfgEnv"
+    "checkInputs"
+    "crateOverridesForTarget"
+    "crateRenames"
     "dependencyDerivations"
     "dependencyFeatures"
     "enableFeatures"
     "expandFeatures"
     "packageFeatures"
+    "profileRustcOpts"
+    "sharedDependencies"
   ];
   testsInFile = f:
     let
@@ -38,6 +38,30 @@ let
         }
       ];
     };
+    "pkg_with_unix_dev_dependency" = {
+      crateName = "with_unix_dev_dependency";
+      devDependencies = [
+        {
+          name = "id1";
+          packageId = "pkg_id1";
+          target = { target, features }: (target."unix" or false);
+        }
+      ];
+    };
+    "pkg_with_optional_build_dependency" = {
+      crateName = "with_optional_build_dependency";
+      features = {
+        "codegen" = [ "optional_id2" ];
+        "codegen_with_std" = [ "optional_id2/std" ];
+      };
+      buildDependencies = [
+        {
+          name = "optional_id2";
+          packageId = "pkg_id2";
+          optional = true;
+        }
+      ];
+    };
     "pkg_id1" = {
       crateName = "id1";
       features = {
@@ -82,6 +106,62 @@ let
         "std" = [ "num-bigint/std" ];
       };
     };
+
+    # Two parents of the same crate, only one of them uses its default features.
+    "pkg_two_parents" = {
+      crateName = "two_parents";
+      dependencies = [
+        {
+          name = "parent_without_defaults";
+          packageId = "pkg_parent_without_defaults";
+        }
+        {
+          name = "parent_with_defaults";
+          packageId = "pkg_parent_with_defaults";
+        }
+      ];
+    };
+    "pkg_two_parents_reversed" = {
+      crateName = "two_parents_reversed";
+      dependencies = [
+        {
+          name = "parent_with_defaults";
+          packageId = "pkg_parent_with_defaults";
+        }
+        {
+          name = "parent_without_defaults";
+          packageId = "pkg_parent_without_defaults";
+        }
+      ];
+    };
+    "pkg_parent_without_defaults" = {
+      crateName = "parent_without_defaults";
+      dependencies = [
+        {
+          name = "shared";
+          packageId = "pkg_shared";
+          usesDefaultFeatures = false;
+          features = [ "extra" ];
+        }
+      ];
+    };
+    "pkg_parent_with_defaults" = {
+      crateName = "parent_with_defaults";
+      dependencies = [
+        {
+          name = "shared";
+          packageId = "pkg_shared";
+        }
+      ];
+    };
+    "pkg_shared" = {
+      crateName = "shared";
+      features = {
+        "default" = [ "std" ];
+        "extra" = [ ];
+        "std" = [ ];
+      };
+    };
   };
   packageFeatures =
     packageId: features:
@@ -92,6 +172,19 @@ let
         rootPackageId = packageId;
         inherit crateConfigs packageId features;
       };
+  packageFeaturesWithTests =
+    target: packageId: features:
+    crate2nix.mergePackageFeatures
+      {
+        runTests = true;
+        rootPackageId = packageId;
+        inherit crateConfigs packageId features target;
+      };
+  windowsTarget = crate2nix.defaultTarget // {
+    unix = false;
+    windows = true;
+    family = "windows";
+  };
 in
 {
 
@@ -152,4 +245,64 @@ in
       "pkg_id1" = [ "default" "for_build" ];
     };
   };
+
+  testUnixDevDependencyWithTestsOnUnix = {
+    expr = packageFeaturesWithTests crate2nix.defaultTarget "pkg_with_unix_dev_dependency" [ ];
+    expected = {
+      "pkg_with_unix_dev_dependency" = [ ];
+      "pkg_id1" = [ "default" ];
+    };
+  };
+
+  testUnixDevDependencyWithTestsOnWindows = {
+    expr = packageFeaturesWithTests windowsTarget "pkg_with_unix_dev_dependency" [ ];
+    expected = {
+      "pkg_with_unix_dev_dependency" = [ ];
+    };
+  };
+
+  testUnixDevDependencyWithoutTests = {
+    expr = packageFeatures "pkg_with_unix_dev_dependency" [ ];
+    expected = {
+      "pkg_with_unix_dev_dependency" = [ ];
+    };
+  };
+
+  testOptionalBuildDependencyDisabled = {
+    expr = packageFeatures "pkg_with_optional_build_dependency" [ ];
+    expected = {
+      "pkg_with_optional_build_dependency" = [ ];
+    };
+  };
+
+  testOptionalBuildDependencyEnabledByFeature = {
+    expr = packageFeatures "pkg_with_optional_build_dependency" [ "codegen" ];
+    expected = {
+      "pkg_with_optional_build_dependency" = [ "codegen" "optional_id2" ];
+      "pkg_id2" = [ "default" ];
+    };
+  };
+
+  testDefaultFeaturesOfTwoParentsAreUnited = {
+    expr = packageFeatures "pkg_two_parents" [ ];
+    expected = {
+      "pkg_two_parents" = [ ];
+      "pkg_parent_without_defaults" = [ "default" ];
+      "pkg_parent_with_defaults" = [ "default" ];
+      "pkg_shared" = [ "default" "extra" "std" ];
+    };
+  };
+
+  testDefaultFeaturesOfTwoParentsAreUnitedInAnyOrder = {
+    expr = (packageFeatures "pkg_two_parents_reversed" [ ]).pkg_shared;
+    expected = [ "default" "extra" "std" ];
+  };
+
+  testOptionalBuildDependencyEnabledByDependencyFeature = {
+    expr = packageFeatures "pkg_with_optional_build_dependency" [ "codegen_with_std" ];
+    expected = {
+      "pkg_with_optional_build_dependency" = [ "codegen_with_std" "optional_id2" "optional_id2/std" ];
+      "pkg_id2" = [ "default" "std" ];
+    };
+  };
 }
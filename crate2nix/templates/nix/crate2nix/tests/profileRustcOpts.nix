@@ -0,0 +1,61 @@
+{ lib, pkgs, crate2nix }:
+let
+  crates = {
+    "pkg_app" = {
+      crateName = "app";
+      version = "0.1.0";
+      src = "app";
+      dependencies = [
+        {
+          name = "greeting";
+          packageId = "pkg_greeting";
+        }
+      ];
+      buildDependencies = [
+        {
+          name = "greeting";
+          packageId = "pkg_greeting";
+        }
+      ];
+    };
+    "pkg_greeting" = {
+      crateName = "greeting";
+      version = "0.1.0";
+      src = "greeting";
+    };
+  };
+  profileCrate2nix = pkgs.callPackage ../default.nix {
+    inherit crates;
+    profileRustcOpts = [ "-C panic=abort" ];
+  };
+  fakeBuildRustCrate = pkgs: crate: {
+    inherit (crate) crateName dependencies buildDependencies extraRustcOpts;
+  };
+  built = runTests: (profileCrate2nix.builtRustCratesWithFeatures {
+    packageId = "pkg_app";
+    features = [ ];
+    buildRustCrateForPkgsFunc = fakeBuildRustCrate;
+    inherit runTests;
+  }).crates.pkg_app;
+in
+{
+  testCrateIsBuiltWithProfileOptions = {
+    expr = (built false).extraRustcOpts;
+    expected = [ "-C panic=abort" ];
+  };
+
+  testDependencyIsBuiltWithProfileOptions = {
+    expr = map (dependency: dependency.extraRustcOpts) (built false).dependencies;
+    expected = [ [ "-C panic=abort" ] ];
+  };
+
+  testBuildDependencyIsBuiltWithoutProfileOptions = {
+    expr = map (dependency: dependency.extraRustcOpts) (built false).buildDependencies;
+    expected = [ [ ] ];
+  };
+
+  testTestsAreBuiltWithoutPanicAbort = {
+    expr = (built true).extraRustcOpts;
+    expected = [ ];
+  };
+}
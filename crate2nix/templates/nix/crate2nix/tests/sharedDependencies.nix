@@ -0,0 +1,72 @@
+{ lib, crate2nix }:
+let
+  crateConfigs = {
+    "pkg_member_a" = {
+      crateName = "member_a";
+      dependencies = [
+        {
+          name = "common";
+          packageId = "pkg_common";
+          usesDefaultFeatures = false;
+          features = [ "a" ];
+        }
+      ];
+    };
+    "pkg_member_b" = {
+      crateName = "member_b";
+      dependencies = [
+        {
+          name = "common";
+          packageId = "pkg_common";
+          features = [ "b" ];
+        }
+      ];
+    };
+    "pkg_common" = {
+      crateName = "common";
+      features = {
+        "default" = [ ];
+        "a" = [ ];
+        "b" = [ ];
+      };
+    };
+  };
+  build = unifyFeaturesWith: packageId:
+    (crate2nix.builtRustCratesWithFeatures {
+      inherit crateConfigs packageId unifyFeaturesWith;
+      features = [ ];
+      buildRustCrateForPkgsFunc = _: crate: {
+        inherit (crate) crateName features dependencies;
+      };
+      runTests = false;
+      target = crate2nix.defaultTarget;
+    }).crates.${packageId};
+  commonDerivations = unifyFeaturesWith:
+    lib.unique
+      (lib.concatMap
+        (packageId: (build unifyFeaturesWith packageId).dependencies)
+        [ "pkg_member_a" "pkg_member_b" ]);
+  workspaceMembers = [ "pkg_member_a" "pkg_member_b" ];
+in
+{
+  testCommonDependencyIsBuiltOnce = {
+    expr = commonDerivations workspaceMembers;
+    expected = [
+      {
+        crateName = "common";
+        features = [ "a" "b" "default" ];
+        dependencies = [ ];
+      }
+    ];
+  };
+
+  testCommonDependencyIsBuiltPerMemberByDefault = {
+    expr = builtins.length (commonDerivations [ ]);
+    expected = 2;
+  };
+
+  testMembersKeepTheirOwnFeatures = {
+    expr = (build workspaceMembers "pkg_member_a").features;
+    expected = [ ];
+  };
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Writes a project that depends on a path crate `dep 0.1.0` in its own directory.
+///
+/// Generating for two of them reports an ambiguous package version without network access.
+fn write_project(dir: &Path) {
+    let write = |path: &Path, content: &str| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    let name = dir.file_name().unwrap().to_str().unwrap();
+    write(
+        &dir.join("Cargo.toml"),
+        &format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+             [dependencies]\ndep = {{ path = \"dep\" }}\n",
+            name
+        ),
+    );
+    write(&dir.join("src").join("lib.rs"), "");
+    write(
+        &dir.join("dep").join("Cargo.toml"),
+        "[package]\nname = \"dep\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+    );
+    write(&dir.join("dep").join("src").join("lib.rs"), "");
+    write(
+        &dir.join("Cargo.lock"),
+        &format!(
+            "version = 3\n\n[[package]]\nname = \"dep\"\nversion = \"0.1.0\"\n\n\
+             [[package]]\nname = \"{}\"\nversion = \"0.1.0\"\ndependencies = [\n \"dep\",\n]\n",
+            name
+        ),
+    );
+}
+
+fn generate(dir: &Path, extra_args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_crate2nix"))
+        .current_dir(dir)
+        .args([
+            "generate",
+            "-f",
+            "first/Cargo.toml",
+            "-f",
+            "second/Cargo.toml",
+            "-o",
+            "Cargo.nix",
+        ])
+        .args(extra_args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn generate_with_deny_warnings_fails_on_warnings() {
+    let dir = tempdir::TempDir::new("crate2nix_deny_warnings").unwrap();
+    write_project(&dir.path().join("first"));
+    write_project(&dir.path().join("second"));
+
+    let allowed = generate(dir.path(), &[]);
+    let stderr = String::from_utf8_lossy(&allowed.stderr);
+    assert!(allowed.status.success(), "{}", stderr);
+    assert!(stderr.contains("ambiguous-package-version"), "{}", stderr);
+
+    let denied = generate(dir.path(), &["--deny-warnings"]);
+    let stderr = String::from_utf8_lossy(&denied.stderr);
+    assert!(!denied.status.success(), "{}", stderr);
+    assert!(stderr.contains("denied warning(s)"), "{}", stderr);
+
+    let other_kind_denied = generate(dir.path(), &["--deny", "missing-cargo-lock"]);
+    assert!(
+        other_kind_denied.status.success(),
+        "{}",
+        String::from_utf8_lossy(&other_kind_denied.stderr)
+    );
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn path_dependency_outside_of_the_repository_can_be_denied() {
+    let dir = tempdir::TempDir::new("crate2nix_deny_outside_repository").unwrap();
+    let write = |path: &Path, content: &str| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    // The app in a git repository depends on a crate in a sibling directory.
+    let repository = dir.path().join("repository");
+    write(
+        &repository.join("Cargo.toml"),
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+         [dependencies]\noutside = { path = \"../outside\" }\n",
+    );
+    write(&repository.join("src").join("lib.rs"), "");
+    write(
+        &repository.join("Cargo.lock"),
+        "version = 3\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n\
+         dependencies = [\n \"outside\",\n]\n\n\
+         [[package]]\nname = \"outside\"\nversion = \"0.1.0\"\n",
+    );
+    write(
+        &dir.path().join("outside").join("Cargo.toml"),
+        "[package]\nname = \"outside\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+    );
+    write(&dir.path().join("outside").join("src").join("lib.rs"), "");
+    let git_init = Command::new("git")
+        .current_dir(&repository)
+        .args(["init", "--quiet"])
+        .status()
+        .unwrap();
+    assert!(git_init.success());
+
+    let generate = |extra_args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_crate2nix"))
+            .current_dir(&repository)
+            .args(["generate", "-f", "Cargo.toml", "-o", "Cargo.nix"])
+            .args(extra_args)
+            .output()
+            .unwrap()
+    };
+
+    let allowed = generate(&[]);
+    let stderr = String::from_utf8_lossy(&allowed.stderr);
+    assert!(allowed.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("WARNING [path-dependency-outside-repository]"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("outside 0.1.0"), "{}", stderr);
+
+    let denied = generate(&["--deny", "path-dependency-outside-repository"]);
+    let stderr = String::from_utf8_lossy(&denied.stderr);
+    assert!(!denied.status.success(), "{}", stderr);
+    assert!(stderr.contains("denied warning(s)"), "{}", stderr);
+
+    dir.close().unwrap();
+}
@@ -0,0 +1,29 @@
+use std::process::Command;
+
+#[test]
+fn generate_tolerates_lints_tables() {
+    let dir = tempdir::TempDir::new("crate2nix_lints").unwrap();
+    let output = dir.path().join("Cargo.nix");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_crate2nix"))
+        .env(
+            "TEMPLATES_DIR",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/templates"),
+        )
+        .args([
+            "generate",
+            "-f",
+            "../sample_projects/workspace_with_lints/Cargo.toml",
+        ])
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let cargo_nix = std::fs::read_to_string(&output).unwrap();
+    assert!(cargo_nix.contains("\"main\" = rec {"), "{}", cargo_nix);
+    assert!(cargo_nix.contains("\"greeting\" = rec {"), "{}", cargo_nix);
+
+    dir.close().unwrap();
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+const REV: &str = "0123456789abcdef0123456789abcdef01234567";
+
+/// Writes an executable shell script to `bin_dir`.
+fn write_script(bin_dir: &Path, name: &str, script: &str) {
+    let path = bin_dir.join(name);
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn pin_git_uses_current_branch_rev() {
+    let dir = tempdir::TempDir::new("crate2nix_pin_git").unwrap();
+    let bin_dir = dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+    // Stand-ins so that the test does not need git, nix or network access.
+    write_script(
+        &bin_dir,
+        "git",
+        &format!(
+            "#!/bin/sh\n\
+             test \"$1 $3\" = \"ls-remote refs/heads/main\" || exit 1\n\
+             printf '{}\\trefs/heads/main\\n'\n",
+            REV
+        ),
+    );
+    write_script(
+        &bin_dir,
+        "nix-prefetch-git",
+        &format!(
+            "#!/bin/sh\n\
+             case \"$*\" in *\"--rev {}\"*) ;; *) exit 1 ;; esac\n\
+             echo '{{ \"sha256\": \"0000000000000000000000000000000000000000000000000000\" }}'\n",
+            REV
+        ),
+    );
+    let path = format!(
+        "{}:{}",
+        bin_dir.to_string_lossy(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_crate2nix"))
+        .env("PATH", path)
+        .args([
+            "pin-git",
+            "https://github.com/kolloch/crate2nix.git",
+            "--branch",
+            "main",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let sources: BTreeMap<String, serde_json::Value> =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        sources["crate2nix"],
+        serde_json::json!({
+            "type": "Git",
+            "url": "https://github.com/kolloch/crate2nix.git",
+            "rev": REV,
+            "sha256": "0000000000000000000000000000000000000000000000000000",
+        })
+    );
+
+    dir.close().unwrap();
+}
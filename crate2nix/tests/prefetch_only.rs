@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+/// A `nix-prefetch-url` stand-in so that the test does not need nix or network access.
+fn write_fake_nix_prefetch_url(bin_dir: &Path) {
+    let script = bin_dir.join("nix-prefetch-url");
+    std::fs::write(
+        &script,
+        "#!/bin/sh\necho 0000000000000000000000000000000000000000000000000000\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn prefetch_only_populates_cache_without_output() {
+    let dir = tempdir::TempDir::new("crate2nix_prefetch_only").unwrap();
+    let bin_dir = dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+    write_fake_nix_prefetch_url(&bin_dir);
+
+    let output = dir.path().join("Cargo.nix");
+    let crate_hashes = dir.path().join("crate-hashes.json");
+    let path = format!(
+        "{}:{}",
+        bin_dir.to_string_lossy(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let status = Command::new(env!("CARGO_BIN_EXE_crate2nix"))
+        .env("PATH", path)
+        .args([
+            "generate",
+            "-f",
+            "../sample_projects/simple_dep/Cargo.toml",
+            "--no-cargo-lock-checksums",
+            "--include-yanked",
+            "--prefetch-only",
+        ])
+        .arg("-o")
+        .arg(&output)
+        .arg("-h")
+        .arg(&crate_hashes)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    assert!(!output.exists(), "{} was written", output.to_string_lossy());
+    let hashes: BTreeMap<String, String> =
+        serde_json::from_str(&std::fs::read_to_string(&crate_hashes).unwrap()).unwrap();
+    assert!(
+        hashes.keys().any(|id| id.contains("nix-base32")),
+        "no hash for nix-base32 in {:?}",
+        hashes
+    );
+
+    dir.close().unwrap();
+}
@@ -0,0 +1,52 @@
+use std::process::Command;
+
+#[test]
+fn print_effective_config_runs_neither_nix_nor_cargo() {
+    let dir = tempdir::TempDir::new("crate2nix_print_effective_config").unwrap();
+    std::fs::write(
+        dir.path().join("Cargo.toml"),
+        "[package]\nname = \"aborting\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+         [profile.release]\npanic = \"abort\"\n",
+    )
+    .unwrap();
+    // Fetching this source would need nix.
+    std::fs::write(
+        dir.path().join("crate2nix.json"),
+        r#"{ "sources": { "other": { "type": "Git", "url": "https://example.com/other.git", "rev": "abc", "sha256": "123" } } }"#,
+    )
+    .unwrap();
+    // Generating would refuse to overwrite it.
+    std::fs::write(dir.path().join("Cargo.nix"), "{ }\n").unwrap();
+    // Neither nix nor cargo can be found.
+    let empty_path = dir.path().join("bin");
+    std::fs::create_dir(&empty_path).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_crate2nix"))
+        .current_dir(dir.path())
+        .env("PATH", &empty_path)
+        .args(["generate", "-f", "Cargo.toml", "--print-effective-config"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let effective_config: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        effective_config["generate"]["cargo_toml"],
+        serde_json::json!(["Cargo.toml"])
+    );
+    assert_eq!(
+        effective_config["profile_rustc_opts"]["release"],
+        serde_json::json!(["-C panic=abort"])
+    );
+    assert!(effective_config["crate2nix_json"]["sources"]["other"].is_object());
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("Cargo.nix")).unwrap(),
+        "{ }\n"
+    );
+
+    dir.close().unwrap();
+}
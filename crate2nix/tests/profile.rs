@@ -0,0 +1,42 @@
+use std::process::Command;
+
+#[test]
+fn generate_keeps_profile_options_off_build_dependencies() {
+    let dir = tempdir::TempDir::new("crate2nix_profile").unwrap();
+    let output = dir.path().join("Cargo.nix");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_crate2nix"))
+        .env(
+            "TEMPLATES_DIR",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/templates"),
+        )
+        .args([
+            "generate",
+            "-f",
+            "../sample_projects/panic_abort_build_dep/Cargo.toml",
+        ])
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // The options are applied by the builder to the crates built for the host only,
+    // so the crate configs shared with build dependencies don't contain them.
+    let cargo_nix = std::fs::read_to_string(&output).unwrap();
+    assert!(
+        cargo_nix.contains(
+            "\n    profileRustcOpts = if release\n      then [ \"-C panic=abort\" ]\n      else [ \"-C panic=abort\" ];\n"
+        ),
+        "{}",
+        cargo_nix
+    );
+    assert!(
+        !cargo_nix.contains("extraRustcOpts = [ \"-C panic=abort\""),
+        "{}",
+        cargo_nix
+    );
+    assert!(cargo_nix.contains("buildDependencies = ["), "{}", cargo_nix);
+
+    dir.close().unwrap();
+}
@@ -1,6 +1,9 @@
 use anyhow::{bail, format_err, Error};
 use colored_diff::PrettyDifference;
-use crate2nix::{nix_build::dump_with_lines, render, BuildInfo, GenerateConfig, GenerateInfo};
+use crate2nix::registry::YankedPolicy;
+use crate2nix::{
+    nix_build::dump_with_lines, render, BuildInfo, GenerateConfig, GenerateInfo, Libc,
+};
 use serde::Deserialize;
 use serde::Serialize;
 use std::io::Write;
@@ -12,7 +15,7 @@ use std::str::FromStr;
 fn self_up_to_date() {
     let metadata = BuildInfo::for_config(
         &GenerateInfo {
-            crate2nix_arguments: vec![
+            crate2nix_arguments: [
                 "generate",
                 "-n",
                 "../nix/nixpkgs.nix",
@@ -34,6 +37,20 @@ fn self_up_to_date() {
             other_metadata_options: vec![],
             use_cargo_lock_checksums: true,
             read_crate_hashes: true,
+            nixpkgs_compat: None,
+            yanked_policy: YankedPolicy::Include,
+            libc: Libc::Glibc,
+            artifact_dependencies: false,
+            all_attribute: false,
+            meta_license: false,
+            meta_source_provenance: false,
+            meta_skip_unpublished: false,
+            metadata_retries: 0,
+            metadata_cache: None,
+            lockfile: None,
+            isolated_metadata: true,
+            build_rust_crate_path: None,
+            offline: false,
         },
     )
     .unwrap();
@@ -56,7 +73,7 @@ fn pregenerated_up_to_date() {
             Some(pregenerated_build) => {
                 let cargo_nix = PathBuf::from_str(&pregenerated_build)
                     .expect("pregeneratedBuild must be valid path");
-                assert_up_to_date(&cargo_nix.parent().expect("Cargo.nix must be in directory"));
+                assert_up_to_date(cargo_nix.parent().expect("Cargo.nix must be in directory"));
             }
             None => println!("Skipping not pregenerated {}", test_config.name),
         }
@@ -79,10 +96,24 @@ fn assert_up_to_date(project_dir: &Path) {
         other_metadata_options: vec![],
         use_cargo_lock_checksums: true,
         read_crate_hashes: true,
+        nixpkgs_compat: None,
+        yanked_policy: YankedPolicy::Include,
+        libc: Libc::Glibc,
+        artifact_dependencies: false,
+        all_attribute: false,
+        meta_license: false,
+        meta_source_provenance: false,
+        meta_skip_unpublished: false,
+        metadata_retries: 0,
+        metadata_cache: None,
+        lockfile: None,
+        isolated_metadata: true,
+        build_rust_crate_path: None,
+        offline: false,
     };
     let metadata = BuildInfo::for_config(
         &GenerateInfo {
-            crate2nix_arguments: vec![
+            crate2nix_arguments: [
                 "generate",
                 "-f",
                 cargo_toml.to_str().unwrap(),
@@ -125,7 +156,7 @@ struct TestConfig {
 
 fn get_test_configs() -> Result<Vec<TestConfig>, Error> {
     let output = Command::new("nix")
-        .args(&["eval", "--json", "-f", "../tests.nix", "buildTestConfigs"])
+        .args(["eval", "--json", "-f", "../tests.nix", "buildTestConfigs"])
         .output()
         .map_err(|e| format_err!("while spawning nix: {}", e))?;
     if !output.status.success() {
@@ -0,0 +1,46 @@
+use std::process::Command;
+
+#[test]
+fn sources_is_an_alias_of_source_registry() {
+    let dir = tempdir::TempDir::new("crate2nix_sources").unwrap();
+    std::fs::write(
+        dir.path().join("Cargo.toml"),
+        "[package]\nname = \"listed\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+    )
+    .unwrap();
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+    std::fs::write(
+        dir.path().join("Cargo.lock"),
+        "version = 3\n\n[[package]]\nname = \"listed\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+
+    // The header with the command line differs, the list does not.
+    let list = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_crate2nix"))
+            .current_dir(dir.path())
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| format!("{}\n", line))
+            .collect::<String>()
+    };
+    let sources = list(&["sources", "--format", "nix-list"]);
+    assert_eq!(sources.trim(), "[\n]");
+    assert_eq!(
+        list(&["source", "registry", "--format", "nix-list"]),
+        sources
+    );
+
+    dir.close().unwrap();
+}
@@ -0,0 +1,7 @@
+use std::io::Write;
+
+fn main() {
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let mut file = std::fs::File::create(format!("{}/name.rs", out_dir)).unwrap();
+    writeln!(file, "const NAME: &str = {:?};", greeting::name()).unwrap();
+}
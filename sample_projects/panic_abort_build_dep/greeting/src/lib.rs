@@ -0,0 +1,7 @@
+pub fn name() -> &'static str {
+    "panic_abort_build_dep"
+}
+
+pub fn greet(name: &str) -> String {
+    format!("Hello, {}!", name)
+}
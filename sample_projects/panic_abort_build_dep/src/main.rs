@@ -0,0 +1,5 @@
+include!(concat!(env!("OUT_DIR"), "/name.rs"));
+
+fn main() {
+    println!("{}", greeting::greet(NAME));
+}
@@ -0,0 +1,6 @@
+//! The greeting of the `workspace_with_lints` sample.
+
+/// Returns the name of the sample project.
+pub fn get_info() -> &'static str {
+    "workspace_with_lints"
+}
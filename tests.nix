@@ -427,6 +427,19 @@ let
       derivationAttrPath = [ "workspaceMembers" "main" ];
     }
 
+    {
+      name = "workspace_with_lints";
+      src = ./sample_projects/workspace_with_lints;
+      expectedOutput = "Hello, workspace_with_lints";
+      derivationAttrPath = [ "workspaceMembers" "main" ];
+    }
+
+    {
+      name = "panic_abort_build_dep";
+      src = ./sample_projects/panic_abort_build_dep;
+      expectedOutput = "Hello, panic_abort_build_dep!";
+    }
+
     {
       name = "with_problematic_crates";
       src = ./sample_projects/with_problematic_crates;